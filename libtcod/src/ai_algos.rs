@@ -5,56 +5,307 @@
 use rand::Rng;
 use tcod::colors::*;
 
+use crate::constants::FLEE_HP_PERCENT;
+use crate::constants::GLIMPSE_PERCENT;
 use crate::constants::PLAYER;
-use crate::menus::Tcod;
+use crate::constants::SEARCH_TURNS;
+use crate::constants::SNEAK_VISION_PERCENT;
+use crate::constants::SURROUND_RADIUS;
+use crate::constants::WANDER_RADIUS;
+use crate::dijkstra::AiMap;
+use crate::handles::find;
+use crate::handles::Handle;
 use crate::moves::move_by;
 use crate::moves::move_towards;
 use crate::objects::Ai;
+use crate::objects::Awareness;
 use crate::objects::Game;
 use crate::objects::Object;
 use crate::utils::mut_two;
-
-// TODO: This logic could support movement speed.
-// TODO: We should support "patroling" NPCs, that move even when the Player isn't around.
+use crate::vision::can_see;
 
 
 // Dispatch method to choose an AI algo to move the NPC
-pub fn ai_take_turn(npc_id: usize, tcod: &Tcod, game: &mut Game, objects: &mut [Object]) {
+pub fn ai_take_turn(npc: Handle, game: &mut Game, objects: &mut [Object]) {
     use Ai::*;
+    let npc_id: usize = match find(objects, npc) {
+        Some(npc_id) => npc_id,
+        None => return,
+    };
     if let Some(ai) = objects[npc_id].ai.take() {
-        let new_ai = match ai {
-            Basic => ai_basic(npc_id, tcod, game, objects),
+        let noticed = notice_player(npc_id, game, objects);
+        let new_ai = match react(ai, noticed, objects[PLAYER].pos()) {
+            // a basic NPC waits where it is for the player to come along
+            Basic => Ai::Basic,
+            Cowardly => ai_cowardly(npc_id, game, objects, noticed),
+            Patrol { route, next } => ai_patrol(npc_id, game, objects, route, next),
+            Wander { goal } => ai_wander(npc_id, game, objects, goal),
+            Guard { post } => ai_guard(npc_id, game, objects, post),
+            Suspicious {
+                last_seen,
+                turns,
+                previous_ai,
+            } => ai_suspicious(npc_id, game, objects, last_seen, turns, previous_ai),
+            Hunting {
+                last_seen,
+                previous_ai,
+            } => ai_hunting(npc_id, game, objects, last_seen, previous_ai, noticed),
             Confused {
                 previous_ai,
                 num_turns,
-            } => ai_confused(npc_id, tcod, game, objects, previous_ai, num_turns),
+            } => ai_confused(npc_id, game, objects, previous_ai, num_turns),
         };
         objects[npc_id].ai = Some(new_ai);
     }
 }
 
 
-pub fn ai_basic(npc_id: usize, tcod: &Tcod, game: &mut Game, objects: &mut [Object]) -> Ai {
-    // a basic npc takes its turn. If you can see it, it can see you
-    let (npc_x, npc_y) = objects[npc_id].pos();
-    if tcod.fov.is_in_fov(npc_x, npc_y) {
-        if objects[npc_id].distance_to(&objects[PLAYER]) >= 2.0 {
-            // move towards player if far away
-            let (player_x, player_y) = objects[PLAYER].pos();
-            move_towards(npc_id, player_x, player_y, &game.map(), objects);
-        } else if objects[PLAYER].fighter.as_ref().map_or(false, |f| f.hp > 0) {
-            // close enough, attack! (if the player is still alive.)
-            let (npc, player) = mut_two(npc_id, PLAYER, objects);
-            npc.melee_attack(player, game);
+// How aware of the player this NPC is now, from what it can see of them.
+// Even a blind one knows when the player is right next to it, unless they're
+// sneaking (and then they're only seen from half as far).
+fn notice_player(npc_id: usize, game: &Game, objects: &[Object]) -> Awareness {
+    let (npc, player) = (&objects[npc_id], &objects[PLAYER]);
+    let distance: f32 = npc.distance_to(player);
+    if distance < 2.0 && !game.sneaking {
+        return Awareness::Hunting;
+    }
+    let mut vision: i32 = npc.fighter.as_ref().map_or(0, |f| f.vision);
+    if game.sneaking {
+        vision = vision * SNEAK_VISION_PERCENT / 100;
+    }
+    if !can_see(&game.maps[game.lvl], npc.pos(), player.pos(), vision) {
+        return Awareness::Unaware;
+    }
+    if distance * 100.0 > (vision * GLIMPSE_PERCENT) as f32 {
+        return Awareness::Suspicious;
+    }
+    Awareness::Hunting
+}
+
+
+// What the NPC does about what it noticed: a glimpse makes it suspicious, and a
+// good look (or a second glimpse) sets it hunting. Cowards and the confused do
+// their own thing.
+fn react(ai: Ai, noticed: Awareness, player: (i32, i32)) -> Ai {
+    match (noticed, ai) {
+        (_, ai @ Ai::Cowardly) | (_, ai @ Ai::Confused { .. }) | (Awareness::Unaware, ai) => ai,
+        (_, Ai::Hunting { previous_ai, .. }) | (_, Ai::Suspicious { previous_ai, .. }) => Ai::Hunting {
+            last_seen: player,
+            previous_ai,
+        },
+        (Awareness::Suspicious, ai) => Ai::Suspicious {
+            last_seen: player,
+            turns: SEARCH_TURNS,
+            previous_ai: Box::new(ai),
+        },
+        (Awareness::Hunting, ai) => Ai::Hunting {
+            last_seen: player,
+            previous_ai: Box::new(ai),
+        },
+    }
+}
+
+
+// An NPC hears a noise: it goes to see what it was (a hunter, out of sight of
+// the player, takes it as where they are now).
+pub fn hear(ai: Ai, at: (i32, i32)) -> Ai {
+    match ai {
+        Ai::Cowardly | Ai::Confused { .. } => ai,
+        Ai::Hunting { previous_ai, .. } => Ai::Hunting {
+            last_seen: at,
+            previous_ai,
+        },
+        Ai::Suspicious { previous_ai, .. } => Ai::Suspicious {
+            last_seen: at,
+            turns: SEARCH_TURNS,
+            previous_ai,
+        },
+        ai => Ai::Suspicious {
+            last_seen: at,
+            turns: SEARCH_TURNS,
+            previous_ai: Box::new(ai),
+        },
+    }
+}
+
+
+pub fn ai_patrol(npc_id: usize, game: &mut Game, objects: &mut [Object], route: Vec<(i32, i32)>, next: usize) -> Ai {
+    // make the rounds, and if something is in the way go on to the next stop
+    if route.is_empty() {
+        // nowhere to go (an edited save, say): stand guard instead
+        let post = objects[npc_id].pos();
+        return ai_guard(npc_id, game, objects, post);
+    }
+    let mut next = next % route.len();
+    if objects[npc_id].pos() == route[next] {
+        next = (next + 1) % route.len();
+    }
+    let (stop_x, stop_y) = route[next];
+    if !move_towards(npc_id, stop_x, stop_y, game, objects) {
+        next = (next + 1) % route.len();
+    }
+    Ai::Patrol { route, next }
+}
+
+
+pub fn ai_wander(npc_id: usize, game: &mut Game, objects: &mut [Object], goal: (i32, i32)) -> Ai {
+    // stroll somewhere nearby, and once there (or stuck) pick somewhere else
+    let (x, y) = objects[npc_id].pos();
+    if (x, y) != goal && move_towards(npc_id, goal.0, goal.1, game, objects) {
+        return Ai::Wander { goal };
+    }
+    let new_x = x + game.rng.gen_range(-WANDER_RADIUS, WANDER_RADIUS + 1);
+    let new_y = y + game.rng.gen_range(-WANDER_RADIUS, WANDER_RADIUS + 1);
+    if game.map().get(new_x, new_y).is_some_and(|tile| tile.passable()) {
+        return Ai::Wander { goal: (new_x, new_y) };
+    }
+    Ai::Wander { goal: (x, y) }
+}
+
+
+pub fn ai_guard(npc_id: usize, game: &mut Game, objects: &mut [Object], post: (i32, i32)) -> Ai {
+    // stand guard, and head back to the post after a chase
+    if objects[npc_id].pos() != post {
+        move_towards(npc_id, post.0, post.1, game, objects);
+    }
+    Ai::Guard { post }
+}
+
+
+pub fn ai_suspicious(
+    npc_id: usize,
+    game: &mut Game,
+    objects: &mut [Object],
+    last_seen: (i32, i32),
+    turns: i32,
+    previous_ai: Box<Ai>,
+) -> Ai {
+    // go and look where the player was, then search around there for a while
+    if objects[npc_id].pos() != last_seen && move_towards(npc_id, last_seen.0, last_seen.1, game, objects) {
+        return Ai::Suspicious {
+            last_seen,
+            turns,
+            previous_ai,
+        };
+    }
+
+    if turns <= 0 {
+        // nothing there: go back to what it was doing
+        return *previous_ai;
+    }
+    let dx: i32 = game.rng.gen_range(-1, 2);
+    let dy: i32 = game.rng.gen_range(-1, 2);
+    move_by(npc_id, dx, dy, game, objects);
+    Ai::Suspicious {
+        last_seen: objects[npc_id].pos(),
+        turns: turns - 1,
+        previous_ai,
+    }
+}
+
+
+pub fn ai_hunting(
+    npc_id: usize,
+    game: &mut Game,
+    objects: &mut [Object],
+    last_seen: (i32, i32),
+    previous_ai: Box<Ai>,
+    noticed: Awareness,
+) -> Ai {
+    // chase the player, and when they get out of sight go to where they were last seen
+    if noticed != Awareness::Unaware {
+        fight(npc_id, game, objects);
+        return Ai::Hunting {
+            last_seen,
+            previous_ai,
+        };
+    }
+    if objects[npc_id].pos() != last_seen && move_towards(npc_id, last_seen.0, last_seen.1, game, objects) {
+        return Ai::Hunting {
+            last_seen,
+            previous_ai,
+        };
+    }
+    // there (or stuck), and no sign of them
+    Ai::Suspicious {
+        last_seen,
+        turns: SEARCH_TURNS,
+        previous_ai,
+    }
+}
+
+
+// one turn of fighting a player in sight
+fn fight(npc_id: usize, game: &mut Game, objects: &mut [Object]) {
+    let distance: f32 = objects[npc_id].distance_to(&objects[PLAYER]);
+    if is_badly_hurt(&objects[npc_id]) {
+        // run for it, and only fight if there's nowhere left to run
+        let fled = step_on(npc_id, AiMap::Flee, game, objects);
+        if !fled && distance < 2.0 {
+            attack_player(npc_id, game, objects);
+        }
+    } else if distance >= 2.0 {
+        // close in: near the player, spread out so the pack surrounds them
+        let (player_x, player_y) = objects[PLAYER].pos();
+        let surrounded = distance <= SURROUND_RADIUS
+            && step_on(npc_id, AiMap::Surround, game, objects);
+        if !surrounded {
+            move_towards(npc_id, player_x, player_y, game, objects);
+        }
+    } else {
+        // close enough, attack!
+        attack_player(npc_id, game, objects);
+    }
+}
+
+
+pub fn ai_cowardly(npc_id: usize, game: &mut Game, objects: &mut [Object], noticed: Awareness) -> Ai {
+    // keep out of reach, but lash out when cornered
+    if noticed != Awareness::Unaware {
+        let kept_away = step_on(npc_id, AiMap::KeepDistance, game, objects);
+        if !kept_away && objects[npc_id].distance_to(&objects[PLAYER]) < 2.0 {
+            attack_player(npc_id, game, objects);
         }
     }
-    Ai::Basic
+    Ai::Cowardly
+}
+
+
+fn is_badly_hurt(npc: &Object) -> bool {
+    npc.fighter
+        .as_ref()
+        .is_some_and(|f| f.hp * 100 < f.max_hp() * FLEE_HP_PERCENT)
+}
+
+
+// attack the player (if they're still alive)
+fn attack_player(npc_id: usize, game: &mut Game, objects: &mut [Object]) {
+    if objects[PLAYER].fighter.as_ref().is_some_and(|f| f.hp > 0) {
+        let (npc, player) = mut_two(npc_id, PLAYER, objects);
+        npc.melee_attack(player, game);
+    }
+}
+
+
+// step downhill on one of the shared Dijkstra maps, if that's better than standing still
+fn step_on(npc_id: usize,
+           which: AiMap,
+           game: &mut Game,
+           objects: &mut [Object]) -> bool {
+    let (x, y) = objects[npc_id].pos();
+    let (map, index) = (&game.maps[game.lvl], &game.index);
+    let next = game.ai_maps
+        .get(which, map, index)
+        .and_then(|dijkstra| dijkstra.best_step((x, y), map, index));
+    match next {
+        Some((nx, ny)) => move_by(npc_id, nx - x, ny - y, game, objects),
+        None => false,
+    }
 }
 
 
 pub fn ai_confused(
     npc_id: usize,
-    _tcod: &Tcod,
     game: &mut Game,
     objects: &mut [Object],
     previous_ai: Box<Ai>,
@@ -63,15 +314,17 @@ pub fn ai_confused(
     if num_turns >= 0 {
         // still confused ...
         // move in a random direction, and decrease the number of turns confused
+        let dx: i32 = game.rng.gen_range(-1, 2);
+        let dy: i32 = game.rng.gen_range(-1, 2);
         move_by(
             npc_id,
-            rand::thread_rng().gen_range(-1, 2),
-            rand::thread_rng().gen_range(-1, 2),
-            &game.map(),
+            dx,
+            dy,
+            game,
             objects,
         );
         Ai::Confused {
-            previous_ai: previous_ai,
+            previous_ai,
             num_turns: num_turns - 1,
         }
     } else {
@@ -83,3 +336,116 @@ pub fn ai_confused(
         *previous_ai
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::new_game;
+    use crate::map::Tile;
+    use crate::objects::Fighter;
+
+    // the first floor, opened up into one big room, with the player alone in a corner
+    fn open_floor(seed: u64) -> (Game, Vec<Object>) {
+        let (mut game, mut all_objects, _) = new_game(seed);
+        for (_, tile) in game.maps[0].iter_mut() {
+            *tile = Tile::empty();
+        }
+        let mut objects = all_objects.swap_remove(0);
+        objects.truncate(1);
+        objects[PLAYER].set_pos(2, 2);
+        game.enter_floor(&objects);
+        game.compute_fov(&objects[PLAYER]);
+        (game, objects)
+    }
+
+
+    fn add_npc(game: &mut Game, objects: &mut Vec<Object>, pos: (i32, i32), ai: Ai) -> Handle {
+        let mut npc = Object::new(pos.0, pos.1, 'o', "orc", WHITE, true);
+        npc.alive = true;
+        npc.ai = Some(ai);
+        npc.fighter = Some(Fighter::new(10, 0, 1, 0, true));
+        objects.push(npc);
+        game.handles.allocate_all(objects);
+        game.enter_floor(objects);
+        objects.last().unwrap().handle
+    }
+
+
+    fn turns(npc: Handle, count: usize, game: &mut Game, objects: &mut [Object]) {
+        for _ in 0..count {
+            ai_take_turn(npc, game, objects);
+        }
+    }
+
+
+    #[test]
+    fn a_patrol_makes_its_rounds() {
+        let (mut game, mut objects) = open_floor(1);
+        let route = vec![(30, 20), (34, 20)];
+        let npc = add_npc(&mut game, &mut objects, (30, 20), Ai::Patrol { route: route.clone(), next: 1 });
+        turns(npc, 4, &mut game, &mut objects);
+        assert_eq!(objects[1].pos(), (34, 20));
+        // there: turn around for the first stop
+        turns(npc, 1, &mut game, &mut objects);
+        assert_eq!(objects[1].pos(), (33, 20));
+        assert_eq!(objects[1].ai, Some(Ai::Patrol { route, next: 0 }));
+    }
+
+
+    #[test]
+    fn a_patrol_with_no_route_stands_guard() {
+        let (mut game, mut objects) = open_floor(2);
+        let npc = add_npc(&mut game, &mut objects, (30, 20), Ai::Patrol { route: vec![], next: 0 });
+        turns(npc, 1, &mut game, &mut objects);
+        assert_eq!(objects[1].ai, Some(Ai::Guard { post: (30, 20) }));
+    }
+
+
+    #[test]
+    fn a_wanderer_keeps_to_its_neighborhood() {
+        let (mut game, mut objects) = open_floor(3);
+        let npc = add_npc(&mut game, &mut objects, (30, 20), Ai::Wander { goal: (30, 20) });
+        for _ in 0..20 {
+            let (x, y) = objects[1].pos();
+            turns(npc, 1, &mut game, &mut objects);
+            // each new spot is picked close to where it stands
+            match objects[1].ai {
+                Some(Ai::Wander { goal }) => {
+                    assert!((goal.0 - x).abs() <= WANDER_RADIUS + 1 && (goal.1 - y).abs() <= WANDER_RADIUS + 1);
+                }
+                ref ai => panic!("a wanderer became {:?}", ai),
+            }
+        }
+        assert_ne!(objects[1].pos(), (30, 20));
+    }
+
+
+    #[test]
+    fn a_guard_gives_chase_then_goes_back_to_its_post() {
+        let (mut game, mut objects) = open_floor(4);
+        let npc = add_npc(&mut game, &mut objects, (30, 20), Ai::Guard { post: (30, 20) });
+
+        // the player comes into sight
+        objects[PLAYER].set_pos(26, 20);
+        game.enter_floor(&objects);
+        game.compute_fov(&objects[PLAYER]);
+        turns(npc, 1, &mut game, &mut objects);
+        match objects[1].ai {
+            Some(Ai::Hunting { last_seen, ref previous_ai, .. }) => {
+                assert_eq!(last_seen, (26, 20));
+                assert_eq!(**previous_ai, Ai::Guard { post: (30, 20) });
+            }
+            ref ai => panic!("a guard that saw the player became {:?}", ai),
+        }
+
+        // and out again: it searches for a while, then heads home
+        objects[PLAYER].set_pos(2, 2);
+        game.enter_floor(&objects);
+        game.compute_fov(&objects[PLAYER]);
+        turns(npc, SEARCH_TURNS as usize + 5, &mut game, &mut objects);
+        assert_eq!(objects[1].ai, Some(Ai::Guard { post: (30, 20) }));
+        turns(npc, 20, &mut game, &mut objects);
+        assert_eq!(objects[1].pos(), (30, 20));
+    }
+}
@@ -0,0 +1,356 @@
+/*
+  Map Generator
+
+  Generate one floor of Purgatory, without opening a window, and write out
+  its layout, stairs and spawned objects. Handy for reviewing map-gen changes.
+  Any floor the player can't walk to is reported (and is a map-gen bug).
+
+    cargo run --bin mapgen -- <level> <seed> [--format ascii|json|png] [--out FILE]
+
+  Levels count from 1, like the in-game panel. The seed is anything you could
+  type at the new-game prompt, and gives the same floor a new game would get.
+ */
+// Import Std Libs
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::process;
+
+// Import Third-Party
+use serde::Serialize;
+use tcod::colors::Color;
+
+// Import Locally
+use feb2::constants::COLOR_DARK_GROUND;
+use feb2::constants::COLOR_LIGHT_DOOR;
+use feb2::constants::LEVELS_FILE;
+use feb2::constants::LVL_NAMES;
+use feb2::constants::NUM_LVLS;
+use feb2::constants::PREFAB_DIR;
+use feb2::engine::new_player;
+use feb2::map::unreachable_cells;
+use feb2::map::Map;
+use feb2::map::Tile;
+use feb2::map::TileKind;
+use feb2::map_algos::prefabs::init_prefabs;
+use feb2::objects::Game;
+use feb2::objects::Object;
+use feb2::profiles::init_profiles;
+use feb2::rng::parse_seed;
+
+// how many pixels wide (and tall) each tile is in a PNG
+const PNG_SCALE: u32 = 4;
+
+const USAGE: &str = "usage: mapgen <level> <seed> [--format ascii|json|png] [--out FILE]";
+
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    Json,
+    Png,
+}
+
+
+struct Args {
+    level: usize,
+    seed: u64,
+    format: Format,
+    out: Option<String>,
+}
+
+
+#[derive(Serialize)]
+struct JsonObject {
+    name: String,
+    chr: char,
+    x: i32,
+    y: i32,
+}
+
+
+#[derive(Serialize)]
+struct JsonMap {
+    level: usize,
+    name: String,
+    seed: u64,
+    width: i32,
+    height: i32,
+    up_stairs: (i32, i32),
+    down_stairs: (i32, i32),
+    // one string per row: '#' wall, '.' floor, '+' door ("'" once open), '=' water, '^' lava, ':' chasm
+    tiles: Vec<String>,
+    objects: Vec<JsonObject>,
+    // floor tiles that can't be reached from the down-stairs
+    unreachable: Vec<(i32, i32)>,
+}
+
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut positional: Vec<String> = vec![];
+    let mut format = Format::Ascii;
+    let mut out: Option<String> = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                format = match args.next().as_deref() {
+                    Some("ascii") => Format::Ascii,
+                    Some("json") => Format::Json,
+                    Some("png") => Format::Png,
+                    _ => return Err("--format must be ascii, json or png".to_string()),
+                };
+            }
+            "--out" => {
+                out = Some(args.next().ok_or("--out needs a file name")?);
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ => positional.push(arg),
+        }
+    }
+    if positional.len() != 2 {
+        return Err(USAGE.to_string());
+    }
+
+    let level: usize = match positional[0].parse::<usize>() {
+        Ok(level) if level >= 1 && level <= NUM_LVLS as usize => level - 1,
+        _ => return Err(format!("the level must be from 1 to {}", NUM_LVLS)),
+    };
+    if format == Format::Png && out.is_none() {
+        return Err("a PNG needs --out FILE".to_string());
+    }
+
+    Ok(Args {
+        level,
+        seed: parse_seed(&positional[1]),
+        format,
+        out,
+    })
+}
+
+
+// The floors generated for a new game from this seed, up to the one asked for
+// (the player starts on the first).
+fn generate(seed: u64, level: usize) -> (Game, Vec<Vec<Object>>) {
+    let mut objects: Vec<Vec<Object>> = vec![vec![]; NUM_LVLS as usize];
+    objects[0].push(new_player());
+    let mut game = Game::new(&mut objects, seed);
+    while game.maps.len() <= level {
+        game.make_level(&mut objects);
+    }
+    (game, objects)
+}
+
+
+// objects that don't block are drawn first, so the ones that do end up on top
+fn drawing_order(objects: &[Object]) -> Vec<&Object> {
+    let mut to_draw: Vec<&Object> = objects.iter().collect();
+    to_draw.sort_by_key(|o1| o1.blocks);
+    to_draw
+}
+
+
+fn tile_char(tile: &Tile) -> char {
+    match tile.kind {
+        TileKind::Wall => '#',
+        TileKind::Floor => '.',
+        TileKind::Door { open: false } => '+',
+        TileKind::Door { open: true } => '\'',
+        TileKind::Water => '=',
+        TileKind::Lava => '^',
+        TileKind::Chasm => ':',
+    }
+}
+
+
+fn tile_rows(map: &Map) -> Vec<String> {
+    (0..map.height)
+        .map(|y| {
+            (0..map.width)
+                .map(|x| tile_char(&map[(x, y)]))
+                .collect()
+        })
+        .collect()
+}
+
+
+fn to_ascii(map: &Map, objects: &[Object]) -> String {
+    let mut grid: Vec<Vec<char>> = tile_rows(map).iter().map(|row| row.chars().collect()).collect();
+    for object in drawing_order(objects) {
+        grid[object.y as usize][object.x as usize] = object.chr;
+    }
+
+    let mut text: String = String::new();
+    for row in grid {
+        text.extend(row);
+        text.push('\n');
+    }
+    text
+}
+
+
+fn to_json(args: &Args, game: &Game, objects: &[Object]) -> Result<String, Box<dyn Error>> {
+    let json = JsonMap {
+        level: args.level + 1,
+        name: LVL_NAMES[args.level].to_string(),
+        seed: args.seed,
+        width: game.maps[args.level].width,
+        height: game.maps[args.level].height,
+        up_stairs: game.up_stairs[args.level],
+        down_stairs: game.down_stairs[args.level],
+        tiles: tile_rows(&game.maps[args.level]),
+        objects: objects
+            .iter()
+            .map(|o| JsonObject { name: o.name.clone(), chr: o.chr, x: o.x, y: o.y })
+            .collect(),
+        unreachable: unreachable_cells(&game.maps[args.level], game.down_stairs[args.level]),
+    };
+    Ok(serde_json::to_string_pretty(&json)? + "\n")
+}
+
+
+fn write_png(path: &str, map: &Map, objects: &[Object]) -> Result<(), Box<dyn Error>> {
+    let width = map.width as u32 * PNG_SCALE;
+    let height = map.height as u32 * PNG_SCALE;
+
+    // one color per tile, then blown up to PNG_SCALE
+    let mut colors: Vec<Vec<Color>> = vec![vec![COLOR_DARK_GROUND; map.width as usize]; map.height as usize];
+    for ((x, y), tile) in map.iter() {
+        colors[y as usize][x as usize] = match tile.glyph() {
+            Some(_) => COLOR_LIGHT_DOOR,
+            None => tile.color(false),
+        };
+    }
+    for object in drawing_order(objects) {
+        colors[object.y as usize][object.x as usize] = object.color;
+    }
+
+    let mut data: Vec<u8> = Vec::with_capacity((width * height * 3) as usize);
+    for py in 0..height {
+        for px in 0..width {
+            let color = colors[(py / PNG_SCALE) as usize][(px / PNG_SCALE) as usize];
+            data.extend_from_slice(&[color.r, color.g, color.b]);
+        }
+    }
+
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(())
+}
+
+
+fn run(args: &Args) -> Result<(), Box<dyn Error>> {
+    // tuning levels.toml and the prefabs is what this is for, so a broken one is an error
+    init_profiles(Path::new(LEVELS_FILE))?;
+    init_prefabs(Path::new(PREFAB_DIR))?;
+    let (game, objects) = generate(args.seed, args.level);
+    let map: &Map = &game.maps[args.level];
+    let objects: &[Object] = &objects[args.level];
+
+    let unreachable = unreachable_cells(map, game.down_stairs[args.level]);
+    if !unreachable.is_empty() {
+        eprintln!("mapgen: {} floor tiles can't be reached: {:?}", unreachable.len(), unreachable);
+    }
+
+    let text = match args.format {
+        Format::Ascii => to_ascii(map, objects),
+        Format::Json => to_json(args, &game, objects)?,
+        Format::Png => return write_png(args.out.as_deref().unwrap(), map, objects),
+    };
+    match &args.out {
+        Some(path) => fs::write(path, text)?,
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+
+fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(&args) {
+        eprintln!("mapgen: {}", e);
+        process::exit(1);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn args(line: &str) -> Result<Args, String> {
+        parse_args(line.split_whitespace().map(|arg| arg.to_string()))
+    }
+
+
+    #[test]
+    fn levels_count_from_one() {
+        let parsed = args("3 groundhog --format json").unwrap();
+        assert_eq!(parsed.level, 2);
+        assert_eq!(parsed.seed, parse_seed("groundhog"));
+        assert!(parsed.format == Format::Json);
+        assert!(args("0 1").is_err());
+        assert!(args(&format!("{} 1", NUM_LVLS + 1)).is_err());
+        assert!(args("1").is_err());
+        assert!(args("1 1 --format gif").is_err());
+        // a PNG has to go somewhere
+        assert!(args("1 1 --format png").is_err());
+    }
+
+
+    #[test]
+    fn ascii_is_one_row_per_line_with_the_player_and_stairs() {
+        let (game, objects) = generate(11, 0);
+        let text = to_ascii(&game.maps[0], &objects[0]);
+        let rows: Vec<&str> = text.lines().collect();
+        assert_eq!(rows.len(), game.maps[0].height as usize);
+        assert!(rows.iter().all(|row| row.chars().count() == game.maps[0].width as usize));
+        assert_eq!(text.matches('@').count(), 1);
+        let (x, y) = game.down_stairs[0];
+        assert_ne!(rows[y as usize].chars().nth(x as usize), Some('#'));
+
+        // the same seed always draws the same floor
+        let (again, again_objects) = generate(11, 0);
+        assert_eq!(text, to_ascii(&again.maps[0], &again_objects[0]));
+    }
+
+
+    #[test]
+    fn json_lists_the_floor_and_its_objects() {
+        let parsed = args("2 11 --format json").unwrap();
+        let (game, objects) = generate(parsed.seed, parsed.level);
+        let json: serde_json::Value = serde_json::from_str(&to_json(&parsed, &game, &objects[1]).unwrap()).unwrap();
+        assert_eq!(json["level"], 2);
+        assert_eq!(json["name"], LVL_NAMES[1]);
+        assert_eq!(json["tiles"].as_array().unwrap().len(), game.maps[1].height as usize);
+        assert_eq!(json["objects"].as_array().unwrap().len(), objects[1].len());
+        assert_eq!(json["down_stairs"][0], game.down_stairs[1].0);
+    }
+
+
+    #[test]
+    fn png_is_scaled_up_from_the_tiles() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("floor.png");
+        let (game, objects) = generate(11, 0);
+        write_png(path.to_str().unwrap(), &game.maps[0], &objects[0]).unwrap();
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let reader = decoder.read_info().unwrap();
+        let info = reader.info();
+        assert_eq!((info.width, info.height), (game.maps[0].width as u32 * PNG_SCALE, game.maps[0].height as u32 * PNG_SCALE));
+    }
+}
@@ -7,7 +7,14 @@ use tcod::colors::Color;
 pub const GAME_TITLE: &str = "February Second";
 pub const AUTHOR_LINE: &str = "by John Science";
 pub const FONT_IMG: &str = "bisasam_16x16.png";
-pub const SAVE_FILE: &str = ".feb2.savegame";
+pub const LEVELS_FILE: &str = "levels.toml";
+pub const PREFAB_DIR: &str = "prefabs";
+pub const SAVE_DIR: &str = "saves";
+pub const SAVE_EXT: &str = "savegame";
+pub const REPLAY_EXT: &str = "replay";
+pub const LEGACY_SAVE_FILE: &str = ".feb2.savegame";
+pub const SLOT_NAME_MAX: usize = 16;
+pub const AUTOSAVE_TURNS: u32 = 100;
 
 // player will always be the first object
 pub const PLAYER: usize = 0;
@@ -15,6 +22,10 @@ pub const PLAYER: usize = 0;
 // 20 frames-per-second maximum
 pub const LIMIT_FPS: i32 = 20;
 
+// replay speeds, in inputs-per-second (0 is as fast as possible)
+pub const REPLAY_SPEEDS: [i32; 5] = [2, 5, 20, 60, 0];
+pub const REPLAY_DEFAULT_SPEED: usize = 2;
+
 // experience and level-ups (BASE + level * FACTOR)
 pub const LEVEL_UP_BASE: i32 = 200;
 pub const LEVEL_UP_FACTOR: i32 = 150;
@@ -27,15 +38,70 @@ pub const SCREEN_HEIGHT: i32 = 50;
 // size of the map
 pub const MAP_WIDTH: i32 = 80;
 pub const MAP_HEIGHT: i32 = 43;
+// the smallest floor a level profile can ask for
+pub const MIN_MAP_WIDTH: i32 = 20;
+pub const MIN_MAP_HEIGHT: i32 = 15;
 
 // field-of-view
 pub const TORCH_RADIUS: i32 = 10;
 
+// Time: every tick, everyone gains their speed in energy, and acts once they have enough.
+// Acting costs energy: most actions cost one turn's worth, wading through water two.
+pub const NORMAL_SPEED: i32 = 100;
+pub const ENERGY_TO_ACT: i32 = 100;
+pub const ACTION_COST: i32 = 100;
+pub const WATER_MOVE_COST: i32 = 200;
+// sneaking, each step costs this much more, and NPCs only see this share (percent) as far
+pub const SNEAK_EXTRA_COST: i32 = 100;
+pub const SNEAK_VISION_PERCENT: i32 = 50;
+
+// pathfinding: the cost of a step, and of going through someone else's tile
+pub const PATH_STEP_COST: u32 = 10;
+pub const PATH_DIAGONAL_COST: u32 = 14;
+pub const PATH_OBSTACLE_COST: u32 = 50;
+// give up searching after this many tiles, and re-plan a cached path after this many steps
+pub const PATH_MAX_NODES: usize = 2000;
+pub const PATH_MAX_AGE: u32 = 10;
+
+// NPC tactics: hurt this badly (percent of max HP) they run, and run this much harder than they chase
+pub const FLEE_HP_PERCENT: i32 = 25;
+pub const FLEE_PERCENT: i32 = 120;
+// cowards keep this many tiles away, and a pack this close spreads out around the player
+pub const KEEP_DISTANCE: i32 = 4;
+pub const SURROUND_RADIUS: f32 = 5.0;
+// how many stops on a patrol, how far a wanderer strolls at a time, and how
+// long a hunter searches where it last saw the player
+pub const PATROL_STOPS: usize = 3;
+pub const WANDER_RADIUS: i32 = 8;
+pub const SEARCH_TURNS: i32 = 10;
+
+// seen beyond this share (percent) of an NPC's sight radius, the player only makes it suspicious
+pub const GLIMPSE_PERCENT: i32 = 50;
+
+// noise: how many steps each sound carries, and how many more it takes to get through a closed door
+pub const NOISE_WALK: i32 = 5;
+pub const NOISE_FIGHT: i32 = 8;
+pub const NOISE_THUNDER: i32 = 15;
+pub const NOISE_FIREBALL: i32 = 15;
+pub const NOISE_DOOR_COST: i32 = 4;
+
 // colors for map objects
 pub const COLOR_DARK_WALL: Color = Color { r: 6, g: 3, b: 1 };
 pub const COLOR_DARK_GROUND: Color = Color { r: 81, g: 44, b: 15 };
 pub const COLOR_LIGHT_WALL: Color = Color { r: 30, g: 16, b: 5 };
 pub const COLOR_LIGHT_GROUND: Color = Color { r: 124, g: 65, b: 21 };
+pub const COLOR_DARK_WATER: Color = Color { r: 20, g: 40, b: 70 };
+pub const COLOR_LIGHT_WATER: Color = Color { r: 40, g: 80, b: 140 };
+pub const COLOR_DARK_LAVA: Color = Color { r: 90, g: 20, b: 0 };
+pub const COLOR_LIGHT_LAVA: Color = Color { r: 200, g: 60, b: 0 };
+pub const COLOR_DARK_CHASM: Color = Color { r: 0, g: 0, b: 0 };
+pub const COLOR_LIGHT_CHASM: Color = Color { r: 12, g: 10, b: 18 };
+pub const COLOR_DARK_DOOR: Color = Color { r: 60, g: 35, b: 12 };
+pub const COLOR_LIGHT_DOOR: Color = Color { r: 180, g: 120, b: 50 };
+
+// tile hazards
+pub const LAVA_DAMAGE: i32 = 10;
+pub const CHASM_FALL_DAMAGE: i32 = 5;
 
 // sizes and coordinates relevant for the GUI
 pub const BAR_WIDTH: i32 = 20;
@@ -47,8 +113,9 @@ pub const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
 pub const HELP_SCREEN_WIDTH: i32 = 50;
 pub const CHARACTER_SCREEN_WIDTH: i32 = 30;
 pub const INVENTORY_WIDTH: i32 = 50;
-pub const INVENTORY_KEYS: &'static str = "abcdefghijklmnopqrstuvwxyz12345678";
+pub const INVENTORY_KEYS: &str = "abcdefghijklmnopqrstuvwxyz12345678";
 pub const INVENTORY_MAX: usize = 34;
+pub const SLOTS_SCREEN_WIDTH: i32 = 72;
 
 // magic items
 pub const HEAL_AMOUNT: i32 = 40;
@@ -64,7 +131,7 @@ pub const MAX_STACK: i32 = 100;
 pub const NUM_LVLS: u32 = 21;
 pub const KARMA_TO_ASCEND: i32 = 1000;
 // NOTE: These strings should be <= 13 chars long
-pub const LVL_NAMES: [&'static str; NUM_LVLS as usize] = [
+pub const LVL_NAMES: [&str; NUM_LVLS as usize] = [
         "The Pit",
         "Well of Souls", "Well of Souls", "Well of Souls",
         "The Abyss", "The Abyss", "The Abyss",
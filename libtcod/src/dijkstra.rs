@@ -0,0 +1,274 @@
+/*
+  Dijkstra Maps
+
+  A Dijkstra map gives every tile of a floor the cost of walking from it to
+  the nearest goal. An NPC that steps to its cheapest neighbor each turn
+  heads for the goals, whatever is in the way. Reshape the values (and let
+  them settle again) and the same map does other jobs:
+
+    - multiply by a negative number: the NPC runs away, but towards open
+      ground rather than into the nearest corner
+    - take the distance from some range: the NPC keeps that far away
+
+  The maps the NPCs share are worked out again every turn, from where the
+  player is, but only once an NPC needs them.
+ */
+// Import Std Libs
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+// Import Locally
+use crate::constants::FLEE_PERCENT;
+use crate::constants::KEEP_DISTANCE;
+use crate::constants::PATH_DIAGONAL_COST;
+use crate::constants::PATH_STEP_COST;
+use crate::map::Map;
+use crate::map::NEIGHBORS;
+use crate::spatial_index::SpatialIndex;
+
+// what a tile that can't reach any goal is worth
+const UNREACHABLE: i32 = i32::MAX;
+
+
+pub struct DijkstraMap {
+    width: i32,
+    height: i32,
+    // column-major, like the Map
+    values: Vec<i32>,
+}
+
+
+impl DijkstraMap {
+    // the cost of walking from each tile to the nearest goal
+    pub fn new(map: &Map, goals: &[(i32, i32)]) -> Self {
+        let mut dijkstra = DijkstraMap {
+            width: map.width,
+            height: map.height,
+            values: vec![UNREACHABLE; (map.width * map.height) as usize],
+        };
+        for &(x, y) in goals.iter() {
+            if let Some(i) = dijkstra.index(x, y) {
+                dijkstra.values[i] = 0;
+            }
+        }
+        dijkstra.settle(map);
+        dijkstra
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x >= 0 && x < self.width && y >= 0 && y < self.height {
+            return Some((x * self.height + y) as usize);
+        }
+        None
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Option<i32> {
+        match self.index(x, y) {
+            Some(i) if self.values[i] != UNREACHABLE => Some(self.values[i]),
+            _ => None,
+        }
+    }
+
+    // Let every tile take the cheapest of its own value, or a neighbor's plus
+    // the step between them. Goals are just tiles that start out cheap.
+    fn settle(&mut self, map: &Map) {
+        let mut open: BinaryHeap<Reverse<(i32, (i32, i32))>> = BinaryHeap::new();
+        for (x, y) in map.positions() {
+            let value = self.values[self.index(x, y).unwrap()];
+            if value != UNREACHABLE {
+                open.push(Reverse((value, (x, y))));
+            }
+        }
+
+        while let Some(Reverse((value, (x, y)))) = open.pop() {
+            if value > self.values[self.index(x, y).unwrap()] {
+                continue;
+            }
+            for &(dx, dy) in NEIGHBORS.iter() {
+                let (nx, ny) = (x + dx, y + dy);
+                if !map.get(nx, ny).is_some_and(|tile| tile.passable()) {
+                    continue;
+                }
+                let step = if dx != 0 && dy != 0 { PATH_DIAGONAL_COST } else { PATH_STEP_COST };
+                let next_value = value + step as i32;
+                let i = self.index(nx, ny).unwrap();
+                if next_value < self.values[i] {
+                    self.values[i] = next_value;
+                    open.push(Reverse((next_value, (nx, ny))));
+                }
+            }
+        }
+    }
+
+    // a new map, from this one's values run through `reshape` and settled again
+    pub fn reshaped<F: Fn(i32) -> i32>(&self, map: &Map, reshape: F) -> DijkstraMap {
+        let values = self
+            .values
+            .iter()
+            .map(|&value| if value == UNREACHABLE { UNREACHABLE } else { reshape(value) })
+            .collect();
+        let mut dijkstra = DijkstraMap { width: self.width, height: self.height, values };
+        dijkstra.settle(map);
+        dijkstra
+    }
+
+    // The cheapest tile to step to from here, if it is cheaper than staying put.
+    // Tiles somebody is standing on don't count.
+    pub fn best_step(&self, from: (i32, i32), map: &Map, index: &SpatialIndex) -> Option<(i32, i32)> {
+        let mut best: Option<(i32, i32)> = None;
+        let mut best_value: i32 = self.get(from.0, from.1).unwrap_or(UNREACHABLE);
+        for &(dx, dy) in NEIGHBORS.iter() {
+            let (nx, ny) = (from.0 + dx, from.1 + dy);
+            if !map.get(nx, ny).is_some_and(|tile| tile.passable()) || index.is_blocked(nx, ny) {
+                continue;
+            }
+            if let Some(value) = self.get(nx, ny) {
+                if value < best_value {
+                    best = Some((nx, ny));
+                    best_value = value;
+                }
+            }
+        }
+        best
+    }
+}
+
+
+// The kinds of map the NPCs steer by
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AiMap {
+    // run away from the player
+    Flee,
+    // stay about KEEP_DISTANCE tiles from the player
+    KeepDistance,
+    // head for a free tile next to the player
+    Surround,
+}
+
+
+// The maps every NPC on the player's floor shares, for one turn. Each is only
+// worked out once some NPC needs it.
+#[derive(Default)]
+pub struct AiMaps {
+    player: (i32, i32),
+    approach: Option<DijkstraMap>,
+    flee: Option<DijkstraMap>,
+    keep_distance: Option<DijkstraMap>,
+    surround: Option<Option<DijkstraMap>>,
+}
+
+
+impl AiMaps {
+    // a new turn: forget the old maps
+    pub fn reset(&mut self, player: (i32, i32)) {
+        *self = AiMaps { player, ..Default::default() };
+    }
+
+    pub fn get(&mut self, which: AiMap, map: &Map, index: &SpatialIndex) -> Option<&DijkstraMap> {
+        let player = self.player;
+        match which {
+            AiMap::Flee => {
+                let approach = self.approach.get_or_insert_with(|| DijkstraMap::new(map, &[player]));
+                Some(self.flee.get_or_insert_with(|| approach.reshaped(map, |value| -value * FLEE_PERCENT / 100)))
+            }
+            AiMap::KeepDistance => {
+                let approach = self.approach.get_or_insert_with(|| DijkstraMap::new(map, &[player]));
+                let range = KEEP_DISTANCE * PATH_STEP_COST as i32;
+                Some(self.keep_distance.get_or_insert_with(|| approach.reshaped(map, |value| (value - range).abs())))
+            }
+            AiMap::Surround => self
+                .surround
+                .get_or_insert_with(|| {
+                    // the free tiles next to the player (there may be none left)
+                    let free_sides: Vec<(i32, i32)> = NEIGHBORS
+                        .iter()
+                        .map(|&(dx, dy)| (player.0 + dx, player.1 + dy))
+                        .filter(|&(x, y)| map.get(x, y).is_some_and(|tile| tile.passable()) && !index.is_blocked(x, y))
+                        .collect();
+                    if free_sides.is_empty() { None } else { Some(DijkstraMap::new(map, &free_sides)) }
+                })
+                .as_ref(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tcod::colors::WHITE;
+    use crate::map::Tile;
+    use crate::objects::Object;
+
+    // an open room with the walls all around, 20 by 9 inside
+    fn open_room() -> Map {
+        let mut map = Map::new(22, 11);
+        for x in 1..21 {
+            for y in 1..10 {
+                map[(x, y)] = Tile::empty();
+            }
+        }
+        map
+    }
+
+
+    #[test]
+    fn each_tile_costs_the_walk_to_the_nearest_goal() {
+        let map = open_room();
+        let dijkstra = DijkstraMap::new(&map, &[(2, 2), (18, 2)]);
+        assert_eq!(dijkstra.get(2, 2), Some(0));
+        assert_eq!(dijkstra.get(3, 2), Some(PATH_STEP_COST as i32));
+        assert_eq!(dijkstra.get(3, 3), Some(PATH_DIAGONAL_COST as i32));
+        assert_eq!(dijkstra.get(17, 2), Some(PATH_STEP_COST as i32));
+        // walls (and off the map) can't be walked from
+        assert_eq!(dijkstra.get(0, 0), None);
+        assert_eq!(dijkstra.get(-1, 4), None);
+    }
+
+
+    #[test]
+    fn fleeing_and_keeping_distance_step_the_right_way() {
+        let map = open_room();
+        let index = SpatialIndex::default();
+        let player = (5, 5);
+        let mut maps = AiMaps::default();
+        maps.reset(player);
+        let approach = DijkstraMap::new(&map, &[player]);
+        let further = |from: (i32, i32), to: (i32, i32)| approach.get(to.0, to.1) > approach.get(from.0, from.1);
+
+        let step = maps.get(AiMap::Flee, &map, &index).unwrap().best_step((6, 5), &map, &index).unwrap();
+        assert!(further((6, 5), step));
+
+        let keep = maps.get(AiMap::KeepDistance, &map, &index).unwrap();
+        assert!(further((6, 5), keep.best_step((6, 5), &map, &index).unwrap()));
+        assert!(further(keep.best_step((15, 5), &map, &index).unwrap(), (15, 5)));
+        // far enough already: stay put
+        assert_eq!(keep.best_step((5 + KEEP_DISTANCE, 5), &map, &index), None);
+    }
+
+
+    #[test]
+    fn surrounding_goes_for_the_free_sides() {
+        let map = open_room();
+        let player = (5, 5);
+        let mut taken: Vec<Object> = NEIGHBORS
+            .iter()
+            .skip(1)
+            .map(|&(dx, dy)| Object::new(5 + dx, 5 + dy, 'o', "orc", WHITE, true))
+            .collect();
+        let mut index = SpatialIndex::default();
+        index.rebuild(&map, &taken);
+
+        // only the tile above the player is free
+        let mut maps = AiMaps::default();
+        maps.reset(player);
+        let surround = maps.get(AiMap::Surround, &map, &index).unwrap();
+        assert_eq!(surround.get(5, 4), Some(0));
+        assert_eq!(surround.best_step((5, 2), &map, &index), Some((5, 3)));
+
+        taken.push(Object::new(5, 4, 'o', "orc", WHITE, true));
+        index.rebuild(&map, &taken);
+        maps.reset(player);
+        assert!(maps.get(AiMap::Surround, &map, &index).is_none());
+    }
+}
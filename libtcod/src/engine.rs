@@ -0,0 +1,610 @@
+/*
+  The Game Engine
+
+  Everything that happens in a turn, with no window in sight: the player's
+  command, the NPCs' replies, changing floors, dying and reincarnating.
+  Whenever the game needs the player to decide something (a menu, a target)
+  it asks through the Ui trait, so the tcod frontend, tests and tools can all
+  drive the same game.
+ */
+// Import Std Libs
+use std::collections::VecDeque;
+
+// Import Third-Party
+use rand::Rng;
+use tcod::colors::*;
+
+// Import Locally
+use crate::ai_algos::ai_take_turn;
+use crate::constants::ACTION_COST;
+use crate::constants::CHASM_FALL_DAMAGE;
+use crate::constants::ENERGY_TO_ACT;
+use crate::constants::INVENTORY_WIDTH;
+use crate::constants::KARMA_TO_ASCEND;
+use crate::constants::LAVA_DAMAGE;
+use crate::constants::NUM_LVLS;
+use crate::constants::PLAYER;
+use crate::equipment::drop_item;
+use crate::equipment::inventory_names;
+use crate::equipment::pick_item_up;
+use crate::equipment::player_use_item;
+use crate::handles::find;
+use crate::handles::Handle;
+use crate::map::TileKind;
+use crate::map_algos::passes::floor_tiles;
+use crate::moves::is_blocked;
+use crate::moves::player_move_or_attack;
+use crate::moves::Command;
+use crate::moves::PlayerAction;
+use crate::noise::hear_noises;
+use crate::objects::Fighter;
+use crate::objects::Game;
+use crate::objects::Object;
+use crate::player::reincarnate_reset;
+
+
+// Everything the game needs to ask the player, mid-turn.
+pub trait Ui {
+    // pick one of the options, or None to cancel
+    fn menu(&mut self, game: &mut Game, header: &str, options: &[String], width: i32) -> Option<usize>;
+
+    // pick a tile in the player's FOV (and in range, if there is one), or None to cancel
+    fn target_tile(&mut self, game: &mut Game, objects: &[Object], max_range: Option<f32>) -> Option<(i32, i32)>;
+}
+
+
+// A Ui with no window: it answers from a script, and cancels once the script runs out.
+#[derive(Default)]
+pub struct ScriptedUi {
+    pub choices: VecDeque<Option<usize>>,
+    pub targets: VecDeque<Option<(i32, i32)>>,
+}
+
+
+impl Ui for ScriptedUi {
+    fn menu(&mut self, _game: &mut Game, _header: &str, _options: &[String], _width: i32) -> Option<usize> {
+        self.choices.pop_front().flatten()
+    }
+
+    fn target_tile(&mut self, _game: &mut Game, _objects: &[Object], _max_range: Option<f32>) -> Option<(i32, i32)> {
+        self.targets.pop_front().flatten()
+    }
+}
+
+
+// create object representing the player
+pub fn new_player() -> Object {
+    let mut player = Object::new(0, 0, '@', "you", WHITE, true);
+    player.alive = true;
+    player.fighter = Some(Fighter::new(100, 2, 3, 0, false));
+
+    // NOTE: This is here to ensure the first rebirth matches all following reincarnations
+    reincarnate_reset(&mut player);
+    player
+}
+
+
+pub fn new_game(seed: u64) -> (Game, Vec<Vec<Object>>, Vec<Vec<Object>>) {
+    // the list of objects in the game, by floor
+    let mut objects: Vec<Vec<Object>> = vec![vec![]; NUM_LVLS as usize];
+    objects[0].push(new_player());
+
+    let mut game = Game::new(&mut objects, seed);
+    game.enter_floor(&objects[0]);
+
+    // a welcome message
+    game.messages.add(
+        "You wake up in Purgatory. Ascend from this place, or be stuck here forever.",
+        RED,
+    );
+
+    // save off the starting position of the game
+    let starts = objects.clone();
+
+    (game, objects, starts)
+}
+
+
+pub fn reincarnate(game: &mut Game,
+                   objects: &mut Vec<Vec<Object>>,
+                   start_objects: &[Vec<Object>]) {
+    // copy over objects
+    let mut player: Object = objects[game.lvl][0].clone();
+    reincarnate_reset(&mut player);
+    (player.x, player.y) = game.down_stairs[0];
+    *objects = start_objects.to_vec();
+    objects[0][PLAYER] = player;
+
+    // update game
+    game.lvl = 0;
+    game.day += 1;
+    game.turn += 1;
+    game.enter_floor(&objects[0]);
+}
+
+
+fn change_player_level(objects: &mut [Vec<Object>], from_lvl: usize, to_lvl: usize) {
+    // the same removal as everywhere else, so nobody left behind changes order
+    let player = objects[from_lvl].remove(PLAYER);
+    objects[to_lvl].insert(PLAYER, player);
+}
+
+
+// The first time the player reaches a floor it gets built, and a copy of it
+// (as it was) is kept for reincarnation.
+pub fn build_levels(game: &mut Game,
+                    all_objects: &mut [Vec<Object>],
+                    start_objects: &mut [Vec<Object>],
+                    level: usize) {
+    while game.maps.len() <= level {
+        let next: usize = game.maps.len();
+        game.make_level(all_objects);
+        start_objects[next] = all_objects[next].clone();
+    }
+}
+
+
+fn go_up_level(game: &mut Game, all_objects: &mut [Vec<Object>], start_objects: &mut [Vec<Object>]) -> bool {
+    if game.lvl == (NUM_LVLS as usize - 1) {
+        if all_objects[game.lvl][PLAYER].fighter.as_ref().unwrap().karma >= KARMA_TO_ASCEND {
+            game.messages.add(
+                "You ascend from Purgatory.",
+                RED,
+            );
+            return false;
+        } else {
+            game.messages.add(
+                "Your karma is too low to leave Purgatory.",
+                RED,
+            );
+            return false;
+        }
+    } else {
+        game.messages.add(
+            "You ascend higher into Purgatory...",
+            RED,
+        );
+        build_levels(game, all_objects, start_objects, game.lvl + 1);
+        game.lvl += 1;
+        change_player_level(all_objects, game.lvl - 1, game.lvl);
+        all_objects[game.lvl][PLAYER].x = game.down_stairs[game.lvl].0;
+        all_objects[game.lvl][PLAYER].y = game.down_stairs[game.lvl].1;
+        game.enter_floor(&all_objects[game.lvl]);
+    }
+    true
+}
+
+
+fn go_down_level(game: &mut Game, all_objects: &mut [Vec<Object>]) -> bool {
+    if game.lvl == 0 {
+        game.messages.add(
+            "There is no going lower than where you are.",
+            RED,
+        );
+        return false;
+    } else {
+        game.messages.add(
+            "You descend back down into Purgatory.",
+            RED,
+        );
+        game.lvl -= 1;
+        change_player_level(all_objects, game.lvl + 1, game.lvl);
+        all_objects[game.lvl][PLAYER].x = game.up_stairs[game.lvl].0;
+        all_objects[game.lvl][PLAYER].y = game.up_stairs[game.lvl].1;
+        game.enter_floor(&all_objects[game.lvl]);
+    }
+    true
+}
+
+
+// Carry out one of the player's commands. Screens that only show
+// information (and quitting) are up to the frontend.
+pub fn player_turn(ui: &mut dyn Ui,
+                   game: &mut Game,
+                   all_objects: &mut [Vec<Object>],
+                   start_objects: &mut [Vec<Object>],
+                   command: Command) -> PlayerAction {
+    use PlayerAction::*;
+
+    let objects = &mut all_objects[game.lvl];
+    let player_alive = objects[PLAYER].alive;
+    match (command, player_alive) {
+        (Command::Move(dx, dy), true) => {
+            let action = player_move_or_attack(dx, dy, game, objects);
+            if game.lvl > 0 && tile_kind_under(game, &objects[PLAYER]) == TileKind::Chasm {
+                fall_into_chasm(game, all_objects);
+            }
+            action
+        }
+        (Command::Wait, true) => {
+            TookTurn
+        }
+
+        // start (or stop) sneaking: it takes no time
+        (Command::Sneak, true) => {
+            game.sneaking = !game.sneaking;
+            if game.sneaking {
+                game.messages.add("You start sneaking.", LIGHT_GREY);
+            } else {
+                game.messages.add("You stop sneaking.", LIGHT_GREY);
+            }
+            DidntTakeTurn
+        }
+
+        // go up stairs, if the player is on them
+        (Command::GoUp, true) => {
+            let (x, y) = objects[PLAYER].pos();
+            let player_on_stairs = game.index
+                .at(x, y)
+                .iter()
+                .any(|&id| objects[id].name == "up-stairs");
+            if player_on_stairs && go_up_level(game, all_objects, start_objects) {
+                // TODO: If game.level >= NUM_LVLS: return WinExit
+                return TookTurn;
+            }
+            DidntTakeTurn
+        }
+
+        // go down stairs, if the player is on them
+        (Command::GoDown, true) => {
+            let (x, y) = objects[PLAYER].pos();
+            let player_on_stairs = game.index
+                .at(x, y)
+                .iter()
+                .any(|&id| objects[id].name == "down-stairs");
+            if player_on_stairs && go_down_level(game, all_objects) {
+                return TookTurn;
+            }
+            DidntTakeTurn
+        }
+
+        // show the inventory: if an item is selected, use it
+        (Command::Inventory, true) => {
+            let inventory_index = choose_from_inventory(
+                ui,
+                game,
+                &objects[PLAYER],
+                "Press the key next to an item to use it, or any other to cancel.\n",
+            );
+            if let Some(inventory_index) = inventory_index {
+                player_use_item(inventory_index, ui, game, objects);
+            }
+            MenuAction
+        }
+
+        // Pick up an item
+        (Command::PickUp, true) => {
+            let (x, y) = objects[PLAYER].pos();
+            let item_id = game.index
+                .at(x, y)
+                .iter()
+                .copied()
+                .find(|&id| objects[id].item.is_some());
+            if let Some(item_id) = item_id {
+                pick_item_up(item_id, PLAYER, game, objects);
+                TookTurn
+            } else {
+                DidntTakeTurn
+            }
+        }
+
+        // show the inventory; if an item is selected, drop it
+        (Command::Drop, true) => {
+            let inventory_index = choose_from_inventory(
+                ui,
+                game,
+                &objects[PLAYER],
+                "Press the key next to an item to drop it, or any other to cancel.\n'",
+            );
+            if let Some(inventory_index) = inventory_index {
+                drop_item(inventory_index, PLAYER, game, objects);
+            }
+            DidntTakeTurn
+        }
+
+        (Command::Exit, _) => Exit,
+
+        (_, _) => DidntTakeTurn,
+    }
+}
+
+
+fn choose_from_inventory(ui: &mut dyn Ui, game: &mut Game, player: &Object, header: &str) -> Option<usize> {
+    let inventory: &[Object] = match player.fighter.as_ref() {
+        Some(fighter) => &fighter.inventory,
+        None => return None,
+    };
+    let choice = ui.menu(game, header, &inventory_names(inventory), INVENTORY_WIDTH);
+
+    // an empty inventory still shows a menu, but there is nothing to choose
+    if !inventory.is_empty() {
+        choice
+    } else {
+        None
+    }
+}
+
+
+// every NPC on the player's floor with the energy for it gets to act (the fast ones, maybe more than once)
+pub fn npc_turns(game: &mut Game, objects: &mut [Object]) {
+    // by handle, so nobody loses their turn if the list changes under us
+    let npcs: Vec<Handle> = objects
+        .iter()
+        .enumerate()
+        .filter(|&(id, object)| id != PLAYER && object.ai.is_some())
+        .map(|(_, object)| object.handle)
+        .collect();
+    let player = objects[PLAYER].pos();
+    game.ai_maps.reset(player);
+    for npc in npcs {
+        while let Some(id) = find(objects, npc) {
+            let energy: i32 = match objects[id].fighter.as_ref() {
+                Some(fighter) => fighter.energy,
+                None => break,
+            };
+            if energy < ENERGY_TO_ACT || objects[id].ai.is_none() {
+                break;
+            }
+            ai_take_turn(npc, game, objects);
+            // standing around takes time too
+            if objects[id].fighter.as_ref().is_some_and(|fighter| fighter.energy == energy) {
+                objects[id].spend_energy(ACTION_COST);
+            }
+        }
+    }
+}
+
+
+// Time passes until the player is ready to act again. Every tick, everyone on
+// the floor gains energy at their own speed, the NPCs hear any noises and act,
+// and lava burns.
+fn pass_time(game: &mut Game, objects: &mut [Object]) {
+    while objects[PLAYER].alive && objects[PLAYER].fighter.as_ref().unwrap().energy < ENERGY_TO_ACT {
+        for object in objects.iter_mut().filter(|object| object.alive) {
+            if let Some(fighter) = object.fighter.as_mut() {
+                fighter.energy += fighter.speed;
+            }
+        }
+        hear_noises(game, objects);
+        npc_turns(game, objects);
+        burn_in_lava(game, objects);
+    }
+}
+
+
+fn tile_kind_under(game: &Game, object: &Object) -> TileKind {
+    game.maps[game.lvl][(object.x, object.y)].kind
+}
+
+
+// Down through the chasm: the player lands somewhere on the floor below, and it hurts.
+fn fall_into_chasm(game: &mut Game, all_objects: &mut [Vec<Object>]) {
+    game.messages.add("You fall through the chasm, to the floor below!", RED);
+    game.lvl -= 1;
+    change_player_level(all_objects, game.lvl + 1, game.lvl);
+
+    let objects = &mut all_objects[game.lvl];
+    game.index.rebuild(&game.maps[game.lvl], objects);
+    let map = &game.maps[game.lvl];
+    let landing: Vec<(i32, i32)> = floor_tiles(map)
+        .into_iter()
+        .filter(|&(x, y)| map[(x, y)].kind == TileKind::Floor && !is_blocked(x, y, map, &game.index))
+        .collect();
+    // with nowhere free to land, the player ends up by the stairs
+    let (x, y) = if landing.is_empty() {
+        game.up_stairs[game.lvl]
+    } else {
+        landing[game.rng.gen_range(0, landing.len())]
+    };
+    objects[PLAYER].set_pos(x, y);
+    game.enter_floor(objects);
+    objects[PLAYER].take_damage(CHASM_FALL_DAMAGE, game);
+}
+
+
+// lava burns everyone standing in it, at the end of every turn
+fn burn_in_lava(game: &mut Game, objects: &mut [Object]) {
+    for (id, object) in objects.iter_mut().enumerate() {
+        if !object.alive || object.fighter.is_none() || tile_kind_under(game, object) != TileKind::Lava {
+            continue;
+        }
+        if id == PLAYER {
+            game.messages.add("The lava burns you!", ORANGE);
+        } else if game.fov.is_in_fov(object.x, object.y) {
+            game.messages.add(format!("The {} burns in the lava.", object.name), ORANGE);
+        }
+        object.take_damage(LAVA_DAMAGE, game);
+    }
+}
+
+
+// One full turn: the player's command, then (if that took a turn) the NPCs' replies, until it's the player's go again.
+pub fn take_turn(ui: &mut dyn Ui,
+                 game: &mut Game,
+                 all_objects: &mut [Vec<Object>],
+                 start_objects: &mut [Vec<Object>],
+                 command: Command) -> PlayerAction {
+    let energy: i32 = all_objects[game.lvl][PLAYER].fighter.as_ref().unwrap().energy;
+    let player_action = player_turn(ui, game, all_objects, start_objects, command);
+
+    if player_action == PlayerAction::TookTurn {
+        game.turn += 1;
+        // the floor the player ended up on, which isn't always the one they started on
+        let objects = &mut all_objects[game.lvl];
+        // whatever the player did took time, even if it didn't spend any energy itself
+        if objects[PLAYER].fighter.as_ref().unwrap().energy == energy {
+            objects[PLAYER].spend_energy(ACTION_COST);
+        }
+        pass_time(game, objects);
+    }
+
+    game.compute_fov(&all_objects[game.lvl][PLAYER]);
+    player_action
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::NORMAL_SPEED;
+    use crate::map::Tile;
+    use crate::objects::Ai;
+    use crate::objects::Fighter;
+    use crate::objects::Item;
+
+    // a new game whose first floor is one big empty room, with only the player in it
+    fn open_floor(seed: u64, player: (i32, i32)) -> (Game, Vec<Vec<Object>>, Vec<Vec<Object>>) {
+        let (mut game, mut objects, starts) = new_game(seed);
+        for (_, tile) in game.maps[0].iter_mut() {
+            *tile = Tile::empty();
+        }
+        objects[0].truncate(1);
+        objects[0][PLAYER].x = player.0;
+        objects[0][PLAYER].y = player.1;
+        game.enter_floor(&objects[0]);
+        (game, objects, starts)
+    }
+
+
+    #[test]
+    fn same_seed_same_game() {
+        let commands = [Command::Move(1, 0), Command::Move(0, 1), Command::PickUp, Command::Move(-1, 0),
+                        Command::Inventory, Command::Move(0, -1), Command::Wait, Command::GoDown];
+        let mut runs: Vec<Vec<String>> = vec![];
+        for _ in 0..2 {
+            let (mut game, mut objects, mut starts) = new_game(9);
+            let mut ui = ScriptedUi::default();
+            let mut states: Vec<String> = vec![];
+            for i in 0..300 {
+                // use whatever is first in the bag, whenever the inventory comes up
+                ui.choices.push_back(Some(0));
+                take_turn(&mut ui, &mut game, &mut objects, &mut starts, commands[(i * 5) % commands.len()]);
+                ui.choices.clear();
+                states.push(serde_json::to_string(&(game.lvl, game.turn, &objects)).unwrap());
+            }
+            runs.push(states);
+        }
+        assert_eq!(runs[0], runs[1]);
+    }
+
+
+    #[test]
+    fn scripted_ui_cancels_when_out_of_answers() {
+        let (mut game, mut objects, mut starts) = open_floor(2, (10, 10));
+        let mut ui = ScriptedUi::default();
+        let turn = game.turn;
+        // nothing scripted: the inventory menu is cancelled, and no time passes
+        let action = take_turn(&mut ui, &mut game, &mut objects, &mut starts, Command::Inventory);
+        assert_eq!(action, PlayerAction::MenuAction);
+        assert_eq!(game.turn, turn);
+        let action = take_turn(&mut ui, &mut game, &mut objects, &mut starts, Command::Move(1, 0));
+        assert_eq!(action, PlayerAction::TookTurn);
+        assert_eq!(objects[0][PLAYER].pos(), (11, 10));
+        assert_eq!(game.turn, turn + 1);
+    }
+
+
+    #[test]
+    fn scripted_choices_use_items() {
+        let (mut game, mut objects, mut starts) = open_floor(3, (10, 10));
+        let mut potion = Object::new(0, 0, '!', "healing potion", WHITE, false);
+        potion.item = Some(Item::HealPot);
+        let fighter = objects[0][PLAYER].fighter.as_mut().unwrap();
+        fighter.inventory.push(potion);
+        fighter.hp = 1;
+
+        let mut ui = ScriptedUi::default();
+        ui.choices.push_back(Some(0));
+        take_turn(&mut ui, &mut game, &mut objects, &mut starts, Command::Inventory);
+        let fighter = objects[0][PLAYER].fighter.as_ref().unwrap();
+        assert!(fighter.hp > 1);
+        assert!(fighter.inventory.is_empty());
+    }
+
+
+    // an NPC that comes for the player, at its own speed
+    fn add_npc(game: &mut Game, objects: &mut Vec<Object>, pos: (i32, i32), speed: i32) {
+        let mut npc = Object::new(pos.0, pos.1, 'o', "orc", WHITE, true);
+        npc.alive = true;
+        npc.ai = Some(Ai::Basic);
+        let mut fighter = Fighter::new(10, 0, 1, 0, true);
+        fighter.speed = speed;
+        npc.fighter = Some(fighter);
+        objects.push(npc);
+        game.handles.allocate_all(objects);
+        game.enter_floor(objects);
+    }
+
+
+    fn steps_away(objects: &[Object], id: usize) -> i32 {
+        let (dx, dy) = (objects[id].x - objects[PLAYER].x, objects[id].y - objects[PLAYER].y);
+        dx.abs().max(dy.abs())
+    }
+
+
+    #[test]
+    fn fast_npcs_act_more_often_than_slow_ones() {
+        let (mut game, mut objects, mut starts) = open_floor(6, (10, 10));
+        add_npc(&mut game, &mut objects[0], (10, 18), 2 * NORMAL_SPEED);
+        add_npc(&mut game, &mut objects[0], (18, 10), NORMAL_SPEED / 2);
+        for _ in 0..2 {
+            take_turn(&mut ScriptedUi::default(), &mut game, &mut objects, &mut starts, Command::Wait);
+        }
+        // two turns: four steps for the fast one, one for the slow one
+        assert_eq!(steps_away(&objects[0], 1), 4);
+        assert_eq!(steps_away(&objects[0], 2), 7);
+        assert!(objects[0][PLAYER].fighter.as_ref().unwrap().energy >= ENERGY_TO_ACT);
+    }
+
+
+    #[test]
+    fn wading_gives_the_npcs_two_goes() {
+        let (mut game, mut objects, mut starts) = open_floor(7, (10, 10));
+        game.maps[0][(11, 10)] = Tile::water();
+        add_npc(&mut game, &mut objects[0], (11, 18), NORMAL_SPEED);
+        take_turn(&mut ScriptedUi::default(), &mut game, &mut objects, &mut starts, Command::Move(1, 0));
+        assert_eq!(objects[0][PLAYER].pos(), (11, 10));
+        assert_eq!(steps_away(&objects[0], 1), 6);
+    }
+
+
+    // the player, on the second floor, next to a chasm
+    fn by_a_chasm(seed: u64) -> (Game, Vec<Vec<Object>>, Vec<Vec<Object>>) {
+        let (mut game, mut objects, mut starts) = open_floor(seed, (10, 10));
+        build_levels(&mut game, &mut objects, &mut starts, 1);
+        change_player_level(&mut objects, 0, 1);
+        objects[1].truncate(1);
+        game.lvl = 1;
+        for (_, tile) in game.maps[1].iter_mut() {
+            *tile = Tile::empty();
+        }
+        game.maps[1][(11, 10)] = Tile::chasm();
+        (game, objects, starts)
+    }
+
+
+    #[test]
+    fn stepping_into_a_chasm_drops_you_a_floor() {
+        let (mut game, mut objects, mut starts) = by_a_chasm(4);
+        let hp = objects[1][PLAYER].fighter.as_ref().unwrap().hp;
+        take_turn(&mut ScriptedUi::default(), &mut game, &mut objects, &mut starts, Command::Move(1, 0));
+        assert_eq!(game.lvl, 0);
+        assert!(objects[1].is_empty());
+        let (x, y) = objects[0][PLAYER].pos();
+        assert_eq!(game.maps[0][(x, y)].kind, TileKind::Floor);
+        assert!(objects[0][PLAYER].fighter.as_ref().unwrap().hp < hp);
+    }
+
+
+    #[test]
+    fn with_nowhere_to_land_you_fall_by_the_stairs() {
+        let (mut game, mut objects, mut starts) = by_a_chasm(5);
+        for (_, tile) in game.maps[0].iter_mut() {
+            *tile = Tile::wall();
+        }
+        take_turn(&mut ScriptedUi::default(), &mut game, &mut objects, &mut starts, Command::Move(1, 0));
+        assert_eq!(game.lvl, 0);
+        assert_eq!(objects[0][PLAYER].pos(), game.up_stairs[0]);
+    }
+}
+
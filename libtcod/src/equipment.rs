@@ -9,12 +9,13 @@ use tcod::colors::*;
 use crate::constants::INVENTORY_MAX;
 use crate::constants::MAX_STACK;
 use crate::constants::PLAYER;
+use crate::engine::Ui;
+use crate::handles::find;
+use crate::handles::Handle;
 use crate::magic::cast_confuse;
 use crate::magic::cast_fireball;
 use crate::magic::cast_heal;
 use crate::magic::cast_lightning;
-use crate::menus::Messages;
-use crate::menus::Tcod;
 use crate::objects::Fighter;
 use crate::objects::Game;
 use crate::objects::Item;
@@ -30,22 +31,26 @@ fn get_equipped_in_slot(slot: Slot, inventory: &[Object]) -> Option<Vec<usize>>
         if item
             .equipment
             .as_ref()
-            .map_or(false, |e| e.equipped && e.slot == slot)
+            .is_some_and(|e| e.equipped && e.slot == slot)
         {
             ids.push(inv_id);
         }
     }
 
-    if ids.len() > 0 {
-        return Some(ids);
+    if !ids.is_empty() {
+        Some(ids)
     } else {
-        return None;
+        None
     }
 }
 
 
-fn toggle_equipment(inv_id: usize, _tcod: &mut Tcod, game: &mut Game, objs: &mut [Object]) -> UseResult {
+fn toggle_equipment(item: Handle, _ui: &mut dyn Ui, game: &mut Game, objs: &mut [Object]) -> UseResult {
     let fighter: &mut Fighter = objs[PLAYER].fighter.as_mut().unwrap();
+    let inv_id: usize = match find(&fighter.inventory, item) {
+        Some(inv_id) => inv_id,
+        None => return UseResult::Cancelled,
+    };
     let equipment = match fighter.inventory[inv_id].equipment {
         Some(equipment) => equipment,
         None => return UseResult::Cancelled,
@@ -67,12 +72,38 @@ fn toggle_equipment(inv_id: usize, _tcod: &mut Tcod, game: &mut Game, objs: &mut
 }
 
 
+// how each item in an inventory is listed in a menu
+pub fn inventory_names(inventory: &[Object]) -> Vec<String> {
+    if inventory.is_empty() {
+        return vec!["Inventory is empty.".into()];
+    }
+
+    inventory
+        .iter()
+        .map(|thing| {
+            // show additional information, in case it's equipped
+            match thing.equipment {
+                Some(equipment) if equipment.equipped => {
+                    format!("{} (on {})", thing.name, equipment.slot)
+                }
+                _ => match thing.item {
+                    Some(_item) if thing.charges > 1 => {
+                        format!("{} ({})", thing.name, thing.charges)
+                    }
+                    _ => thing.name.clone(),
+                }
+            }
+        })
+        .collect()
+}
+
+
 // add to the player's inventory and remove from the map
-pub fn pick_item_up(obj_id: usize, picker_id: usize, messages: &mut Messages, objs: &mut Vec<Object>) {
-    let is_player: bool = if picker_id == PLAYER { true } else { false };
+pub fn pick_item_up(obj_id: usize, picker_id: usize, game: &mut Game, objs: &mut Vec<Object>) {
+    let is_player: bool = picker_id == PLAYER;
     let fighter: &Fighter = objs[picker_id].fighter.as_ref().unwrap();
     if is_player && fighter.inventory.len() >= INVENTORY_MAX {
-        messages.add(
+        game.messages.add(
             format!(
                 "Your inventory is full, you cannot pick up {}.",
                 objs[obj_id].name
@@ -95,41 +126,48 @@ pub fn pick_item_up(obj_id: usize, picker_id: usize, messages: &mut Messages, ob
         for id in ids.iter() {
             if fighter.inventory[*id].charges < MAX_STACK {
                 objs[picker_id].fighter.as_mut().unwrap().inventory[*id].charges += 1;
-                messages.add(format!("{} added a {} to a stack.",
+                game.messages.add(format!("{} added a {} to a stack.",
                                      objs[picker_id].name,
                                      objs[obj_id].name.clone()),
                              GREEN);
-                objs.remove(obj_id);
+                let picked: Object = objs.remove(obj_id);
+                game.handles.release(picked.handle);
+                // the ids after it have all moved down one, so index the floor again
+                game.index.rebuild(&game.maps[game.lvl], objs);
                 return;
             }
         }
     }
 
     // if it's not stackable, or no current stack was found, just add it to the inventory
-    let item: Object = objs.swap_remove(obj_id);
+    let item: Object = objs.remove(obj_id);
+    // it's kept, so its handle is too
+    game.index.rebuild(&game.maps[game.lvl], objs);
     let item_name: String = item.name.clone();
     objs[picker_id].fighter.as_mut().unwrap().inventory.push(item);
-    messages.add(format!("{} picked up a {}.", objs[picker_id].name, item_name), GREEN);
+    game.messages.add(format!("{} picked up a {}.", objs[picker_id].name, item_name), GREEN);
 }
 
 
-pub fn drop_item(inv_id: usize, dropper_id: usize, messages: &mut Messages, objects: &mut Vec<Object>) {
+pub fn drop_item(inv_id: usize, dropper_id: usize, game: &mut Game, objects: &mut Vec<Object>) {
     let fighter: &mut Fighter = objects[dropper_id].fighter.as_mut().unwrap();
     let mut item: Object = fighter.inventory.remove(inv_id);
     if item.equipment.is_some() {
-        item.dequip(messages);
+        item.dequip(&mut game.messages);
     }
     item.set_pos(objects[dropper_id].x, objects[dropper_id].y);
-    messages.add(format!("{} dropped a {}.", objects[dropper_id].name, item.name), YELLOW);
+    game.messages.add(format!("{} dropped a {}.", objects[dropper_id].name, item.name), YELLOW);
+    game.index.add(objects.len(), item.pos(), item.blocks);
     objects.push(item);
 }
 
 
 // The player should also be able to use scrolls/potions they are standing on (and is useable).
-pub fn player_use_item(inv_id: usize, tcod: &mut Tcod, game: &mut Game, objs: &mut Vec<Object>) {
+pub fn player_use_item(inv_id: usize, ui: &mut dyn Ui, game: &mut Game, objs: &mut [Object]) {
     use Item::*;
     // just call the "use_function" if it is defined
     let fighter: &Fighter = objs[PLAYER].fighter.as_ref().unwrap();
+    let handle: Handle = fighter.inventory[inv_id].handle;
     if let Some(item) = fighter.inventory[inv_id].item {
         let on_use = match item {
             // TODO: This seems like a limiting design.
@@ -140,14 +178,21 @@ pub fn player_use_item(inv_id: usize, tcod: &mut Tcod, game: &mut Game, objs: &m
             Sword => toggle_equipment,
             Shield => toggle_equipment,
         };
-        match on_use(inv_id, tcod, game, objs) {
+        match on_use(handle, ui, game, objs) {
             UseResult::UsedUp => {
-                if objs[PLAYER].fighter.as_mut().unwrap().inventory[inv_id].charges > 1 {
+                // using it may have moved it around the inventory
+                let inventory: &mut Vec<Object> = &mut objs[PLAYER].fighter.as_mut().unwrap().inventory;
+                let inv_id: usize = match find(inventory, handle) {
+                    Some(inv_id) => inv_id,
+                    None => return,
+                };
+                if inventory[inv_id].charges > 1 {
                     // if the object has charges, just reduce the charges
-                    objs[PLAYER].fighter.as_mut().unwrap().inventory[inv_id].charges -= 1;
+                    inventory[inv_id].charges -= 1;
                 } else {
                     // destroy after use, unless it was cancelled for some reason
-                    objs[PLAYER].fighter.as_mut().unwrap().inventory.remove(inv_id);
+                    inventory.remove(inv_id);
+                    game.handles.release(handle);
                 }
             }
             UseResult::UsedAndKept => {} // do nothing
@@ -0,0 +1,152 @@
+/*
+  Object Handles
+
+  An object's place in its floor's list changes whenever something before it
+  is removed, so holding on to that index is asking for trouble. Instead,
+  every object gets a handle when it comes into the game: a slot number, and
+  which generation of that slot it is. When an object is destroyed its slot
+  can be handed out again, but with the next generation, so an old handle
+  never finds the new object.
+
+  Look an object up by its handle right before using it, and let go of the
+  index again straight after.
+ */
+// Import Third-Party
+use serde::{Deserialize, Serialize};
+
+// Import Locally
+use crate::objects::Object;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Handle {
+    pub slot: u32,
+    pub generation: u32,
+}
+
+
+impl Handle {
+    // what an object has before it is handed a real one
+    pub const NONE: Handle = Handle { slot: u32::MAX, generation: 0 };
+}
+
+
+// Hands out handles, and takes them back when objects are destroyed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Handles {
+    // the current generation of each slot
+    generations: Vec<u32>,
+    // slots whose objects were destroyed, ready to be reused
+    free: Vec<u32>,
+}
+
+
+impl Handles {
+    pub fn allocate(&mut self) -> Handle {
+        match self.free.pop() {
+            Some(slot) => Handle { slot, generation: self.generations[slot as usize] },
+            None => {
+                self.generations.push(0);
+                Handle { slot: self.generations.len() as u32 - 1, generation: 0 }
+            }
+        }
+    }
+
+    // give every object that doesn't have a handle yet (and anything it carries) one
+    pub fn allocate_all(&mut self, objects: &mut [Object]) {
+        for object in objects.iter_mut() {
+            if object.handle == Handle::NONE {
+                object.handle = self.allocate();
+            }
+            if let Some(fighter) = object.fighter.as_mut() {
+                self.allocate_all(&mut fighter.inventory);
+            }
+        }
+    }
+
+    // the object with this handle is gone for good
+    pub fn release(&mut self, handle: Handle) {
+        let slot = handle.slot as usize;
+        if slot < self.generations.len() && self.generations[slot] == handle.generation {
+            self.generations[slot] += 1;
+            self.free.push(handle.slot);
+        }
+    }
+}
+
+
+// where the object with this handle is in the list (a floor, or an inventory), if it's there
+pub fn find(objects: &[Object], handle: Handle) -> Option<usize> {
+    objects.iter().position(|object| object.handle == handle)
+}
+
+
+pub fn get(objects: &[Object], handle: Handle) -> Option<&Object> {
+    objects.iter().find(|object| object.handle == handle)
+}
+
+
+pub fn get_mut(objects: &mut [Object], handle: Handle) -> Option<&mut Object> {
+    objects.iter_mut().find(|object| object.handle == handle)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tcod::colors::WHITE;
+    use crate::objects::Fighter;
+
+
+    #[test]
+    fn a_reused_slot_is_a_new_generation() {
+        let mut handles = Handles::default();
+        let first = handles.allocate();
+        let second = handles.allocate();
+        assert_ne!(first, second);
+
+        handles.release(first);
+        let reused = handles.allocate();
+        assert_eq!(reused.slot, first.slot);
+        assert_eq!(reused.generation, first.generation + 1);
+
+        // letting go of a stale handle doesn't free the slot again
+        handles.release(first);
+        assert_ne!(handles.allocate().slot, first.slot);
+    }
+
+
+    #[test]
+    fn an_old_handle_never_finds_the_new_object() {
+        let mut handles = Handles::default();
+        let mut objects = vec![Object::new(1, 1, 'o', "orc", WHITE, true)];
+        handles.allocate_all(&mut objects);
+        let orc = objects[0].handle;
+
+        let gone = objects.remove(0);
+        handles.release(gone.handle);
+        objects.push(Object::new(2, 2, 'T', "troll", WHITE, true));
+        handles.allocate_all(&mut objects);
+
+        assert!(find(&objects, orc).is_none());
+        assert_eq!(get(&objects, objects[0].handle).unwrap().name, "troll");
+        get_mut(&mut objects, Handle { slot: orc.slot, generation: orc.generation + 1 }).unwrap().x = 5;
+        assert_eq!(objects[0].x, 5);
+    }
+
+
+    #[test]
+    fn what_an_object_carries_gets_handles_too() {
+        let mut handles = Handles::default();
+        let mut player = Object::new(0, 0, '@', "you", WHITE, true);
+        let mut fighter = Fighter::new(30, 2, 5, 0, false);
+        fighter.inventory.push(Object::new(0, 0, '/', "sword", WHITE, false));
+        player.fighter = Some(fighter);
+        let mut objects = vec![player];
+        handles.allocate_all(&mut objects);
+
+        let carried = objects[0].fighter.as_ref().unwrap().inventory[0].handle;
+        assert_ne!(carried, Handle::NONE);
+        assert_ne!(carried, objects[0].handle);
+    }
+}
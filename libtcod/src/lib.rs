@@ -0,0 +1,30 @@
+/*
+February Second
+
+  The game itself, with no window: the Game and its objects, map-gen and turn
+  processing. The tcod frontend (main.rs, with its menus, replays and drawing)
+  drives it through the engine's Ui trait, and so can anything else (tests, tools).
+ */
+pub mod ai_algos;
+pub mod constants;
+pub mod dijkstra;
+pub mod engine;
+pub mod equipment;
+pub mod handles;
+pub mod loot_table;
+pub mod magic;
+pub mod map;
+pub mod map_algos;
+pub mod moves;
+pub mod noise;
+pub mod npc_table;
+pub mod objects;
+pub mod pathfinding;
+pub mod player;
+pub mod profiles;
+pub mod rng;
+pub mod savegame;
+pub mod spatial_index;
+pub mod transition;
+pub mod utils;
+pub mod vision;
@@ -7,6 +7,7 @@ use tcod::colors::*;
 use crate::objects::Equipment;
 use crate::objects::Item;
 use crate::objects::Object;
+use crate::rng::GameRng;
 use crate::objects::Slot;
 
 
@@ -26,6 +27,8 @@ struct Loot {
 }
 
 impl Loot {
+    // one argument per column of the table
+    #[allow(clippy::too_many_arguments)]
     fn new(
         symbol: char,
         name: &str,
@@ -42,17 +45,17 @@ impl Loot {
         assert!(min_level < max_level);
 
         Loot {
-            symbol: symbol,
+            symbol,
             name: name.to_string(),
-            color: color,
-            item_type: item_type,
-            slot: slot,
-            hp_bonus: hp_bonus,
-            defense_bonus: defense_bonus,
-            attack_bonus: attack_bonus,
-            min_level: min_level,
-            max_level: max_level,
-            weight: weight,
+            color,
+            item_type,
+            slot,
+            hp_bonus,
+            defense_bonus,
+            attack_bonus,
+            min_level,
+            max_level,
+            weight,
         }
     }
 
@@ -69,7 +72,7 @@ impl Loot {
                 power_bonus: self.attack_bonus,
             });
         }
-        return obj;
+        obj
     }
 }
 
@@ -89,7 +92,7 @@ impl Loot {
 
 */
 fn loot_table() -> Vec<Loot> {
-    return vec![
+    vec![
         // weapons
         Loot::new('/', "sword breaker", DARK_BLUE, Item::Sword, Slot::Hand, 0, 2, 4, 17, 99, 10),
         Loot::new('/', "long sword", BLUE, Item::Sword, Slot::Hand, 0, 0, 5, 13, 99, 10),
@@ -101,31 +104,31 @@ fn loot_table() -> Vec<Loot> {
         Loot::new('[', "shield", ORANGE, Item::Shield, Slot::Hand, 0, 3, 0, 10, 19, 10),
         Loot::new('[', "buckler", LIGHT_ORANGE, Item::Shield, Slot::Hand, 0, 2, 0, 7, 16, 12),
         // potions
-        Loot::new((173 as u8) as char, "healing potion", VIOLET, Item::HealPot, Slot::Head, 0, 0, 0, -99, 99, 35),
+        Loot::new(173_u8 as char, "healing potion", VIOLET, Item::HealPot, Slot::Head, 0, 0, 0, -99, 99, 35),
         // scrolls
         Loot::new('~', "scroll of lightning bolt", LIGHT_BLUE, Item::LightningScroll, Slot::Head, 0, 0, 0, 3, 99, 25),
         Loot::new('~', "scroll of fireball", RED, Item::FireballScroll, Slot::Head, 0, 0, 0, 1, 99, 25),
         Loot::new('~', "scroll of confusion", LIGHT_GREEN, Item::ConfuseScroll, Slot::Head, 0, 0, 0, 0, 99, 45),
         // books
         // Loot::new((247 as u8) as char, "book of something", LIGHT_GREEN, Item::ConfuseScroll, Slot::Head, 0, 0, 0, 0, 99, 10),
-    ];
+    ]
 }
 
 
 fn trim_loot_by_level(level: i32) -> Vec<Loot> {
-    return loot_table()
+    loot_table()
            .iter()
            .filter(|&row| level >= row.min_level && level <= row.max_level)
            .cloned()
-           .collect::<Vec<Loot>>();
+           .collect::<Vec<Loot>>()
 }
 
 
 // TODO: Convert to generate multiple items
-pub fn generate_floor_item(level: i32) -> Object {
+pub fn generate_floor_item(level: i32, rng: &mut GameRng) -> Object {
     // find all items possible on a given floor, and their weights
     let table: Vec<Loot> = trim_loot_by_level(level);
-    assert!(table.len() > 0);
+    assert!(!table.is_empty());
 
     // do the weighted random chance thing
     let mut chances = vec![];
@@ -135,6 +138,15 @@ pub fn generate_floor_item(level: i32) -> Object {
 
     let choices = WeightedChoice::new(&mut chances);
 
-    return table[choices.ind_sample(&mut rand::thread_rng())].generate();
+    table[choices.ind_sample(rng)].generate()
 }
 
+
+
+// A particular item, by name (say, one a prefab room insists on).
+pub fn item_by_name(name: &str) -> Option<Object> {
+    loot_table()
+           .iter()
+           .find(|row| row.name == name)
+           .map(|row| row.generate())
+}
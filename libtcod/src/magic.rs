@@ -4,20 +4,20 @@
  */
 // Import Third-Party
 use tcod::colors::*;
-use tcod::input::{self, Event};
 
 // Import Locally
 use crate::constants::*;
-use crate::menus::Tcod;
+use crate::engine::Ui;
+use crate::handles::find;
+use crate::handles::Handle;
 use crate::objects::Ai;
 use crate::objects::Game;
 use crate::objects::Object;
 use crate::objects::UseResult;
-use crate::ui::render_all;
 
 
 // find closest enemy, up to a maximum range, and in the player's FOV
-fn closest_npc(tcod: &Tcod, objects: &[Object], max_range: i32) -> Option<usize> {
+fn closest_npc(game: &Game, objects: &[Object], max_range: i32) -> Option<Handle> {
     let mut closest_enemy = None;
     let mut closest_dist = (max_range + 1) as f32; // start with (slightly more than) maximum range
 
@@ -25,13 +25,13 @@ fn closest_npc(tcod: &Tcod, objects: &[Object], max_range: i32) -> Option<usize>
         if (id != PLAYER)
             && object.fighter.is_some()
             && object.ai.is_some()
-            && tcod.fov.is_in_fov(object.x, object.y)
+            && game.fov.is_in_fov(object.x, object.y)
         {
             // calculate distance between this object and the player
             let dist = objects[PLAYER].distance_to(object);
             if dist < closest_dist {
                 // it's closer, so remember it
-                closest_enemy = Some(id);
+                closest_enemy = Some(object.handle);
                 closest_dist = dist;
             }
         }
@@ -43,18 +43,18 @@ fn closest_npc(tcod: &Tcod, objects: &[Object], max_range: i32) -> Option<usize>
 // TODO: I'd like to be able to tab-select too. And auto-select closest.
 // returns a clicked npc inside FOV up to a range, or None if right-clicked
 pub fn target_npc(
-    tcod: &mut Tcod,
+    ui: &mut dyn Ui,
     game: &mut Game,
     objects: &[Object],
     max_range: Option<f32>,
-) -> Option<usize> {
+) -> Option<Handle> {
     loop {
-        match target_tile(tcod, game, objects, max_range) {
+        match ui.target_tile(game, objects, max_range) {
             Some((x, y)) => {
                 // return the first clicked npc, otherwise continue looping
                 for (id, obj) in objects.iter().enumerate() {
                     if obj.pos() == (x, y) && obj.fighter.is_some() && id != PLAYER {
-                        return Some(id);
+                        return Some(obj.handle);
                     }
                 }
             }
@@ -64,48 +64,10 @@ pub fn target_npc(
 }
 
 
-// Return the position of a tile left-clicked in player's FOV (optionally in a range),
-// or (None, None) if right-clicked.
-pub fn target_tile(
-    tcod: &mut Tcod,
-    game: &mut Game,
-    objects: &[Object],
-    max_range: Option<f32>,
-) -> Option<(i32, i32)> {
-    use tcod::input::KeyCode::Escape;
-    loop {
-        // render the screen. this erases the inventory and shows the names of
-        // objects under the mouse.
-        tcod.root.flush();
-        let event = input::check_for_event(input::KEY_PRESS | input::MOUSE).map(|e| e.1);
-        match event {
-            Some(Event::Mouse(m)) => tcod.mouse = m,
-            Some(Event::Key(k)) => tcod.key = k,
-            None => tcod.key = Default::default(),
-        }
-        render_all(tcod, game, objects, false);
-
-        let (x, y) = (tcod.mouse.cx as i32, tcod.mouse.cy as i32);
-
-        // accept the target if the player clicked in FOV, and in case a range
-        // is specified, if it's in that range
-        let in_fov = (x < MAP_WIDTH) && (y < MAP_HEIGHT) && tcod.fov.is_in_fov(x, y);
-        let in_range = max_range.map_or(true, |range| objects[PLAYER].distance(x, y) <= range);
-        if tcod.mouse.lbutton_pressed && in_fov && in_range {
-            return Some((x, y));
-        }
-
-        if tcod.mouse.rbutton_pressed || tcod.key.code == Escape {
-            return None; // cancel if the player right-clicked or pressed Escape
-        }
-    }
-}
-
-
 // NOTE: Player Only
-pub fn cast_lightning(_inv_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) -> UseResult {
+pub fn cast_lightning(_item: Handle, _ui: &mut dyn Ui, game: &mut Game, objects: &mut [Object]) -> UseResult {
     // find closest enemy (inside a maximum range and damage it)
-    let npc_id = closest_npc(tcod, objects, LIGHTNING_RANGE);
+    let npc_id = closest_npc(game, objects, LIGHTNING_RANGE).and_then(|npc| find(objects, npc));
     if let Some(npc_id) = npc_id {
         // zap it!
         game.messages.add(
@@ -116,23 +78,24 @@ pub fn cast_lightning(_inv_id: usize, tcod: &mut Tcod, game: &mut Game, objects:
             ),
             LIGHT_BLUE,
         );
+        game.make_noise(objects[npc_id].pos(), NOISE_THUNDER);
         let xp = objects[npc_id].take_damage(LIGHTNING_DAMAGE, game);
         if xp > 0 {
             objects[PLAYER].fighter.as_mut().unwrap().kill_rewards(xp, game.lvl as i32);
         }
-        return UseResult::UsedUp;
+        UseResult::UsedUp
     } else {
         // no enemy found within maximum range
         game.messages.add("No enemy is close enough to strike.", RED);
-        return UseResult::Cancelled;
+        UseResult::Cancelled
     }
 }
 
 
 // NOTE: Player Only
 pub fn cast_fireball(
-    _inv_id: usize,
-    tcod: &mut Tcod,
+    _item: Handle,
+    ui: &mut dyn Ui,
     game: &mut Game,
     objects: &mut [Object],
 ) -> UseResult {
@@ -141,7 +104,7 @@ pub fn cast_fireball(
         "Left-click a target tile for the fireball, or right-click to cancel.",
         LIGHT_CYAN,
     );
-    let (x, y) = match target_tile(tcod, game, objects, None) {
+    let (x, y) = match ui.target_tile(game, objects, None) {
         Some(tile_pos) => tile_pos,
         None => return UseResult::Cancelled,
     };
@@ -152,6 +115,7 @@ pub fn cast_fireball(
         ),
         ORANGE,
     );
+    game.make_noise((x, y), NOISE_FIREBALL);
 
     let mut xp_to_gain = 0;
     for (id, obj) in objects.iter_mut().enumerate() {
@@ -164,11 +128,9 @@ pub fn cast_fireball(
                 ORANGE,
             );
             let xp = obj.take_damage(FIREBALL_DAMAGE, game);
-            if xp > 0 {
-                if id != PLAYER {
-                    // Don't reward the player for burning themself.
-                    xp_to_gain += xp;
-                }
+            // Don't reward the player for burning themself.
+            if xp > 0 && id != PLAYER {
+                xp_to_gain += xp;
             }
         }
     }
@@ -180,8 +142,8 @@ pub fn cast_fireball(
 
 // NOTE: Player Only
 pub fn cast_heal(
-    _inv_id: usize,
-    _tcod: &mut Tcod,
+    _item: Handle,
+    _ui: &mut dyn Ui,
     game: &mut Game,
     objects: &mut [Object],
 ) -> UseResult {
@@ -202,13 +164,13 @@ pub fn cast_heal(
 
 
 // NOTE: Player Only
-pub fn cast_confuse(_inv_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) -> UseResult {
+pub fn cast_confuse(_item: Handle, ui: &mut dyn Ui, game: &mut Game, objects: &mut [Object]) -> UseResult {
     // ask the player for a target to confuse
     game.messages.add(
         "Left-click an enemy to confuse it, or right-click to cancel.",
         LIGHT_CYAN,
     );
-    let npc_id = target_npc(tcod, game, objects, Some(CONFUSE_RANGE as f32));
+    let npc_id = target_npc(ui, game, objects, Some(CONFUSE_RANGE as f32)).and_then(|npc| find(objects, npc));
     if let Some(npc_id) = npc_id {
         let old_ai = objects[npc_id].ai.take().unwrap_or(Ai::Basic);
         // replace the npc's AI with a "confused" one; after
@@ -2,373 +2,172 @@
 February Second
  */
 // Import Std Libs
-use std::error::Error;
-use std::fs::File;
-use std::io::{Read, Write};
+use std::cmp;
+use std::path::Path;
 
 // Import Third-Party
 use tcod::colors::*;
 use tcod::console::*;
 use tcod::input::{self, Event, Key};
-use tcod::map::{Map as FovMap};
 //use tcod::tileset::{load_tilesheet};
 
 // Import Locally
-mod ai_algos;
-mod constants;
-mod equipment;
-mod loot_table;
-mod magic;
-mod map;
-mod map_algos;
 mod menus;
-mod moves;
-mod npc_table;
-mod objects;
-mod player;
-mod transition;
+mod replay;
 mod ui;
-mod utils;
-use ai_algos::ai_take_turn;
-use constants::AUTHOR_LINE;
-use constants::FONT_IMG;
-use constants::GAME_TITLE;
-use constants::KARMA_TO_ASCEND;
-use constants::LIMIT_FPS;
-use constants::MAP_HEIGHT;
-use constants::MAP_WIDTH;
-use constants::NUM_LVLS;
-use constants::PANEL_HEIGHT;
-use constants::PLAYER;
-use constants::SAVE_FILE;
-use constants::SCREEN_HEIGHT;
-use constants::SCREEN_WIDTH;
-use equipment::drop_item;
-use equipment::pick_item_up;
-use equipment::player_use_item;
-use map::Map;
+use feb2::constants::AUTHOR_LINE;
+use feb2::constants::AUTOSAVE_TURNS;
+use feb2::constants::SLOT_NAME_MAX;
+use feb2::constants::FONT_IMG;
+use feb2::constants::GAME_TITLE;
+use feb2::constants::LEVELS_FILE;
+use feb2::constants::LIMIT_FPS;
+use feb2::constants::MAP_HEIGHT;
+use feb2::constants::MAP_WIDTH;
+use feb2::constants::NUM_LVLS;
+use feb2::constants::PANEL_HEIGHT;
+use feb2::constants::PLAYER;
+use feb2::constants::PREFAB_DIR;
+use feb2::constants::REPLAY_DEFAULT_SPEED;
+use feb2::constants::REPLAY_SPEEDS;
+use feb2::constants::SAVE_DIR;
+use feb2::constants::SCREEN_HEIGHT;
+use feb2::constants::SCREEN_WIDTH;
+use feb2::engine::new_game;
+use feb2::engine::reincarnate;
+use feb2::engine::take_turn;
+use feb2::map_algos::prefabs::init_prefabs;
+use menus::character_screen;
 use menus::help_menu;
-use menus::inventory_menu;
+use menus::load_game_menu;
 use menus::menu;
 use menus::msgbox;
+use menus::replay_menu;
+use menus::text_input;
 use menus::Tcod;
-use moves::player_move_or_attack;
-use moves::PlayerAction;
-use objects::Fighter;
-use objects::Game;
-use objects::Object;
-use player::character_screen;
-use player::level_up;
-use player::reincarnate_reset;
+use feb2::moves::Command;
+use feb2::moves::PlayerAction;
+use feb2::objects::Game;
+use feb2::objects::Object;
+use feb2::player::level_up;
+use feb2::profiles::init_profiles;
+use replay::load_replay;
+use replay::next_command;
+use replay::resume_recording;
+use replay::start_recording;
+use replay::InputLog;
+use feb2::rng::parse_seed;
+use feb2::rng::random_seed;
+use feb2::savegame::list_slots;
+use feb2::savegame::load_game;
+use feb2::savegame::save_game;
+use feb2::savegame::slot_exists;
+use feb2::savegame::valid_slot_name;
 use ui::render_all;
 
 
-fn change_player_level(objects: &mut Vec<Vec<Object>>, from_lvl: usize, to_lvl: usize) {
-    let player = objects[from_lvl].swap_remove(PLAYER);
-
-    let mut tmp: Vec<Object> = vec![player];
-    tmp.extend(objects[to_lvl].drain(0..));
-    objects[to_lvl] = tmp;
-}
-
-
-fn go_up_level(tcod: &mut Tcod, game: &mut Game, all_objects: &mut Vec<Vec<Object>>) -> bool {
-    if game.lvl == (NUM_LVLS as usize - 1) {
-        if all_objects[game.lvl][PLAYER].fighter.as_ref().unwrap().karma >= KARMA_TO_ASCEND {
-            game.messages.add(
-                "You ascend from Purgatory.",
-                RED,
-            );
-            return false;
-        } else {
-            game.messages.add(
-                "Your karma is too low to leave Purgatory.",
-                RED,
-            );
-            return false;
-        }
-    } else {
-        game.messages.add(
-            "You ascend higher into Purgatory...",
-            RED,
-        );
-        game.lvl += 1;
-        change_player_level(all_objects, game.lvl - 1, game.lvl);
-        all_objects[game.lvl][PLAYER].x = game.down_stairs[game.lvl].0;
-        all_objects[game.lvl][PLAYER].y = game.down_stairs[game.lvl].1;
-        initialise_fov(tcod, &game.map());
-    }
-    return true;
-}
-
-
-fn go_down_level(tcod: &mut Tcod, game: &mut Game, all_objects: &mut Vec<Vec<Object>>) -> bool {
-    if game.lvl == 0 {
-        game.messages.add(
-            "There is no going lower than where you are.",
-            RED,
-        );
-        return false;
-    } else {
-        game.messages.add(
-            "You descend back down into Purgatory.",
-            RED,
-        );
-        game.lvl -= 1;
-        change_player_level(all_objects, game.lvl + 1, game.lvl);
-        all_objects[game.lvl][PLAYER].x = game.up_stairs[game.lvl].0;
-        all_objects[game.lvl][PLAYER].y = game.up_stairs[game.lvl].1;
-        initialise_fov(tcod, &game.map());
-    }
-    return true;
-}
-
-
 // TODO: Key "m" should open a scrollable messages window.
 // TODO: Fullscreen isn't working.
-fn handle_keys(tcod: &mut Tcod, game: &mut Game, all_objects: &mut Vec<Vec<Object>>) -> PlayerAction {
+fn key_to_command(key: Key) -> Option<Command> {
     use tcod::input::KeyCode::*;
-    use PlayerAction::*;
+    use Command::*;
 
-    let objects = &mut all_objects[game.lvl];
-    let player_alive = objects[PLAYER].alive;
-    match (tcod.key, tcod.key.text(), player_alive) {
+    match (key, key.text()) {
         // movement keys
-        (Key { code: Up, .. }, _, true) => {
-            return player_move_or_attack(0, -1, game, objects);
-        }
-        (Key { code: Down, .. }, _, true) => {
-            return player_move_or_attack(0, 1, game, objects);
-        }
-        (Key { code: Left, .. }, _, true) => {
-            return player_move_or_attack(-1, 0, game, objects);
-        }
-        (Key { code: Right, .. }, _, true) => {
-            return player_move_or_attack(1, 0, game, objects);
-        }
+        (Key { code: Up, .. }, _) => Some(Move(0, -1)),
+        (Key { code: Down, .. }, _) => Some(Move(0, 1)),
+        (Key { code: Left, .. }, _) => Some(Move(-1, 0)),
+        (Key { code: Right, .. }, _) => Some(Move(1, 0)),
         // numpad keys
-        (Key { code: NumPad1, .. }, _, true) | (Key { code: End, .. }, _, true) => {
-            return player_move_or_attack(-1, 1, game, objects);
-        }
-        (Key { code: NumPad2, .. }, _, true) => {
-            return player_move_or_attack(0, 1, game, objects);
-        }
-        (Key { code: NumPad3, .. }, _, true) | (Key { code: PageDown, .. }, _, true) => {
-            return player_move_or_attack(1, 1, game, objects);
-        }
-        (Key { code: NumPad4, .. }, _, true) => {
-            return player_move_or_attack(-1, 0, game, objects);
-        }
-        (Key { code: NumPad5, .. }, _, true) => {
-            return TookTurn;
-        }
-        (Key { code: NumPad6, .. }, _, true) => {
-            return player_move_or_attack(1, 0, game, objects);
-        }
-        (Key { code: NumPad7, .. }, _, true) | (Key { code: Home, .. }, _, true) => {
-            return player_move_or_attack(-1, -1, game, objects);
-        }
-        (Key { code: NumPad8, .. }, _, true) => {
-            return player_move_or_attack(0, -1, game, objects);
-        }
-        (Key { code: NumPad9, .. }, _, true) | (Key { code: PageUp, .. }, _, true) => {
-            return player_move_or_attack(1, -1, game, objects);
-        }
-
-        // go up stairs, if the player is on them
-        (Key { code: Text, .. }, ">", true) => {
-            let player_on_stairs = objects
-                .iter()
-                .any(|object| object.pos() == objects[PLAYER].pos() && object.name == "up-stairs");
-            if player_on_stairs {
-                let success: bool = go_up_level(tcod, game, all_objects);
-                if success {
-                    // TODO: If game.level >= NUM_LVLS: return WinExit
-                    return TookTurn;
-                } else {
-                    return DidntTakeTurn;
-                }
-            }
-            return DidntTakeTurn;
-        }
-
-        // go down stairs, if the player is on them
-        (Key { code: Text, .. }, "<", true) => {
-            let player_on_stairs = objects
-                .iter()
-                .any(|object| object.pos() == objects[PLAYER].pos() && object.name == "down-stairs");
-            if player_on_stairs {
-                let success: bool = go_down_level(tcod, game, all_objects);
-                if success {
-                    return TookTurn;
-                } else {
-                    return DidntTakeTurn;
-                }
-            }
-            return DidntTakeTurn;
-        }
+        (Key { code: NumPad1, .. }, _) | (Key { code: End, .. }, _) => Some(Move(-1, 1)),
+        (Key { code: NumPad2, .. }, _) => Some(Move(0, 1)),
+        (Key { code: NumPad3, .. }, _) | (Key { code: PageDown, .. }, _) => Some(Move(1, 1)),
+        (Key { code: NumPad4, .. }, _) => Some(Move(-1, 0)),
+        (Key { code: NumPad5, .. }, _) => Some(Wait),
+        (Key { code: NumPad6, .. }, _) => Some(Move(1, 0)),
+        (Key { code: NumPad7, .. }, _) | (Key { code: Home, .. }, _) => Some(Move(-1, -1)),
+        (Key { code: NumPad8, .. }, _) => Some(Move(0, -1)),
+        (Key { code: NumPad9, .. }, _) | (Key { code: PageUp, .. }, _) => Some(Move(1, -1)),
+
+        (Key { code: Text, .. }, ">") => Some(GoUp),
+        (Key { code: Text, .. }, "<") => Some(GoDown),
+        (Key { code: Text, .. }, "c") => Some(CharacterScreen),
+        (Key { code: Text, .. }, "i") => Some(Inventory),
+        (Key { code: Text, .. }, "s") => Some(Sneak),
+        (Key { code: Text, .. }, "g") => Some(PickUp),
+        (Key { code: Text, .. }, "d") => Some(Drop),
+        (Key { code: Text, .. }, "?") => Some(Help),
+        (Key { code: Escape, .. }, _) => Some(Exit),
+
+        (_, _) => None,
+    }
+}
 
-        (Key { code: Text, .. }, "c", true) => {
-            let player = &objects[PLAYER];
-            character_screen(tcod, player, game);
-            return DidntTakeTurn;
-        }
 
-        // show the inventory
-        (Key { code: Text, .. }, "i", true) => {
-            let player = &mut objects[PLAYER];
-            if let Some(fighter) = player.fighter.as_mut() {
-                // show the inventory: if an item is selected, use it
-                let inventory_index = inventory_menu(
-                    &fighter.inventory,
-                    "Press the key next to an item to use it, or any other to cancel.\n",
-                    &mut tcod.root,
-                );
-                if let Some(inventory_index) = inventory_index {
-                    player_use_item(inventory_index, tcod, game, objects);
-                }
-            }
-            return MenuAction;
-        }
+fn handle_keys(tcod: &mut Tcod,
+               game: &mut Game,
+               all_objects: &mut [Vec<Object>],
+               start_objects: &mut [Vec<Object>]
+              ) -> PlayerAction {
+    use PlayerAction::*;
 
-        // Pick up an item
-        (Key { code: Text, .. }, "g", true) => {
-            let item_id = objects
-                .iter()
-                .position(|object| object.pos() == objects[PLAYER].pos() && object.item.is_some());
-            if let Some(item_id) = item_id {
-                pick_item_up(item_id, PLAYER, &mut game.messages, objects);
-                return TookTurn;
-            } else {
-                return DidntTakeTurn;
-            }
-        }
+    // the key the player pressed, or the next one from the replay
+    let pressed = key_to_command(tcod.key);
+    let command = match next_command(tcod, game, pressed) {
+        Some(command) => command,
+        None => return DidntTakeTurn,
+    };
 
-        // show the inventory; if an item is selected, drop it
-        (Key { code: Text, .. }, "d", true) => {
-            let player = &objects[PLAYER];
-            if let Some(fighter) = player.fighter.as_ref() {
-                let inventory_index = inventory_menu(
-                    &fighter.inventory,
-                    "Press the key next to an item to drop it, or any other to cancel.\n'",
-                    &mut tcod.root,
-                );
-                if let Some(inventory_index) = inventory_index {
-                    drop_item(inventory_index, PLAYER, &mut game.messages, objects);
-                }
-            }
-            return DidntTakeTurn;
+    let player_alive = all_objects[game.lvl][PLAYER].alive;
+    match (command, player_alive) {
+        (Command::CharacterScreen, true) => {
+            character_screen(tcod, &all_objects[game.lvl][PLAYER], game);
+            DidntTakeTurn
         }
 
-        // Escape to exit game
-        (Key { code: Escape, .. }, _, _) => { return Exit; }
-
         // Help Menu
-        (Key { code: Text, .. }, "?", true) => {
+        (Command::Help, true) => {
             help_menu(tcod);
-            return DidntTakeTurn;
+            DidntTakeTurn
         }
 
-        (_, _, _) => { return DidntTakeTurn; }
-    };
-}
-
+        // Escape to exit game
+        (Command::Exit, _) => Exit,
 
-fn initialise_fov(tcod: &mut Tcod, map: &Map) {
-    // create the FOV map, according to the generated map
-    for y in 0..MAP_HEIGHT {
-        for x in 0..MAP_WIDTH {
-            tcod.fov.set(
-                x,
-                y,
-                !map[x as usize][y as usize].block_sight,
-                !map[x as usize][y as usize].blocked,
-            );
-        }
+        // everything else is up to the game itself
+        (_, _) => take_turn(tcod, game, all_objects, start_objects, command),
     }
-
-    // unexplored areas start black (which is the default background color)
-    tcod.con.clear();
-}
-
-
-// NOTE: There can currently be only one save game at a time.
-//       But the save games are human-readable, storable, and editable.
-fn save_game(game: &Game,
-             objects: &Vec<Vec<Object>>,
-             start_objects: &Vec<Vec<Object>>
-            ) -> Result<(), Box<dyn Error>> {
-    let save_data = serde_json::to_string(&(game, objects, start_objects))?;
-    let mut file = File::create(SAVE_FILE)?;
-    file.write_all(save_data.as_bytes())?;
-    Ok(())
 }
 
 
-fn load_game() -> Result<(Game, Vec<Vec<Object>>, Vec<Vec<Object>>), Box<dyn Error>> {
-    let mut json_save_state = String::new();
-    let mut file = File::open(SAVE_FILE)?;
-    file.read_to_string(&mut json_save_state)?;
-    let result = serde_json::from_str::<(Game, Vec<Vec<Object>>, Vec<Vec<Object>>)>(&json_save_state)?;
-    Ok(result)
-}
-
-
-fn reincarnate(game: &mut Game,
-               objects: &mut Vec<Vec<Object>>,
-               start_objects: &Vec<Vec<Object>>) {
-    // copy over objects
-    let mut player: Object = objects[game.lvl][0].clone();
-    reincarnate_reset(&mut player);
-    (player.x, player.y) = game.down_stairs[0];
-    *objects = start_objects.clone();
-    objects[0][PLAYER] = player;
-
-    // update game
-    game.lvl = 0;
-    game.day += 1;
-    game.turn += 1;
-}
-
-
-fn new_game(tcod: &mut Tcod) -> (Game, Vec<Vec<Object>>, Vec<Vec<Object>>) {
-    // create object representing the player
-    let mut player = Object::new(0, 0, '@', "you", WHITE, true);
-    player.alive = true;
-    player.fighter = Some(Fighter::new(100, 2, 3, 0, false));
-
-    // NOTE: This is here to ensure the first rebirth matches all following reincarnations
-    reincarnate_reset(&mut player);
-
-    // the list of objects in the game, by floor
-    let mut objects: Vec<Vec<Object>> = vec![vec![]; NUM_LVLS as usize];
-    objects[0].push(player);
-
-    let mut game = Game::new(&mut objects);
-
-    initialise_fov(tcod, &game.map());
-
-    // a welcome message
-    game.messages.add(
-        "You wake up in Purgatory. Ascend from this place, or be stuck here forever.",
-        RED,
-    );
-
-    // save off the starting position of the game
-    let starts = objects.clone();
+// Keys that steer a replay: '+' and '-' change the speed, space pauses, Escape stops watching.
+fn replay_controls(tcod: &mut Tcod) -> bool {
+    use tcod::input::KeyCode::*;
 
-    return (game, objects, starts);
+    let key = tcod.key;
+    if let InputLog::Playing { speed, paused, .. } = &mut tcod.log {
+        match (key, key.text()) {
+            (Key { code: Escape, .. }, _) => return true,
+            (Key { code: Spacebar, .. }, _) | (Key { code: Text, .. }, " ") => *paused = !*paused,
+            (Key { code: Text, .. }, "+") => *speed = cmp::min(*speed + 1, REPLAY_SPEEDS.len() - 1),
+            (Key { code: Text, .. }, "-") => *speed = speed.saturating_sub(1),
+            _ => {}
+        }
+        tcod::system::set_fps(REPLAY_SPEEDS[*speed]);
+    }
+    false
 }
 
 
-fn play_game(tcod: &mut Tcod, game: &mut Game,
+fn play_game(tcod: &mut Tcod, slot: &str, game: &mut Game,
             all_objects: &mut Vec<Vec<Object>>,
             start_objects: &mut Vec<Vec<Object>>
             ) {
-    // force FOV "recompute" first time through the game loop
-    let mut previous_player_position = (-1, -1);
     let mut last_action: PlayerAction = PlayerAction::DidntTakeTurn;
 
+    // watching a replay must never overwrite the real save
+    let replaying: bool = tcod.log.is_playing();
+
     // the game loop!
     while !tcod.root.window_closed() {
         // clear the off-screen console
@@ -379,24 +178,34 @@ fn play_game(tcod: &mut Tcod, game: &mut Game,
             Some((_, Event::Key(k))) => tcod.key = k,
             _ => tcod.key = Default::default(),
         }
+        if replaying && replay_controls(tcod) {
+            break;
+        }
 
         // render the screen
-        let lvl: usize = game.lvl as usize;
-        let fov_recompute = previous_player_position != (all_objects[lvl][PLAYER].pos());
-        render_all(tcod, game, &mut all_objects[lvl], fov_recompute);
+        let lvl: usize = game.lvl;
+        render_all(tcod, game, &all_objects[lvl]);
 
         // handle death and reincarnation
         if !all_objects[lvl][PLAYER].alive {
             last_action = PlayerAction::DidntTakeTurn;
-            character_screen(tcod, &all_objects[lvl][PLAYER], game);
+            if !replaying {
+                character_screen(tcod, &all_objects[lvl][PLAYER], game);
+            }
             reincarnate(game, all_objects, start_objects);
-            initialise_fov(tcod, &game.map());
             tcod.root.flush();
             continue;
         }
 
         tcod.root.flush();
 
+        // the replay ran out (or went wrong)
+        if let InputLog::Finished(reason) = &tcod.log {
+            let msg = format!("\n{}\n", reason);
+            msgbox(&msg, 32, &mut tcod.root);
+            break;
+        }
+
         if last_action == PlayerAction::MenuAction {
             last_action = PlayerAction::DidntTakeTurn;
             continue;
@@ -405,22 +214,25 @@ fn play_game(tcod: &mut Tcod, game: &mut Game,
         // level up if needed
         level_up(tcod, game, &mut all_objects[lvl]);
 
-        // handle keys and exit game if needed
-        previous_player_position = all_objects[lvl][PLAYER].pos();
-        let player_action = handle_keys(tcod, game, all_objects);
-        if player_action == PlayerAction::Exit {
-            save_game(game, all_objects, start_objects).unwrap();
+        // handle keys (the game takes its turn from there) and exit game if needed
+        let player_action = handle_keys(tcod, game, all_objects, start_objects);
+        if player_action == PlayerAction::Exit && replaying {
+            // the player saved and quit here, then came back later
+            last_action = PlayerAction::DidntTakeTurn;
+            continue;
+        } else if player_action == PlayerAction::Exit {
+            if let Err(e) = save_game(Path::new(SAVE_DIR), slot, game, all_objects, start_objects) {
+                let save_err = format!("ERROR Saving Game\n\n{}\n", e);
+                msgbox(&save_err, 32, &mut tcod.root);
+            }
             break;
-        } else if player_action == PlayerAction::TookTurn {
-            game.turn += 1;
         }
 
-        // let npcs take their turn
-        if player_action == PlayerAction::TookTurn && all_objects[lvl][PLAYER].alive {
-            for id in 0..all_objects[lvl].len() {
-                if id != PLAYER && all_objects[lvl][id].ai.is_some() {
-                    ai_take_turn(id, tcod, game, &mut all_objects[lvl]);
-                }
+        // autosave whenever the player changes floors, and every so often
+        let autosave_due = player_action == PlayerAction::TookTurn && game.turn.is_multiple_of(AUTOSAVE_TURNS);
+        if !replaying && (game.lvl != lvl || autosave_due) {
+            if let Err(e) = save_game(Path::new(SAVE_DIR), slot, game, all_objects, start_objects) {
+                game.messages.add(format!("Autosave failed: {}", e), RED);
             }
         }
 
@@ -429,29 +241,8 @@ fn play_game(tcod: &mut Tcod, game: &mut Game,
 }
 
 
-fn load_version_equals(tcod: &mut Tcod, game: &Game) -> bool {
-    let this_version: String = env!("CARGO_PKG_VERSION").to_string();
-
-    if !game.version.eq(&this_version) {
-        let mut load_err: String = "ERROR Loading Game\n\nCannot load save game, because it is the wrong version.\n".to_string();
-        load_err.push_str("\nsave game version: ");
-        load_err.push_str(&game.version);
-        load_err.push_str("\nload game version: ");
-        load_err.push_str(&this_version);
-
-        msgbox(&load_err, 32, &mut tcod.root);
-
-        return false;
-    } else {
-        return true;
-    }
-}
-
-
 fn main_menu(tcod: &mut Tcod) {
-    let img = tcod::image::Image::from_file("menu_background.png")
-        .ok()
-        .expect("Background image not found");
+    let img = tcod::image::Image::from_file("menu_background.png").expect("Background image not found");
 
     while !tcod.root.window_closed() {
         // show the background image, at twice the regular console resolution
@@ -474,33 +265,47 @@ fn main_menu(tcod: &mut Tcod) {
         );
 
         // show options and wait for the player's choice
-        let choices = &["Play a new game", "Continue last game", "Quit"];
+        let choices = &["Play a new game", "Continue last game", "Load a saved game", "Watch a replay", "Quit"];
         let choice = menu("", choices, 24, &mut tcod.root);
 
         match choice {
             Some(0) => {
                 // new game
-                let (mut game, mut objects, mut start_objects) = new_game(tcod);
-                play_game(tcod, &mut game, &mut objects, &mut start_objects);
+                if let Some(slot) = choose_new_slot(tcod) {
+                    let seed = match text_input("Seed (leave blank for a random one):", "", 20, 40, &mut tcod.root) {
+                        Some(text) if !text.is_empty() => parse_seed(&text),
+                        Some(_) => random_seed(),
+                        None => continue,
+                    };
+                    let (mut game, mut objects, mut start_objects) = new_game(seed);
+                    match start_recording(Path::new(SAVE_DIR), &slot, seed) {
+                        Ok(log) => tcod.log = log,
+                        Err(e) => game.messages.add(format!("Not recording a replay: {}", e), RED),
+                    }
+                    play_game(tcod, &slot, &mut game, &mut objects, &mut start_objects);
+                    tcod.log = InputLog::Off;
+                }
             }
             Some(1) => {
-                // load game
-                match load_game() {
-                    Ok((mut game, mut objects, mut start_objects)) => {
-                        if !load_version_equals(tcod, &game) {
-                            continue;
-                        } else {
-                            initialise_fov(tcod, &game.map());
-                            play_game(tcod, &mut game, &mut objects, &mut start_objects);
-                        }
-                    }
-                    Err(_e) => {
-                        msgbox("\nNo saved game to load.\n", 24, &mut tcod.root);
-                        continue;
-                    }
+                // continue the most recently played game
+                match list_slots(Path::new(SAVE_DIR)).first() {
+                    Some(latest) => start_saved_game(tcod, &latest.name),
+                    None => msgbox("\nNo saved game to load.\n", 24, &mut tcod.root),
                 }
             }
             Some(2) => {
+                // pick a saved game to load
+                if let Some(slot) = load_game_menu(&mut tcod.root) {
+                    start_saved_game(tcod, &slot);
+                }
+            }
+            Some(3) => {
+                // watch a recorded run
+                if let Some(slot) = replay_menu(&mut tcod.root) {
+                    watch_replay(tcod, &slot);
+                }
+            }
+            Some(4) => {
                 // quit
                 break;
             }
@@ -510,9 +315,72 @@ fn main_menu(tcod: &mut Tcod) {
 }
 
 
+// Ask for a name for a new game, making sure we don't clobber an old one by accident.
+fn choose_new_slot(tcod: &mut Tcod) -> Option<String> {
+    let mut suggestion: String = String::from("run 1");
+    let mut n: u32 = 1;
+    while slot_exists(Path::new(SAVE_DIR), &suggestion) {
+        n += 1;
+        suggestion = format!("run {}", n);
+    }
+
+    loop {
+        let slot = text_input("Name this game:", &suggestion, SLOT_NAME_MAX, 32, &mut tcod.root)?;
+        if !valid_slot_name(&slot) {
+            let msg = format!("\nUse up to {} letters, numbers, spaces, '-' or '_'.\n", SLOT_NAME_MAX);
+            msgbox(&msg, 32, &mut tcod.root);
+            continue;
+        }
+        if slot_exists(Path::new(SAVE_DIR), &slot) {
+            let header = format!("\"{}\" already exists. Overwrite it?\n", slot);
+            if menu(&header, &["No", "Yes"], 32, &mut tcod.root) != Some(1) {
+                continue;
+            }
+        }
+        return Some(slot);
+    }
+}
+
+
+fn start_saved_game(tcod: &mut Tcod, slot: &str) {
+    match load_game(Path::new(SAVE_DIR), slot) {
+        Ok((mut game, mut objects, mut start_objects)) => {
+            match resume_recording(Path::new(SAVE_DIR), slot, &game) {
+                Ok(log) => tcod.log = log,
+                Err(e) => game.messages.add(format!("Not recording a replay: {}", e), RED),
+            }
+            play_game(tcod, slot, &mut game, &mut objects, &mut start_objects);
+            tcod.log = InputLog::Off;
+        }
+        Err(e) => {
+            let load_err = format!("ERROR Loading Game\n\n{}\n", e);
+            msgbox(&load_err, 32, &mut tcod.root);
+        }
+    }
+}
+
+
+// Re-run a recorded game from its seed, feeding it the recorded inputs.
+fn watch_replay(tcod: &mut Tcod, slot: &str) {
+    match load_replay(Path::new(SAVE_DIR), slot, REPLAY_DEFAULT_SPEED) {
+        Ok((seed, log)) => {
+            let (mut game, mut objects, mut start_objects) = new_game(seed);
+            tcod.log = log;
+            play_game(tcod, slot, &mut game, &mut objects, &mut start_objects);
+            tcod.log = InputLog::Off;
+            tcod::system::set_fps(LIMIT_FPS);
+        }
+        Err(e) => {
+            let replay_err = format!("ERROR Loading Replay\n\n{}\n", e);
+            msgbox(&replay_err, 32, &mut tcod.root);
+        }
+    }
+}
+
+
 fn main() {
     // Do some validation on data
-    assert!(NUM_LVLS % 2 == 1);
+    const { assert!(NUM_LVLS % 2 == 1) };
 
     // set the FPS
     tcod::system::set_fps(LIMIT_FPS);
@@ -530,10 +398,20 @@ fn main() {
         root,
         con: Offscreen::new(MAP_WIDTH, MAP_HEIGHT),
         panel: Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT),
-        fov: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
         key: Default::default(),
         mouse: Default::default(),
+        log: InputLog::Off,
     };
 
+    // a broken levels.toml or prefab still lets you play, on the built-in ones
+    if let Err(e) = init_profiles(Path::new(LEVELS_FILE)) {
+        let msg = format!("\n{}\n\nUsing the built-in level profiles instead.\n", e);
+        msgbox(&msg, 48, &mut tcod0.root);
+    }
+    if let Err(e) = init_prefabs(Path::new(PREFAB_DIR)) {
+        let msg = format!("\n{}\n\nUsing the built-in prefabs instead.\n", e);
+        msgbox(&msg, 48, &mut tcod0.root);
+    }
+
     main_menu(&mut tcod0);
 }
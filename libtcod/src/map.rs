@@ -1,65 +1,463 @@
 /*
   Highest-level map tooling
  */
+// Import Std Libs
+use std::collections::VecDeque;
+use std::ops::Index;
+use std::ops::IndexMut;
+
 // Import Third-Party
 use serde::{Deserialize, Serialize};
+use tcod::colors::Color;
 
 // Import Locally
+use crate::constants::COLOR_DARK_CHASM;
+use crate::constants::COLOR_DARK_GROUND;
+use crate::constants::COLOR_DARK_LAVA;
+use crate::constants::COLOR_DARK_WALL;
+use crate::constants::COLOR_DARK_WATER;
+use crate::constants::COLOR_LIGHT_CHASM;
+use crate::constants::COLOR_LIGHT_GROUND;
+use crate::constants::COLOR_LIGHT_LAVA;
+use crate::constants::COLOR_LIGHT_WALL;
+use crate::constants::COLOR_LIGHT_WATER;
 use crate::objects::Object;
+use crate::profiles::profile_for;
+use crate::profiles::Generator;
+use crate::rng::GameRng;
 use crate::map_algos::simple_fast::simple_fast;
 use crate::map_algos::bsp::bsp;
 use crate::map_algos::bsp_mod::bsp_mod;
+use crate::map_algos::caves::caves;
+use crate::map_algos::maze::maze;
+use crate::map_algos::passes::Behaviours;
+use crate::map_algos::passes::Doors;
+use crate::map_algos::passes::Pools;
+
+
+// What a tile is made of
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TileKind {
+    Wall,
+    Floor,
+    // blocks movement and sight until someone opens it
+    Door { open: bool },
+    // shallow: wading through it is slow
+    Water,
+    // burns whoever stands in it
+    Lava,
+    // drops whoever steps in it to the floor below
+    Chasm,
+}
 
 
 // A tile of the map and its properties
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Tile {
+    pub kind: TileKind,
     pub blocked: bool,
     pub explored: bool,
     pub block_sight: bool,
 }
 
 impl Tile {
-    pub fn empty() -> Self {
+    fn new(kind: TileKind, blocked: bool) -> Self {
         Tile {
-            blocked: false,
+            kind,
+            blocked,
             explored: false,
-            block_sight: false,
+            block_sight: blocked,
         }
     }
 
+    pub fn empty() -> Self {
+        Tile::new(TileKind::Floor, false)
+    }
+
     pub fn wall() -> Self {
-        Tile {
-            blocked: true,
-            explored: false,
-            block_sight: true,
+        Tile::new(TileKind::Wall, true)
+    }
+
+    pub fn door() -> Self {
+        Tile::new(TileKind::Door { open: false }, true)
+    }
+
+    pub fn water() -> Self {
+        Tile::new(TileKind::Water, false)
+    }
+
+    pub fn lava() -> Self {
+        Tile::new(TileKind::Lava, false)
+    }
+
+    pub fn chasm() -> Self {
+        Tile::new(TileKind::Chasm, false)
+    }
+
+    pub fn of_kind(kind: TileKind) -> Self {
+        match kind {
+            TileKind::Wall => Tile::wall(),
+            TileKind::Floor => Tile::empty(),
+            TileKind::Door { open } => Tile::new(kind, !open),
+            TileKind::Water => Tile::water(),
+            TileKind::Lava => Tile::lava(),
+            TileKind::Chasm => Tile::chasm(),
+        }
+    }
+
+    pub fn is_closed_door(&self) -> bool {
+        self.kind == TileKind::Door { open: false }
+    }
+
+    // once open, a door stays open
+    pub fn open_door(&mut self) {
+        self.kind = TileKind::Door { open: true };
+        self.blocked = false;
+        self.block_sight = false;
+    }
+
+    // tiles nobody would walk onto on purpose
+    pub fn is_hazard(&self) -> bool {
+        matches!(self.kind, TileKind::Lava | TileKind::Chasm)
+    }
+
+    // could be walked through safely, once the doors are opened
+    pub fn passable(&self) -> bool {
+        (!self.blocked || self.is_closed_door()) && !self.is_hazard()
+    }
+
+    // the background color, in or out of the player's sight
+    pub fn color(&self, lit: bool) -> Color {
+        match (self.kind, lit) {
+            (TileKind::Wall, false) => COLOR_DARK_WALL,
+            (TileKind::Wall, true) => COLOR_LIGHT_WALL,
+            (TileKind::Floor, false) | (TileKind::Door { .. }, false) => COLOR_DARK_GROUND,
+            (TileKind::Floor, true) | (TileKind::Door { .. }, true) => COLOR_LIGHT_GROUND,
+            (TileKind::Water, false) => COLOR_DARK_WATER,
+            (TileKind::Water, true) => COLOR_LIGHT_WATER,
+            (TileKind::Lava, false) => COLOR_DARK_LAVA,
+            (TileKind::Lava, true) => COLOR_LIGHT_LAVA,
+            (TileKind::Chasm, false) => COLOR_DARK_CHASM,
+            (TileKind::Chasm, true) => COLOR_LIGHT_CHASM,
+        }
+    }
+
+    // doors are drawn on top of the background
+    pub fn glyph(&self) -> Option<char> {
+        match self.kind {
+            TileKind::Door { open: false } => Some('+'),
+            TileKind::Door { open: true } => Some('\''),
+            _ => None,
+        }
+    }
+}
+
+
+// the eight steps to the tiles around one, straight ones first
+pub const NEIGHBORS: [(i32, i32); 8] = [(0, -1), (1, 0), (0, 1), (-1, 0), (1, -1), (1, 1), (-1, 1), (-1, -1)];
+
+
+// The tiles of one floor, in a single flat Vec: column by column (x, then y).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Map {
+    pub width: i32,
+    pub height: i32,
+    tiles: Vec<Tile>,
+}
+
+impl Map {
+    // solid rock, to carve the floor out of
+    pub fn new(width: i32, height: i32) -> Self {
+        Map {
+            width,
+            height,
+            tiles: vec![Tile::wall(); (width * height) as usize],
+        }
+    }
+
+    pub fn in_bounds(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < self.width && y < self.height
+    }
+
+    // inside the outer wall, which nothing ever carves through
+    pub fn in_interior(&self, x: i32, y: i32) -> bool {
+        x > 0 && y > 0 && x < self.width - 1 && y < self.height - 1
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        (x * self.height + y) as usize
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Option<&Tile> {
+        if self.in_bounds(x, y) {
+            return Some(&self.tiles[self.index(x, y)]);
         }
+        None
+    }
+
+    pub fn get_mut(&mut self, x: i32, y: i32) -> Option<&mut Tile> {
+        if self.in_bounds(x, y) {
+            let i = self.index(x, y);
+            return Some(&mut self.tiles[i]);
+        }
+        None
+    }
+
+    // change a tile; anything off the map is quietly left alone
+    pub fn set(&mut self, x: i32, y: i32, tile: Tile) {
+        if let Some(old) = self.get_mut(x, y) {
+            *old = tile;
+        }
+    }
+
+    // off the map counts as wall
+    pub fn is_wall(&self, x: i32, y: i32) -> bool {
+        self.get(x, y).is_none_or(|tile| tile.blocked)
+    }
+
+    // every position on the map, column by column
+    pub fn positions(&self) -> impl Iterator<Item = (i32, i32)> {
+        let height = self.height;
+        (0..self.width).flat_map(move |x| (0..height).map(move |y| (x, y)))
+    }
+
+    // every tile, with its position
+    pub fn iter(&self) -> impl Iterator<Item = ((i32, i32), &Tile)> {
+        self.positions().zip(self.tiles.iter())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = ((i32, i32), &mut Tile)> {
+        self.positions().zip(self.tiles.iter_mut())
     }
 }
 
+// map[(x, y)] is for positions already known to be on the map
+impl Index<(i32, i32)> for Map {
+    type Output = Tile;
 
-pub type Map = Vec<Vec<Tile>>;
+    fn index(&self, (x, y): (i32, i32)) -> &Tile {
+        self.get(x, y)
+            .unwrap_or_else(|| panic!("({}, {}) is off the {}x{} map", x, y, self.width, self.height))
+    }
+}
 
+impl IndexMut<(i32, i32)> for Map {
+    fn index_mut(&mut self, (x, y): (i32, i32)) -> &mut Tile {
+        let (width, height) = (self.width, self.height);
+        self.get_mut(x, y)
+            .unwrap_or_else(|| panic!("({}, {}) is off the {}x{} map", x, y, width, height))
+    }
+}
 
-// Useful testing function, when designing new map algorithms.
-#[allow(dead_code)]
-pub fn print_map(map: &Map) {
-    map.iter().for_each(|row| {
-        row.iter().for_each(|cell| {
-            if cell.blocked { print!("#"); }
-            else { print!(" "); }
+
+pub fn make_map(all_objects: &mut [Vec<Object>], level: usize, rng: &mut GameRng) -> (Map, (i32, i32), (i32, i32)) {
+    // the level's profile decides how it gets built
+    let profile = profile_for(level);
+    let density = &profile.density;
+    let mut chain = match &profile.generator {
+        Generator::SimpleFast(params) => simple_fast(params, density),
+        Generator::Bsp(params) => bsp(params, density),
+        Generator::BspMod(params) => bsp_mod(params, density),
+        Generator::Caves(params) => caves(params, density),
+        Generator::Maze(params) => maze(params, density),
+    };
+    let terrain = &profile.terrain;
+    chain = chain
+        .with(Doors { percent: terrain.door_percent })
+        .with(Pools { kind: TileKind::Water, count: terrain.water_pools, size: terrain.pool_size })
+        .with(Pools { kind: TileKind::Lava, count: terrain.lava_pools, size: terrain.pool_size })
+        .with(Pools { kind: TileKind::Chasm, count: terrain.chasms, size: terrain.pool_size })
+        .with(Behaviours {
+            patrol_percent: profile.behaviour.patrol_percent,
+            wander_percent: profile.behaviour.wander_percent,
+            guard_percent: profile.behaviour.guard_percent,
         });
-        print!("\n");
-    });
-    println!("\n");
+    let (mut map, up, down) = chain.build(all_objects, level, profile.width, profile.height, rng);
+
+    // the stairs have to be stood on (a pillar can land right on top of them),
+    // and everything has to be reachable from where the player arrives (without
+    // crossing lava or chasms)
+    map.set(up.0, up.1, Tile::empty());
+    map.set(down.0, down.1, Tile::empty());
+    connect_map(&mut map, down);
+
+    (map, up, down)
+}
+
+
+// How many steps it takes to walk to each tile from the start (moving in all
+// 8 directions, like the player, through doors but around lava and chasms), or
+// None if it can't be reached at all.
+pub fn distances(map: &Map, start: (i32, i32)) -> Vec<Vec<Option<u32>>> {
+    let (width, height) = (map.width, map.height);
+    let mut dist: Vec<Vec<Option<u32>>> = vec![vec![None; height as usize]; width as usize];
+    if !map.get(start.0, start.1).is_some_and(|tile| tile.passable()) {
+        return dist;
+    }
+
+    let mut queue: VecDeque<(i32, i32)> = VecDeque::new();
+    dist[start.0 as usize][start.1 as usize] = Some(0);
+    queue.push_back(start);
+    while let Some((x, y)) = queue.pop_front() {
+        let steps = dist[x as usize][y as usize].unwrap();
+        for &(dx, dy) in NEIGHBORS.iter() {
+            let (nx, ny) = (x + dx, y + dy);
+            if map.in_bounds(nx, ny) && dist[nx as usize][ny as usize].is_none() && map[(nx, ny)].passable() {
+                dist[nx as usize][ny as usize] = Some(steps + 1);
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+    dist
+}
+
+
+// Which tiles can be walked to from the start.
+pub fn reachable(map: &Map, start: (i32, i32)) -> Vec<Vec<bool>> {
+    distances(map, start)
+        .iter()
+        .map(|column| column.iter().map(|d| d.is_some()).collect())
+        .collect()
+}
+
+
+// Report every floor tile that can't be reached from the start.
+pub fn unreachable_cells(map: &Map, start: (i32, i32)) -> Vec<(i32, i32)> {
+    let seen = reachable(map, start);
+    let mut cells: Vec<(i32, i32)> = vec![];
+    for y in 0..map.height {
+        for x in 0..map.width {
+            if map[(x, y)].passable() && !seen[x as usize][y as usize] {
+                cells.push((x, y));
+            }
+        }
+    }
+    cells
+}
+
+
+// Dig tunnels until every floor tile can be reached from the start. Each tunnel
+// is the shortest one from the reachable area to the nearest cut-off floor tile.
+// Returns how many tunnels were dug.
+pub fn connect_map(map: &mut Map, start: (i32, i32)) -> u32 {
+    let (width, height) = (map.width, map.height);
+    let mut tunnels: u32 = 0;
+
+    loop {
+        let seen = reachable(map, start);
+
+        // search outwards from the reachable area, through walls (but never the outer wall)
+        let mut came_from: Vec<Vec<Option<(i32, i32)>>> = vec![vec![None; height as usize]; width as usize];
+        let mut queue: VecDeque<(i32, i32)> = VecDeque::new();
+        for x in 0..width {
+            for y in 0..height {
+                if seen[x as usize][y as usize] {
+                    queue.push_back((x, y));
+                }
+            }
+        }
+
+        let mut found: Option<(i32, i32)> = None;
+        while let Some((x, y)) = queue.pop_front() {
+            if map[(x, y)].passable() && !seen[x as usize][y as usize] {
+                found = Some((x, y));
+                break;
+            }
+            for &(dx, dy) in NEIGHBORS.iter() {
+                let (nx, ny) = (x + dx, y + dy);
+                if map.in_interior(nx, ny) && !seen[nx as usize][ny as usize] && came_from[nx as usize][ny as usize].is_none() {
+                    came_from[nx as usize][ny as usize] = Some((x, y));
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+
+        // walk back from the cut-off tile, opening up the walls on the way
+        let mut cell = match found {
+            Some(cell) => cell,
+            None => return tunnels,
+        };
+        while let Some(prev) = came_from[cell.0 as usize][cell.1 as usize] {
+            map[cell] = Tile::empty();
+            cell = prev;
+        }
+        tunnels += 1;
+    }
 }
 
-pub fn make_map(all_objects: &mut Vec<Vec<Object>>, level: usize) -> (Map, (i32, i32), (i32, i32)) {
-    if level < 10 {
-        return bsp_mod(all_objects, level);
-    } else if level < 16 {
-        return bsp(all_objects, level);
-    } else {
-        return simple_fast(all_objects, level);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::NUM_LVLS;
+    use crate::engine::new_player;
+
+    fn carve(map: &mut Map, xs: std::ops::Range<i32>, ys: std::ops::Range<i32>) {
+        for x in xs {
+            for y in ys.clone() {
+                map[(x, y)] = Tile::empty();
+            }
+        }
+    }
+
+
+    #[test]
+    fn the_map_is_bounds_checked() {
+        let mut map = Map::new(30, 20);
+        map.set(3, 4, Tile::empty());
+        assert!(!map[(3, 4)].blocked);
+        // column by column, so (3, 4) and (4, 3) are different tiles
+        assert!(map[(4, 3)].blocked);
+
+        assert!(map.get(30, 0).is_none() && map.get(0, -1).is_none());
+        assert!(map.is_wall(-1, 5) && map.is_wall(5, 20));
+        map.set(40, 40, Tile::empty());
+        assert_eq!(map.positions().count(), 30 * 20);
+        assert!(!map.in_interior(0, 5) && map.in_interior(1, 5) && !map.in_interior(1, 19));
+    }
+
+
+    #[test]
+    #[should_panic(expected = "(30, 0) is off the 30x20 map")]
+    fn indexing_off_the_map_panics() {
+        let map = Map::new(30, 20);
+        let _ = map[(30, 0)];
+    }
+
+
+    #[test]
+    fn cut_off_areas_get_tunnels() {
+        let mut map = Map::new(30, 20);
+        carve(&mut map, 1..5, 1..5);
+        carve(&mut map, 20..25, 10..15);
+        map[(10, 18)] = Tile::empty();
+
+        assert_eq!(unreachable_cells(&map, (2, 2)).len(), 26);
+        assert_eq!(connect_map(&mut map, (2, 2)), 2);
+        assert!(unreachable_cells(&map, (2, 2)).is_empty());
+        // a connected map needs no more tunnels
+        assert_eq!(connect_map(&mut map, (2, 2)), 0);
+    }
+
+
+    #[test]
+    fn nothing_is_reachable_from_inside_a_wall() {
+        let map = Map::new(5, 5);
+        assert!(reachable(&map, (2, 2)).iter().flatten().all(|seen| !seen));
+    }
+
+
+    #[test]
+    fn every_floor_is_connected_to_its_stairs() {
+        for seed in 0..3 {
+            let mut rng = GameRng::new(seed);
+            let mut objects: Vec<Vec<Object>> = vec![vec![]; NUM_LVLS as usize];
+            objects[0].push(new_player());
+            for level in 0..NUM_LVLS as usize {
+                let (map, up, down) = make_map(&mut objects, level, &mut rng);
+                assert!(unreachable_cells(&map, down).is_empty(), "seed {} level {}", seed, level);
+                assert!(!map[up].blocked);
+            }
+        }
     }
 }
@@ -1,3 +1,9 @@
 pub mod bsp;
 pub mod bsp_mod;
+pub mod builder;
+pub mod caves;
+pub mod common;
+pub mod maze;
+pub mod passes;
+pub mod prefabs;
 pub mod simple_fast;
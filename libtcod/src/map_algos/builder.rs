@@ -0,0 +1,101 @@
+/*
+  Map Builders
+
+  Every map-gen algorithm is a MapBuilder: it takes the floor built so far and
+  does its part. One builder lays the floor out from solid rock, and then any
+  number of passes (stairs, smoothing, spawning) are chained on after it:
+
+    BuilderChain::new(Caves::new(params))
+        .with(FarthestStairs)
+        .with(TileSpawner::new(density, safe_radius))
+        .build(all_objects, level, width, height, rng)
+ */
+// Import Third-Party
+use tcod::colors::*;
+
+// Import Locally
+use crate::constants::PLAYER;
+use crate::map::Map;
+use crate::map_algos::common::Rect;
+use crate::objects::Object;
+use crate::rng::GameRng;
+
+
+// Everything known about the floor while it is being built
+pub struct BuildData {
+    pub level: usize,
+    pub map: Map,
+    // for builders that make rooms: the first one is where the player arrives
+    pub rooms: Vec<Rect>,
+    // where the player arrives on this floor, and where they leave it
+    pub down_stairs: (i32, i32),
+    pub up_stairs: (i32, i32),
+    // the NPCs and items to put on the floor
+    pub spawns: Vec<Object>,
+}
+
+impl BuildData {
+    pub fn new(level: usize, width: i32, height: i32) -> Self {
+        BuildData {
+            level,
+            map: Map::new(width, height),
+            rooms: vec![],
+            down_stairs: (-1, -1),
+            up_stairs: (-1, -1),
+            spawns: vec![],
+        }
+    }
+}
+
+
+pub trait MapBuilder {
+    fn build(&mut self, data: &mut BuildData, rng: &mut GameRng);
+}
+
+
+// One builder to lay out the floor, followed by passes that work on what it made.
+pub struct BuilderChain {
+    builders: Vec<Box<dyn MapBuilder>>,
+}
+
+impl BuilderChain {
+    pub fn new<B: MapBuilder + 'static>(starter: B) -> Self {
+        BuilderChain { builders: vec![Box::new(starter)] }
+    }
+
+    pub fn with<B: MapBuilder + 'static>(mut self, pass: B) -> Self {
+        self.builders.push(Box::new(pass));
+        self
+    }
+
+    // run every builder in order, then put the stairs (or the player) and spawns on the floor
+    pub fn build(&mut self,
+                 all_objects: &mut [Vec<Object>],
+                 level: usize,
+                 width: i32,
+                 height: i32,
+                 rng: &mut GameRng) -> (Map, (i32, i32), (i32, i32)) {
+        let mut data = BuildData::new(level, width, height);
+        for builder in self.builders.iter_mut() {
+            builder.build(&mut data, rng);
+        }
+
+        let objects = &mut all_objects[level];
+        let (down_x, down_y) = data.down_stairs;
+        if level == 0 {
+            // the very first floor is where the player starts
+            objects[PLAYER].set_pos(down_x, down_y);
+        } else {
+            let mut down_stairs = Object::new(down_x, down_y, '<', "down-stairs", WHITE, false);
+            down_stairs.always_visible = true;
+            objects.push(down_stairs);
+        }
+        let (up_x, up_y) = data.up_stairs;
+        let mut up_stairs = Object::new(up_x, up_y, '>', "up-stairs", WHITE, false);
+        up_stairs.always_visible = true;
+        objects.push(up_stairs);
+        objects.extend(data.spawns);
+
+        (data.map, data.up_stairs, data.down_stairs)
+    }
+}
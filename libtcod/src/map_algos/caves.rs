@@ -0,0 +1,211 @@
+/*
+  Cellular Automata Caves
+
+  Scatter walls at random, then smooth them out a few times: a tile becomes
+  wall when most of its neighbors are walls, and floor when most aren't. What
+  is left looks like natural caverns instead of rooms and corridors.
+
+  - Either keep just the biggest cavern: wide open, with nothing cut off (the Abyss).
+  - Or keep all of the caverns, and join them with narrow tunnels (the Underdark).
+ */
+// Import Std Libs
+use std::cmp::Reverse;
+
+// Import Third-Party
+use rand::Rng;
+use serde::Deserialize;
+
+// Import Locally
+use crate::map::connect_map;
+use crate::map::distances;
+use crate::map::Map;
+use crate::map::Tile;
+use crate::map_algos::builder::BuildData;
+use crate::map_algos::builder::BuilderChain;
+use crate::map_algos::builder::MapBuilder;
+use crate::map_algos::passes::FarthestStairs;
+use crate::map_algos::passes::Smoothing;
+use crate::map_algos::passes::TileSpawner;
+use crate::map_algos::prefabs::PrefabRooms;
+use crate::profiles::Density;
+use crate::rng::GameRng;
+
+
+// parameters for map generator
+#[derive(Clone, Debug, Deserialize)]
+pub struct CavesParams {
+    // chance-in-100 that a tile starts out as wall
+    pub fill_percent: u32,
+    pub smoothing_steps: u32,
+    // in the first few steps, big empty areas also get a wall, so there are no vast plains
+    pub rough_steps: u32,
+    // reject any cave where less than this percent of the map can be walked on
+    pub min_floor_percent: usize,
+    // no NPCs spawn this close to where the player arrives
+    pub safe_radius: i32,
+    // tunnel between all the caverns, instead of keeping only the biggest
+    pub connect_caverns: bool,
+}
+
+// after this many cramped caves, settle for the next one with any floor at all
+const MAX_DIG_ATTEMPTS: u32 = 100;
+
+
+fn random_fill(width: i32, height: i32, fill_percent: u32, rng: &mut GameRng) -> Map {
+    let mut map: Map = Map::new(width, height);
+    for x in 1..width - 1 {
+        for y in 1..height - 1 {
+            if rng.gen_range(0, 100) >= fill_percent {
+                map[(x, y)] = Tile::empty();
+            }
+        }
+    }
+    map
+}
+
+
+// Split the floor into caverns: groups of tiles you can walk between.
+fn caverns(map: &Map) -> Vec<Vec<(i32, i32)>> {
+    let mut labelled = vec![vec![false; map.height as usize]; map.width as usize];
+    let mut caverns: Vec<Vec<(i32, i32)>> = vec![];
+    for (x, y) in map.positions() {
+        if map[(x, y)].blocked || labelled[x as usize][y as usize] {
+            continue;
+        }
+        let dist = distances(map, (x, y));
+        let mut cavern: Vec<(i32, i32)> = vec![];
+        for (cx, cy) in map.positions() {
+            if dist[cx as usize][cy as usize].is_some() {
+                labelled[cx as usize][cy as usize] = true;
+                cavern.push((cx, cy));
+            }
+        }
+        caverns.push(cavern);
+    }
+    caverns
+}
+
+
+/**
+ * Cellular Automata Caves for Map Generation
+ *
+ * Step 1: Scatter walls at random, and smooth them into caverns
+ * Step 2: Keep the biggest cavern, or tunnel between them all
+ */
+pub struct Caves {
+    params: CavesParams,
+}
+
+impl MapBuilder for Caves {
+    fn build(&mut self, data: &mut BuildData, rng: &mut GameRng) {
+        let params = &self.params;
+        let mut smoothing = Smoothing { steps: params.smoothing_steps, rough_steps: params.rough_steps };
+
+        let mut attempts: u32 = 0;
+        loop {
+            attempts += 1;
+            data.map = random_fill(data.map.width, data.map.height, params.fill_percent, rng);
+            smoothing.build(data, rng);
+
+            let mut caves = caverns(&data.map);
+            caves.sort_by_key(|cave| Reverse(cave.len()));
+            // the floor that will be left: every cavern if they get joined up, otherwise only the biggest
+            let walkable: usize = if params.connect_caverns {
+                caves.iter().map(|cave| cave.len()).sum()
+            } else {
+                caves.first().map_or(0, |cave| cave.len())
+            };
+            let cramped: bool = walkable * 100 < params.min_floor_percent * (data.map.width * data.map.height) as usize;
+            if caves.is_empty() || (cramped && attempts < MAX_DIG_ATTEMPTS) {
+                // too cramped, try again
+                continue;
+            }
+
+            if params.connect_caverns {
+                // tunnel between all the caverns
+                connect_map(&mut data.map, caves[0][0]);
+            } else {
+                // fill in everything but the biggest cavern
+                for cave in caves.iter().skip(1) {
+                    for &(x, y) in cave {
+                        data.map[(x, y)] = Tile::wall();
+                    }
+                }
+            }
+            return;
+        }
+    }
+}
+
+
+pub fn caves(params: &CavesParams, density: &Density) -> BuilderChain {
+    BuilderChain::new(Caves { params: params.clone() })
+        .with(PrefabRooms)
+        .with(FarthestStairs)
+        .with(TileSpawner::new(density, params.safe_radius))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::MAP_HEIGHT;
+    use crate::constants::MAP_WIDTH;
+    use crate::map::unreachable_cells;
+    use crate::map_algos::passes::floor_tiles;
+    use crate::objects::Object;
+    use crate::profiles::profile_for;
+    use crate::profiles::Generator;
+
+    fn params_for(level: usize) -> &'static CavesParams {
+        match &profile_for(level).generator {
+            Generator::Caves(params) => params,
+            _ => panic!("floor {} isn't built as caves", level),
+        }
+    }
+
+
+    // dig just the caverns of a floor, without stairs or spawns
+    fn dig(level: usize, seed: u64) -> Map {
+        let mut rng = GameRng::new(seed);
+        let mut data = BuildData::new(level, profile_for(level).width, profile_for(level).height);
+        Caves { params: params_for(level).clone() }.build(&mut data, &mut rng);
+        data.map
+    }
+
+
+    #[test]
+    fn the_abyss_is_one_big_cavern() {
+        for seed in 0..4 {
+            let map = dig(4, seed);
+            assert_eq!(caverns(&map).len(), 1, "seed {}", seed);
+            assert!(floor_tiles(&map).len() * 100 >= params_for(4).min_floor_percent * (map.width * map.height) as usize);
+        }
+    }
+
+
+    #[test]
+    fn the_underdark_tunnels_between_its_caverns() {
+        for seed in 0..4 {
+            let map = dig(8, seed);
+            let floor = floor_tiles(&map);
+            assert!(unreachable_cells(&map, floor[0]).is_empty(), "seed {}", seed);
+        }
+    }
+
+
+    #[test]
+    fn the_stairs_are_far_apart_and_connected() {
+        let mut rng = GameRng::new(3);
+        let mut objects: Vec<Vec<Object>> = vec![vec![]; 10];
+        let (map, up, down) = caves(params_for(5), &profile_for(5).density).build(&mut objects, 5, MAP_WIDTH, MAP_HEIGHT, &mut rng);
+        let dist = distances(&map, down);
+        assert!(dist[up.0 as usize][up.1 as usize].unwrap() > 0);
+        assert!(unreachable_cells(&map, down).is_empty());
+        // nothing hostile waits right where the player arrives
+        assert!(objects[5]
+            .iter()
+            .filter(|o| o.ai.is_some())
+            .all(|o| (o.x - down.0).pow(2) + (o.y - down.1).pow(2) > params_for(5).safe_radius.pow(2)));
+    }
+}
@@ -0,0 +1,204 @@
+/*
+  Geometry and carving, shared by the map-gen algos.
+ */
+// Import Std Libs
+use std::cmp;
+
+// Import Third-Party
+use rand::Rng;
+
+// Import Locally
+use crate::map::Map;
+use crate::map::Tile;
+use crate::rng::GameRng;
+
+
+// A rectangle on the map, used to characterise a room.
+// The corners are its walls: only the tiles inside them get carved out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x1: i32,
+    pub y1: i32,
+    pub x2: i32,
+    pub y2: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
+        Rect {
+            x1: x,
+            y1: y,
+            x2: x + w,
+            y2: y + h,
+        }
+    }
+
+    pub fn from_corners(x1: i32, y1: i32, x2: i32, y2: i32) -> Self {
+        Rect {
+            x1,
+            y1,
+            x2,
+            y2,
+        }
+    }
+
+    pub fn center(&self) -> (i32, i32) {
+        // find the center of the Rect
+        let center_x: i32 = (self.x1 + self.x2) / 2;
+        let center_y: i32 = (self.y1 + self.y2) / 2;
+        (center_x, center_y)
+    }
+
+    pub fn intersects_with(&self, other: &Rect) -> bool {
+        // returns true if this rectangle intersects with another one
+        (self.x1 <= other.x2)
+            && (self.x2 >= other.x1)
+            && (self.y1 <= other.y2)
+            && (self.y2 >= other.y1)
+    }
+
+    // the tiles a room in this rectangle would have
+    pub fn interior(&self) -> Vec<(i32, i32)> {
+        let mut tiles: Vec<(i32, i32)> = vec![];
+        for x in (self.x1 + 1)..self.x2 {
+            for y in (self.y1 + 1)..self.y2 {
+                tiles.push((x, y));
+            }
+        }
+        tiles
+    }
+}
+
+
+// open up one tile, but never the outer wall (or off the edge of the map)
+fn carve(x: i32, y: i32, map: &mut Map) {
+    if map.in_interior(x, y) {
+        map[(x, y)] = Tile::empty();
+    }
+}
+
+
+pub fn carve_room(room: Rect, map: &mut Map) {
+    // go through the tiles in the rectangle and make them passable
+    for (x, y) in room.interior() {
+        carve(x, y, map);
+    }
+}
+
+
+pub fn carve_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
+    // horizontal tunnel. `min()` and `max()` are used in case `x1 > x2`
+    for x in cmp::min(x1, x2)..(cmp::max(x1, x2) + 1) {
+        carve(x, y, map);
+    }
+}
+
+
+pub fn carve_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
+    // vertical tunnel
+    for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
+        carve(x, y, map);
+    }
+}
+
+
+// An L-shaped tunnel between two points, bending one way or the other.
+pub fn carve_tunnel(from: (i32, i32), to: (i32, i32), map: &mut Map, rng: &mut GameRng) {
+    let (prev_x, prev_y) = from;
+    let (new_x, new_y) = to;
+
+    // flip a coin
+    if rng.gen() {
+        // first move horizontally, then vertically
+        carve_h_tunnel(prev_x, new_x, prev_y, map);
+        carve_v_tunnel(prev_y, new_y, new_x, map);
+    } else {
+        // first move vertically, then horizontally
+        carve_v_tunnel(prev_y, new_y, prev_x, map);
+        carve_h_tunnel(prev_x, new_x, new_y, map);
+    }
+}
+
+
+/**
+ * Split a single cell into two (if possible).
+ *
+ * NOTE: If the split is not possible, the second cell is None.
+ * NOTE: The corner positions listed are inclusive.
+ */
+fn split_single_cell(cell: Rect, room_min_size: i32, rng: &mut GameRng) -> (Rect, Option<Rect>) {
+    let cell_width: i32 = cell.x2 - cell.x1;
+    let cell_height: i32 = cell.y2 - cell.y1;
+
+    if cell_width <= 2 * room_min_size && cell_height <= 2 * room_min_size {
+        // Case 0: We can't go smaller, return this cell
+        return (cell, None);
+    }
+
+    let min_split_x: i32 = cell.x1 + room_min_size;
+    let max_split_x: i32 = cell.x2 - room_min_size;
+    let min_split_y: i32 = cell.y1 + room_min_size;
+    let max_split_y: i32 = cell.y2 - room_min_size;
+
+    let mut split_vert: bool = true;
+    if cell_width <= 2 * room_min_size || (cell_height as f32 / cell_width as f32) > 3.0 {
+        // Case 1: Split Horizontally
+        split_vert = false;
+    } else if cell_height <= 2 * room_min_size || (cell_width as f32 / cell_height as f32) > 3.0 {
+        // Case 2: Split Vertically
+        split_vert = true;
+    } else {
+        // Case 3: Split Vertically/Horizontally at random
+        if rng.gen_range(0, 2) == 1 {
+            split_vert = false;
+        }
+    }
+
+    // return the 2 new cells
+    if split_vert {
+        let x: i32 = rng.gen_range(min_split_x, max_split_x + 1);
+        (Rect::from_corners(cell.x1, cell.y1, x, cell.y2),
+                Some(Rect::from_corners(x + 1, cell.y1, cell.x2, cell.y2)))
+    } else {
+        let y: i32 = rng.gen_range(min_split_y, max_split_y + 1);
+        (Rect::from_corners(cell.x1, cell.y1, cell.x2, y),
+                Some(Rect::from_corners(cell.x1, y + 1, cell.x2, cell.y2)))
+    }
+}
+
+
+/**
+ * Binary Space Partition: the purely spatial part
+ *
+ * 1. Grab the space and divide it in 2. Save off the 2 new spaces
+ * 2. Repeat, storing off the smaller spaces, N times.
+ * 3. There are rules for (1) and (2). Minimum size rules.
+ * 4. If a space is too small, just don't split it.
+ */
+pub fn binary_space_partition(width: i32, height: i32, iterations: i32, room_min_size: i32, rng: &mut GameRng) -> Vec<Rect> {
+    // quick validation
+    assert!(width > room_min_size);
+    assert!(height > room_min_size);
+    assert!(iterations > 0);
+
+    // init the entire space as a cell
+    let mut cells: Vec<Rect> = vec![Rect::from_corners(0, 0, width, height)];
+
+    for _iter in 0..iterations {
+        let mut new_cells: Vec<Rect> = vec![];
+
+        // Go through each current cell and try to split it
+        for this_cell in cells.iter() {
+            let (t1, t2) = split_single_cell(*this_cell, room_min_size, rng);
+            new_cells.push(t1);
+            if let Some(t2) = t2 {
+                new_cells.push(t2);
+            }
+        }
+
+        // wipe the old cells, and put in the new (smaller) ones
+        cells = new_cells;
+    }
+
+    cells
+}
@@ -0,0 +1,313 @@
+/*
+  Growing Tree Maze
+
+  Carve a perfect maze (exactly one path between any two places) on a grid of
+  cells at odd coordinates, with the walls between them at even ones. Then
+  "braid" it: knock through some of the dead ends so the maze has loops.
+
+  Growing tree grows the maze from a list of cells: always taking the newest
+  one makes it a recursive backtracker (long, winding passages), taking a
+  random one makes it branch a lot more.
+ */
+// Import Third-Party
+use rand::Rng;
+use serde::Deserialize;
+
+// Import Locally
+use crate::loot_table::generate_floor_item;
+use crate::map::distances;
+use crate::map::Map;
+use crate::map::Tile;
+use crate::map_algos::builder::BuildData;
+use crate::map_algos::builder::BuilderChain;
+use crate::map_algos::builder::MapBuilder;
+use crate::map_algos::passes::TileSpawner;
+use crate::map_algos::prefabs::PrefabRooms;
+use crate::profiles::Density;
+use crate::rng::GameRng;
+use crate::transition::from_map_level;
+use crate::transition::Transition;
+
+
+// parameters for map generator
+#[derive(Clone, Debug, Deserialize)]
+pub struct MazeParams {
+    // chance-in-100 of growing from the newest cell (otherwise a random one)
+    pub newest_percent: u32,
+    // chance-in-100 that a dead end is knocked through, making a loop
+    pub braid_percent: u32,
+    // no NPCs spawn this close to where the player arrives
+    pub safe_radius: i32,
+}
+
+const DIRECTIONS: [(i32, i32); 4] = [(0, -2), (2, 0), (0, 2), (-2, 0)];
+
+
+// open up a cell, and the wall between it and where we came from
+fn carve(map: &mut Map, from: (i32, i32), to: (i32, i32)) {
+    let (wall_x, wall_y) = ((from.0 + to.0) / 2, (from.1 + to.1) / 2);
+    map[(wall_x, wall_y)] = Tile::empty();
+    map[to] = Tile::empty();
+}
+
+
+fn growing_tree(width: i32, height: i32, newest_percent: u32, rng: &mut GameRng) -> Map {
+    let mut map: Map = Map::new(width, height);
+
+    // start from a random cell
+    let start: (i32, i32) = (
+        rng.gen_range(0, (width - 1) / 2) * 2 + 1,
+        rng.gen_range(0, (height - 1) / 2) * 2 + 1,
+    );
+    map[start] = Tile::empty();
+    let mut active: Vec<(i32, i32)> = vec![start];
+
+    while !active.is_empty() {
+        let index: usize = if rng.gen_range(0, 100) < newest_percent {
+            active.len() - 1
+        } else {
+            rng.gen_range(0, active.len())
+        };
+        let (x, y) = active[index];
+
+        // the neighboring cells nobody has carved into yet
+        let unvisited: Vec<(i32, i32)> = DIRECTIONS
+            .iter()
+            .map(|&(dx, dy)| (x + dx, y + dy))
+            .filter(|&(nx, ny)| map.in_interior(nx, ny) && map[(nx, ny)].blocked)
+            .collect();
+
+        if unvisited.is_empty() {
+            // this cell is finished
+            active.remove(index);
+        } else {
+            let next = unvisited[rng.gen_range(0, unvisited.len())];
+            carve(&mut map, (x, y), next);
+            active.push(next);
+        }
+    }
+    map
+}
+
+
+// the cells with only one way in or out
+fn dead_ends(map: &Map) -> Vec<(i32, i32)> {
+    let mut ends: Vec<(i32, i32)> = vec![];
+    for x in (1..map.width - 1).step_by(2) {
+        for y in (1..map.height - 1).step_by(2) {
+            let exits = DIRECTIONS
+                .iter()
+                .filter(|&&(dx, dy)| !map[(x + dx / 2, y + dy / 2)].blocked)
+                .count();
+            if exits == 1 {
+                ends.push((x, y));
+            }
+        }
+    }
+    ends
+}
+
+
+// Knock through some dead ends, preferring to join two dead ends at once.
+fn braid(map: &mut Map, braid_percent: u32, rng: &mut GameRng) {
+    for (x, y) in dead_ends(map) {
+        if rng.gen_range(0, 100) >= braid_percent {
+            continue;
+        }
+        // an earlier knock-through may have already fixed this one
+        let open = DIRECTIONS
+            .iter()
+            .filter(|&&(dx, dy)| !map[(x + dx / 2, y + dy / 2)].blocked)
+            .count();
+        let closed: Vec<(i32, i32)> = DIRECTIONS
+            .iter()
+            .filter(|&&(dx, dy)| map.in_interior(x + dx, y + dy) && map[(x + dx / 2, y + dy / 2)].blocked)
+            .map(|&(dx, dy)| (x + dx, y + dy))
+            .collect();
+        if open != 1 || closed.is_empty() {
+            continue;
+        }
+
+        let ends = dead_ends(map);
+        let target = match closed.iter().find(|cell| ends.contains(cell)) {
+            Some(&cell) => cell,
+            None => closed[rng.gen_range(0, closed.len())],
+        };
+        carve(map, (x, y), target);
+    }
+}
+
+
+// The two dead ends farthest apart (walking), for the stairs.
+fn farthest_dead_ends(map: &Map) -> ((i32, i32), (i32, i32)) {
+    let ends = dead_ends(map);
+    if ends.len() < 2 {
+        // fully braided: fall back on opposite corners
+        return ((1, 1), (((map.width - 1) / 2 - 1) * 2 + 1, ((map.height - 1) / 2 - 1) * 2 + 1));
+    }
+
+    let mut best: ((i32, i32), (i32, i32)) = (ends[0], ends[1]);
+    let mut best_dist: u32 = 0;
+    for &start in ends.iter() {
+        let dist = distances(map, start);
+        for &end in ends.iter() {
+            if let Some(d) = dist[end.0 as usize][end.1 as usize] {
+                if d > best_dist {
+                    best_dist = d;
+                    best = (start, end);
+                }
+            }
+        }
+    }
+    best
+}
+
+
+// The dead ends farthest apart: arrive at one, and leave from the other.
+pub struct DeadEndStairs;
+
+impl MapBuilder for DeadEndStairs {
+    fn build(&mut self, data: &mut BuildData, _rng: &mut GameRng) {
+        let (down, up) = farthest_dead_ends(&data.map);
+        data.down_stairs = down;
+        data.up_stairs = up;
+    }
+}
+
+
+// The dead ends are where the treasure is (the values are chance-in-1000 for each one).
+pub struct DeadEndLoot {
+    items: Vec<Transition>,
+}
+
+impl MapBuilder for DeadEndLoot {
+    fn build(&mut self, data: &mut BuildData, rng: &mut GameRng) {
+        let level = data.level as u32;
+        let item_chance: u32 = from_map_level(&self.items, level);
+
+        for (x, y) in dead_ends(&data.map) {
+            let chance: u32 = rng.gen_range(0, 1000) as u32;
+            let taken: bool = data.spawns.iter().any(|object| object.pos() == (x, y))
+                || (x, y) == data.down_stairs
+                || (x, y) == data.up_stairs;
+            if chance < item_chance && !taken {
+                let mut item = generate_floor_item(level as i32, rng);
+                item.set_pos(x, y);
+                data.spawns.push(item);
+            }
+        }
+    }
+}
+
+
+/**
+ * Growing Tree Maze for Map Generation
+ *
+ * Step 1: Carve a perfect maze
+ * Step 2: Braid it, so there are loops
+ */
+pub struct Maze {
+    params: MazeParams,
+}
+
+impl MapBuilder for Maze {
+    fn build(&mut self, data: &mut BuildData, rng: &mut GameRng) {
+        data.map = growing_tree(data.map.width, data.map.height, self.params.newest_percent, rng);
+        braid(&mut data.map, self.params.braid_percent, rng);
+    }
+}
+
+
+// NPCs roam the corridors, and items wait at the dead ends.
+pub fn maze(params: &MazeParams, density: &Density) -> BuilderChain {
+    let corridors = Density { npcs: density.npcs.clone(), items: vec![] };
+    BuilderChain::new(Maze { params: params.clone() })
+        .with(PrefabRooms)
+        .with(DeadEndStairs)
+        .with(TileSpawner::new(&corridors, params.safe_radius))
+        .with(DeadEndLoot { items: density.items.clone() })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::unreachable_cells;
+    use crate::objects::Object;
+    use crate::profiles::profile_for;
+    use crate::profiles::Generator;
+
+    const NEWEST: u32 = 75;
+    const BRAID: u32 = 40;
+    // any odd size will do
+    const WIDTH: i32 = 41;
+    const HEIGHT: i32 = 25;
+
+
+    // the cells, and the open walls between neighboring cells
+    fn cells_and_passages(map: &Map) -> (usize, usize) {
+        let mut cells: usize = 0;
+        let mut passages: usize = 0;
+        for x in (1..map.width - 1).step_by(2) {
+            for y in (1..map.height - 1).step_by(2) {
+                if !map[(x, y)].blocked {
+                    cells += 1;
+                }
+                // only look right and down, so each passage is counted once
+                if map.in_interior(x + 2, y) && !map[(x + 1, y)].blocked {
+                    passages += 1;
+                }
+                if map.in_interior(x, y + 2) && !map[(x, y + 1)].blocked {
+                    passages += 1;
+                }
+            }
+        }
+        (cells, passages)
+    }
+
+
+    #[test]
+    fn the_growing_tree_is_a_perfect_maze() {
+        for seed in 0..4 {
+            let mut rng = GameRng::new(seed);
+            let map = growing_tree(WIDTH, HEIGHT, NEWEST, &mut rng);
+            let (cells, passages) = cells_and_passages(&map);
+            // every cell is carved, and a tree has one less edge than it has nodes
+            assert_eq!(cells, ((WIDTH - 1) / 2 * ((HEIGHT - 1) / 2)) as usize);
+            assert_eq!(passages, cells - 1, "seed {}", seed);
+            assert!(unreachable_cells(&map, (1, 1)).is_empty());
+        }
+    }
+
+
+    #[test]
+    fn braiding_adds_loops_and_removes_dead_ends() {
+        for seed in 0..4 {
+            let mut rng = GameRng::new(seed);
+            let mut map = growing_tree(WIDTH, HEIGHT, NEWEST, &mut rng);
+            let ends_before = dead_ends(&map).len();
+            braid(&mut map, BRAID, &mut rng);
+
+            let (cells, passages) = cells_and_passages(&map);
+            assert!(passages > cells - 1, "seed {}", seed);
+            assert!(dead_ends(&map).len() < ends_before, "seed {}", seed);
+            assert!(unreachable_cells(&map, (1, 1)).is_empty());
+        }
+    }
+
+
+    #[test]
+    fn the_stairs_are_at_dead_ends() {
+        let mut rng = GameRng::new(7);
+        let mut objects: Vec<Vec<Object>> = vec![vec![]; 11];
+        let profile = profile_for(10);
+        let params = match &profile.generator {
+            Generator::Maze(params) => params,
+            _ => panic!("the Labyrinth isn't a maze"),
+        };
+        let (map, up, down) = maze(params, &profile.density).build(&mut objects, 10, profile.width, profile.height, &mut rng);
+        let ends = dead_ends(&map);
+        assert!(ends.contains(&up) && ends.contains(&down));
+        assert!(distances(&map, down)[up.0 as usize][up.1 as usize].unwrap() > 0);
+    }
+}
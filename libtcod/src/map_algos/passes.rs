@@ -0,0 +1,462 @@
+/*
+  Passes to chain on after a map builder: they place the stairs, smooth the
+  walls, fill the floor with NPCs and items (and give some of the NPCs
+  something to do), and add doors and pools.
+ */
+// Import Third-Party
+use rand::Rng;
+
+// Import Locally
+use crate::constants::PATROL_STOPS;
+use crate::loot_table::generate_floor_item;
+use crate::map::distances;
+use crate::map::Map;
+use crate::map::Tile;
+use crate::map::TileKind;
+use crate::map_algos::builder::BuildData;
+use crate::map_algos::builder::MapBuilder;
+use crate::npc_table::generate_npc;
+use crate::objects::Ai;
+use crate::profiles::Density;
+use crate::rng::GameRng;
+use crate::transition::from_map_level;
+
+
+// every tile that can be walked on
+pub fn floor_tiles(map: &Map) -> Vec<(i32, i32)> {
+    map.iter()
+        .filter(|(_, tile)| !tile.blocked)
+        .map(|(pos, _)| pos)
+        .collect()
+}
+
+
+// Nothing spawns on the stairs, or on top of anything else that blocks.
+fn is_free(data: &BuildData, x: i32, y: i32) -> bool {
+    let taken: bool = data.spawns.iter().any(|object| object.blocks && object.pos() == (x, y));
+    !data.map.is_wall(x, y) && !taken && (x, y) != data.down_stairs && (x, y) != data.up_stairs
+}
+
+
+// Arrive in the center of the first room, and leave from the center of the last.
+pub struct RoomStairs;
+
+impl MapBuilder for RoomStairs {
+    fn build(&mut self, data: &mut BuildData, _rng: &mut GameRng) {
+        data.down_stairs = data.rooms[0].center();
+        data.up_stairs = data.rooms[data.rooms.len() - 1].center();
+    }
+}
+
+
+// Arrive anywhere, and the way up is as far away as the floor allows.
+pub struct FarthestStairs;
+
+impl MapBuilder for FarthestStairs {
+    fn build(&mut self, data: &mut BuildData, rng: &mut GameRng) {
+        let floor = floor_tiles(&data.map);
+        data.down_stairs = floor[rng.gen_range(0, floor.len())];
+        let dist = distances(&data.map, data.down_stairs);
+        data.up_stairs = *floor
+            .iter()
+            .max_by_key(|&&(x, y)| dist[x as usize][y as usize].unwrap_or(0))
+            .unwrap();
+    }
+}
+
+
+// Cellular automata: a tile becomes wall when most of its neighbors are walls,
+// and floor when most aren't.
+pub struct Smoothing {
+    pub steps: u32,
+    // in the first few steps, big empty areas also get a wall, so there are no vast plains
+    pub rough_steps: u32,
+}
+
+impl Smoothing {
+    // count the walls within `radius` of a tile, itself included (off the map counts as wall)
+    fn walls_near(map: &Map, x: i32, y: i32, radius: i32) -> u32 {
+        let mut count: u32 = 0;
+        for nx in (x - radius)..(x + radius + 1) {
+            for ny in (y - radius)..(y + radius + 1) {
+                if map.is_wall(nx, ny) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    fn smooth(map: &Map, rough: bool) -> Map {
+        let mut next: Map = map.clone();
+        for x in 1..map.width - 1 {
+            for y in 1..map.height - 1 {
+                let near: u32 = Smoothing::walls_near(map, x, y, 1);
+                let wall: bool = near >= 5 || (rough && Smoothing::walls_near(map, x, y, 2) <= 2);
+                next[(x, y)] = if wall { Tile::wall() } else { Tile::empty() };
+            }
+        }
+        next
+    }
+}
+
+impl MapBuilder for Smoothing {
+    fn build(&mut self, data: &mut BuildData, _rng: &mut GameRng) {
+        for step in 0..self.steps {
+            data.map = Smoothing::smooth(&data.map, step < self.rough_steps);
+        }
+    }
+}
+
+
+// A random number of NPCs and items in every room but the first
+// (the density is the most of each in one room).
+pub struct RoomSpawner {
+    density: Density,
+}
+
+impl RoomSpawner {
+    pub fn new(density: &Density) -> Self {
+        RoomSpawner { density: density.clone() }
+    }
+}
+
+impl MapBuilder for RoomSpawner {
+    fn build(&mut self, data: &mut BuildData, rng: &mut GameRng) {
+        let level = data.level as u32;
+        let max_npcs = from_map_level(&self.density.npcs, level);
+        let max_items = from_map_level(&self.density.items, level);
+
+        for room in data.rooms.clone().iter().skip(1) {
+            // choose random number of npcs
+            let num_npcs = rng.gen_range(0, max_npcs + 1);
+            for _ in 0..num_npcs {
+                // choose random spot for this npc
+                let x = rng.gen_range(room.x1 + 1, room.x2);
+                let y = rng.gen_range(room.y1 + 1, room.y2);
+
+                // TODO: Also don't place the NPC if it is in FOV of the player
+                // only place it if the tile is not blocked
+                if is_free(data, x, y) {
+                    let mut npc = generate_npc(level as i32, rng);
+                    npc.set_pos(x, y);
+                    data.spawns.push(npc);
+                }
+            }
+
+            // choose random number of items
+            let num_items = rng.gen_range(0, max_items + 1);
+            for _ in 0..num_items {
+                // choose random spot for this item
+                let x = rng.gen_range(room.x1 + 1, room.x2);
+                let y = rng.gen_range(room.y1 + 1, room.y2);
+
+                // only place it if the tile is not blocked
+                if is_free(data, x, y) {
+                    let mut item = generate_floor_item(level as i32, rng);
+                    item.set_pos(x, y);
+                    data.spawns.push(item);
+                }
+            }
+        }
+    }
+}
+
+
+// Roll the dice on every floor tile to place an NPC or an item
+// (the density is chance-in-1000 for each tile).
+pub struct TileSpawner {
+    density: Density,
+    // no NPCs spawn this close to where the player arrives
+    safe_radius: i32,
+}
+
+impl TileSpawner {
+    pub fn new(density: &Density, safe_radius: i32) -> Self {
+        TileSpawner {
+            density: density.clone(),
+            safe_radius,
+        }
+    }
+}
+
+impl MapBuilder for TileSpawner {
+    fn build(&mut self, data: &mut BuildData, rng: &mut GameRng) {
+        let level = data.level as u32;
+        let npc_chance: u32 = from_map_level(&self.density.npcs, level);
+        let item_chance: u32 = from_map_level(&self.density.items, level);
+        let (arrival_x, arrival_y) = data.down_stairs;
+
+        for (x, y) in floor_tiles(&data.map) {
+            if !is_free(data, x, y) {
+                continue;
+            }
+
+            let chance: u32 = rng.gen_range(0, 1000) as u32;
+            let dx = x - arrival_x;
+            let dy = y - arrival_y;
+            if chance < npc_chance && dx * dx + dy * dy > self.safe_radius * self.safe_radius {
+                let mut npc = generate_npc(level as i32, rng);
+                npc.set_pos(x, y);
+                data.spawns.push(npc);
+                continue;
+            }
+
+            let chance: u32 = rng.gen_range(0, 1000) as u32;
+            if chance < item_chance {
+                let mut item = generate_floor_item(level as i32, rng);
+                item.set_pos(x, y);
+                data.spawns.push(item);
+            }
+        }
+    }
+}
+
+
+// Hang a door in some of the doorways into the rooms: gaps in a room's wall
+// with wall on either side.
+pub struct Doors {
+    // chance-in-100 for each doorway
+    pub percent: u32,
+}
+
+impl Doors {
+    fn is_doorway(map: &Map, x: i32, y: i32) -> bool {
+        let wall = |dx: i32, dy: i32| map[(x + dx, y + dy)].blocked;
+        let floor = |dx: i32, dy: i32| map[(x + dx, y + dy)].kind == TileKind::Floor;
+        map[(x, y)].kind == TileKind::Floor
+            && ((wall(-1, 0) && wall(1, 0) && floor(0, -1) && floor(0, 1))
+                || (wall(0, -1) && wall(0, 1) && floor(-1, 0) && floor(1, 0)))
+    }
+}
+
+impl MapBuilder for Doors {
+    fn build(&mut self, data: &mut BuildData, rng: &mut GameRng) {
+        for room in data.rooms.clone() {
+            // every tile of the room's wall
+            let mut ring: Vec<(i32, i32)> = vec![];
+            for x in room.x1..room.x2 + 1 {
+                ring.push((x, room.y1));
+                ring.push((x, room.y2));
+            }
+            for y in room.y1 + 1..room.y2 {
+                ring.push((room.x1, y));
+                ring.push((room.x2, y));
+            }
+
+            for (x, y) in ring {
+                if data.map.in_interior(x, y) && is_free(data, x, y) && Doors::is_doorway(&data.map, x, y) && rng.gen_range(0, 100) < self.percent {
+                    data.map[(x, y)] = Tile::door();
+                }
+            }
+        }
+    }
+}
+
+
+// Pools of water, lava or chasm: a few blobs, each grown by wandering randomly
+// over the floor. They never cover the stairs, or anything already placed
+// (and connect_map keeps the floor walkable around the lava and chasms).
+pub struct Pools {
+    pub kind: TileKind,
+    pub count: u32,
+    // how many steps to wander, for each pool
+    pub size: u32,
+}
+
+impl MapBuilder for Pools {
+    fn build(&mut self, data: &mut BuildData, rng: &mut GameRng) {
+        // there's no floor below the bottom one, to fall into
+        if self.kind == TileKind::Chasm && data.level == 0 {
+            return;
+        }
+
+        // nor under any item: in lava or down a chasm, it could never be picked up
+        let can_flood = |data: &BuildData, x: i32, y: i32| {
+            data.map[(x, y)].kind == TileKind::Floor
+                && is_free(data, x, y)
+                && !data.spawns.iter().any(|object| object.pos() == (x, y))
+        };
+        for _ in 0..self.count {
+            let floor: Vec<(i32, i32)> = floor_tiles(&data.map)
+                .into_iter()
+                .filter(|&(x, y)| can_flood(data, x, y))
+                .collect();
+            if floor.is_empty() {
+                return;
+            }
+
+            let (mut x, mut y) = floor[rng.gen_range(0, floor.len())];
+            for _ in 0..self.size {
+                if can_flood(data, x, y) {
+                    data.map[(x, y)] = Tile::of_kind(self.kind);
+                }
+                // wander, but not into the walls
+                let nx = x + rng.gen_range(-1, 2);
+                let ny = y + rng.gen_range(-1, 2);
+                if !data.map.is_wall(nx, ny) {
+                    (x, y) = (nx, ny);
+                }
+            }
+        }
+    }
+}
+
+
+// Some of the NPCs don't just wait for the player: they patrol from room to
+// room (or between spots in the open), wander about, or guard where they stand.
+pub struct Behaviours {
+    pub patrol_percent: u32,
+    pub wander_percent: u32,
+    pub guard_percent: u32,
+}
+
+impl MapBuilder for Behaviours {
+    fn build(&mut self, data: &mut BuildData, rng: &mut GameRng) {
+        // where a patrol can stop: the middle of every room, or anywhere in the open
+        let mut stops: Vec<(i32, i32)> = data.rooms.iter().map(|room| room.center()).collect();
+        if stops.len() < PATROL_STOPS {
+            stops = floor_tiles(&data.map);
+        }
+        let map = &data.map;
+        stops.retain(|&(x, y)| map[(x, y)].passable());
+
+        for npc in data.spawns.iter_mut().filter(|object| object.ai == Some(Ai::Basic)) {
+            let roll: u32 = rng.gen_range(0, 100);
+            if roll < self.patrol_percent && !stops.is_empty() {
+                // start from where it stands, then make the rounds
+                let mut route: Vec<(i32, i32)> = vec![npc.pos()];
+                for _ in 0..PATROL_STOPS {
+                    route.push(stops[rng.gen_range(0, stops.len())]);
+                }
+                npc.ai = Some(Ai::Patrol { route, next: 1 });
+            } else if roll < self.patrol_percent + self.wander_percent {
+                npc.ai = Some(Ai::Wander { goal: npc.pos() });
+            } else if roll < self.patrol_percent + self.wander_percent + self.guard_percent {
+                npc.ai = Some(Ai::Guard { post: npc.pos() });
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::MAP_HEIGHT;
+    use crate::constants::MAP_WIDTH;
+    use crate::map_algos::builder::BuilderChain;
+    use crate::map_algos::common::carve_h_tunnel;
+    use crate::map_algos::common::carve_room;
+    use crate::map_algos::common::Rect;
+    use crate::objects::Object;
+    use crate::transition::Transition;
+    use tcod::colors::WHITE;
+
+    // two rooms, and nothing joining them
+    struct TwoRooms;
+
+    impl MapBuilder for TwoRooms {
+        fn build(&mut self, data: &mut BuildData, _rng: &mut GameRng) {
+            data.rooms = vec![Rect::new(2, 2, 8, 8), Rect::new(30, 20, 10, 10)];
+            for &room in data.rooms.iter() {
+                carve_room(room, &mut data.map);
+            }
+        }
+    }
+
+
+    fn density(npcs: u32, items: u32) -> Density {
+        Density {
+            npcs: vec![Transition { level: 0, value: npcs }],
+            items: vec![Transition { level: 0, value: items }],
+        }
+    }
+
+
+    #[test]
+    fn the_chain_runs_in_order_and_places_the_stairs() {
+        let mut rng = GameRng::new(1);
+        let mut objects: Vec<Vec<Object>> = vec![vec![]; 3];
+        let (map, up, down) = BuilderChain::new(TwoRooms)
+            .with(RoomStairs)
+            .with(RoomSpawner::new(&density(5, 5)))
+            .build(&mut objects, 2, MAP_WIDTH, MAP_HEIGHT, &mut rng);
+
+        assert_eq!(down, Rect::new(2, 2, 8, 8).center());
+        assert_eq!(up, Rect::new(30, 20, 10, 10).center());
+        assert!(!map[up].blocked);
+        assert_eq!(objects[2][0].name, "down-stairs");
+        assert_eq!(objects[2][1].name, "up-stairs");
+        // the spawns all land in the second room, and never on the stairs
+        let spawns = &objects[2][2..];
+        assert!(!spawns.is_empty());
+        assert!(spawns.iter().all(|o| o.x > 30 && o.y > 20 && o.pos() != up));
+    }
+
+
+    #[test]
+    fn tile_spawner_keeps_clear_of_the_arrival() {
+        let mut rng = GameRng::new(2);
+        let mut data = BuildData::new(1, MAP_WIDTH, MAP_HEIGHT);
+        TwoRooms.build(&mut data, &mut rng);
+        FarthestStairs.build(&mut data, &mut rng);
+        TileSpawner::new(&density(1000, 0), 4).build(&mut data, &mut rng);
+
+        let (x, y) = data.down_stairs;
+        assert!(!data.spawns.is_empty());
+        for npc in data.spawns.iter() {
+            assert!((npc.x - x).pow(2) + (npc.y - y).pow(2) > 16);
+            assert!(npc.pos() != data.up_stairs);
+        }
+    }
+
+    #[test]
+    fn doors_go_in_the_doorways() {
+        let mut rng = GameRng::new(3);
+        let mut data = BuildData::new(1, MAP_WIDTH, MAP_HEIGHT);
+        data.rooms = vec![Rect::new(2, 2, 8, 8), Rect::new(20, 2, 8, 8)];
+        for &room in data.rooms.iter() {
+            carve_room(room, &mut data.map);
+        }
+        carve_h_tunnel(5, 25, 6, &mut data.map);
+        Doors { percent: 100 }.build(&mut data, &mut rng);
+
+        let doors: Vec<(i32, i32)> = data
+            .map
+            .positions()
+            .filter(|&pos| matches!(data.map[pos].kind, TileKind::Door { .. }))
+            .collect();
+        assert_eq!(doors, vec![(10, 6), (20, 6)]);
+    }
+
+
+    #[test]
+    fn pools_stay_off_the_stairs_and_the_loot() {
+        let mut rng = GameRng::new(6);
+        let mut data = BuildData::new(1, MAP_WIDTH, MAP_HEIGHT);
+        TwoRooms.build(&mut data, &mut rng);
+        RoomStairs.build(&mut data, &mut rng);
+        let mut potion = Object::new(0, 0, '!', "healing potion", WHITE, false);
+        potion.set_pos(5, 5);
+        data.spawns.push(potion);
+        Pools { kind: TileKind::Lava, count: 6, size: 400 }.build(&mut data, &mut rng);
+
+        let kind = |pos: (i32, i32)| data.map[pos].kind;
+        assert!(floor_tiles(&data.map).into_iter().any(|tile| kind(tile) == TileKind::Lava));
+        assert_eq!(kind(data.down_stairs), TileKind::Floor);
+        assert_eq!(kind(data.up_stairs), TileKind::Floor);
+        assert_eq!(kind((5, 5)), TileKind::Floor);
+    }
+
+
+    #[test]
+    fn the_bottom_floor_has_no_chasms() {
+        let mut rng = GameRng::new(7);
+        let mut data = BuildData::new(0, MAP_WIDTH, MAP_HEIGHT);
+        TwoRooms.build(&mut data, &mut rng);
+        Pools { kind: TileKind::Chasm, count: 6, size: 400 }.build(&mut data, &mut rng);
+        assert!(floor_tiles(&data.map).into_iter().all(|pos| data.map[pos].kind == TileKind::Floor));
+    }
+}
+
@@ -0,0 +1,411 @@
+/*
+  Prefab Rooms and Vaults
+
+  Hand-drawn rooms, each in its own file in PREFAB_DIR: a layout drawn in
+  glyphs, a legend saying what each glyph is, and how often the room turns
+  up (by level). They are read at startup; without that directory, the
+  copies built into the game are used. Legend entries can be:
+
+    wall, floor         the tile
+    npc, item           a random one for the level, on floor
+    npc:NAME, item:NAME that very one, on floor
+
+  A space in the layout leaves whatever was already there.
+
+  The PrefabRooms pass stamps them, turned and flipped at random, into a
+  room (if the builder made rooms) or else into solid rock.
+ */
+// Import Std Libs
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+// Import Third-Party
+use rand::Rng;
+use serde::Deserialize;
+
+// Import Locally
+use crate::loot_table::generate_floor_item;
+use crate::loot_table::item_by_name;
+use crate::map::Tile;
+use crate::map_algos::builder::BuildData;
+use crate::map_algos::builder::MapBuilder;
+use crate::npc_table::generate_npc;
+use crate::npc_table::npc_by_name;
+use crate::rng::GameRng;
+use crate::transition::from_map_level;
+use crate::transition::Transition;
+
+const BUILT_IN_PREFABS: [&str; 4] = [
+    include_str!("../../prefabs/pillared_hall.toml"),
+    include_str!("../../prefabs/shrine.toml"),
+    include_str!("../../prefabs/troll_den.toml"),
+    include_str!("../../prefabs/vault.toml"),
+];
+
+static PREFABS: OnceLock<Vec<Prefab>> = OnceLock::new();
+
+// how many random spots in solid rock to try, before giving up on a prefab
+const ROCK_ATTEMPTS: u32 = 200;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Glyph {
+    Keep,
+    Wall,
+    Floor,
+    // a named NPC or item, or None for a random one
+    Npc(Option<String>),
+    Item(Option<String>),
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Prefab {
+    pub name: String,
+    // chance-in-100 that a floor gets one, by level
+    pub frequency: Vec<Transition>,
+    // indexed [y][x], like the file
+    pub rows: Vec<Vec<Glyph>>,
+}
+
+impl Prefab {
+    pub fn width(&self) -> i32 {
+        self.rows[0].len() as i32
+    }
+
+    pub fn height(&self) -> i32 {
+        self.rows.len() as i32
+    }
+
+    // turned a quarter clockwise
+    fn rotated(&self) -> Prefab {
+        let mut rows: Vec<Vec<Glyph>> = vec![];
+        for x in 0..self.width() as usize {
+            rows.push(self.rows.iter().rev().map(|row| row[x].clone()).collect());
+        }
+        Prefab { rows, ..self.clone() }
+    }
+
+    // flipped left-to-right
+    fn mirrored(&self) -> Prefab {
+        let rows = self.rows.iter().map(|row| row.iter().rev().cloned().collect()).collect();
+        Prefab { rows, ..self.clone() }
+    }
+
+    // any of the 8 ways to turn and flip it
+    pub fn transformed(&self, turns: u32, mirror: bool) -> Prefab {
+        let mut prefab = if mirror { self.mirrored() } else { self.clone() };
+        for _ in 0..turns % 4 {
+            prefab = prefab.rotated();
+        }
+        prefab
+    }
+}
+
+
+#[derive(Deserialize)]
+struct PrefabFile {
+    name: String,
+    frequency: Vec<Transition>,
+    layout: String,
+    legend: HashMap<String, String>,
+}
+
+
+fn parse_glyph(meaning: &str) -> Result<Glyph, String> {
+    let glyph = match meaning.split_once(':') {
+        None if meaning == "wall" => Glyph::Wall,
+        None if meaning == "floor" => Glyph::Floor,
+        None if meaning == "npc" => Glyph::Npc(None),
+        None if meaning == "item" => Glyph::Item(None),
+        Some(("npc", name)) if npc_by_name(name).is_some() => Glyph::Npc(Some(name.to_string())),
+        Some(("item", name)) if item_by_name(name).is_some() => Glyph::Item(Some(name.to_string())),
+        _ => return Err(format!("no idea what \"{}\" is", meaning)),
+    };
+    Ok(glyph)
+}
+
+
+pub fn parse_prefab(text: &str) -> Result<Prefab, Box<dyn Error>> {
+    let file: PrefabFile = toml::from_str(text)?;
+
+    let mut legend: HashMap<char, Glyph> = HashMap::new();
+    legend.insert(' ', Glyph::Keep);
+    for (key, meaning) in file.legend.iter() {
+        let mut chars = key.chars();
+        match (chars.next(), chars.next()) {
+            (Some(chr), None) => legend.insert(chr, parse_glyph(meaning)?),
+            _ => return Err(format!("{}: \"{}\" should be a single glyph", file.name, key).into()),
+        };
+    }
+
+    // every row as wide as the widest, padded out with spaces
+    let lines: Vec<&str> = file.layout.lines().filter(|line| !line.trim().is_empty()).collect();
+    let width: usize = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+    if width == 0 {
+        return Err(format!("{}: the layout is empty", file.name).into());
+    }
+    let mut rows: Vec<Vec<Glyph>> = vec![];
+    for line in lines {
+        let mut row: Vec<Glyph> = vec![];
+        for chr in line.chars().chain(std::iter::repeat(' ')).take(width) {
+            match legend.get(&chr) {
+                Some(glyph) => row.push(glyph.clone()),
+                None => return Err(format!("{}: '{}' is not in the legend", file.name, chr).into()),
+            }
+        }
+        rows.push(row);
+    }
+
+    Ok(Prefab {
+        name: file.name,
+        frequency: file.frequency,
+        rows,
+    })
+}
+
+
+// every prefab in the directory, in file-name order (so the same seed gives the same floors)
+pub fn load_prefabs(dir: &Path) -> Result<Vec<Prefab>, Box<dyn Error>> {
+    let mut paths: Vec<_> = fs::read_dir(dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    paths.sort();
+
+    let mut prefabs: Vec<Prefab> = vec![];
+    for path in paths {
+        let prefab = parse_prefab(&fs::read_to_string(&path)?)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        prefabs.push(prefab);
+    }
+    Ok(prefabs)
+}
+
+
+fn built_in_prefabs() -> Vec<Prefab> {
+    BUILT_IN_PREFABS
+        .iter()
+        .map(|text| parse_prefab(text).expect("the built-in prefabs are broken"))
+        .collect()
+}
+
+
+// Read the prefabs from PREFAB_DIR, if there is one, before any floor gets built.
+// A broken one is handed back to the frontend, and the built-in prefabs are used instead.
+pub fn init_prefabs(dir: &Path) -> Result<(), Box<dyn Error>> {
+    let loaded = if dir.is_dir() { load_prefabs(dir) } else { Ok(built_in_prefabs()) };
+    match loaded {
+        Ok(prefabs) => {
+            let _ = PREFABS.set(prefabs);
+            Ok(())
+        }
+        Err(e) => {
+            let _ = PREFABS.set(built_in_prefabs());
+            Err(e)
+        }
+    }
+}
+
+
+// All the prefabs (the built-in ones, unless init_prefabs found others)
+pub fn all_prefabs() -> &'static [Prefab] {
+    PREFABS.get_or_init(built_in_prefabs)
+}
+
+
+// Stamp the prefab with its top-left corner at (x, y).
+fn stamp(prefab: &Prefab, x: i32, y: i32, data: &mut BuildData, rng: &mut GameRng) {
+    let level = data.level as i32;
+    for (dy, row) in prefab.rows.iter().enumerate() {
+        for (dx, glyph) in row.iter().enumerate() {
+            let (tx, ty) = (x + dx as i32, y + dy as i32);
+            let spawn = match glyph {
+                Glyph::Keep => continue,
+                Glyph::Wall => {
+                    data.map[(tx, ty)] = Tile::wall();
+                    continue;
+                }
+                Glyph::Floor => None,
+                Glyph::Npc(None) => Some(generate_npc(level, rng)),
+                Glyph::Npc(Some(name)) => npc_by_name(name),
+                Glyph::Item(None) => Some(generate_floor_item(level, rng)),
+                Glyph::Item(Some(name)) => item_by_name(name),
+            };
+            data.map[(tx, ty)] = Tile::empty();
+            if let Some(mut object) = spawn {
+                object.set_pos(tx, ty);
+                data.spawns.push(object);
+            }
+        }
+    }
+}
+
+
+// Somewhere for the prefab: centered in a room the player doesn't arrive or
+// leave from (and no other prefab took), or else anywhere in solid rock
+// (connect_map digs the way in).
+fn find_spot(prefab: &Prefab, data: &BuildData, used: &mut Vec<usize>, rng: &mut GameRng) -> Option<(i32, i32)> {
+    let (w, h) = (prefab.width(), prefab.height());
+
+    if data.rooms.len() > 2 {
+        let mut candidates: Vec<usize> = (1..data.rooms.len() - 1)
+            .filter(|&i| {
+                let room = data.rooms[i];
+                !used.contains(&i) && room.x2 - room.x1 > w && room.y2 - room.y1 > h
+            })
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        let i = candidates.swap_remove(rng.gen_range(0, candidates.len()));
+        let room = data.rooms[i];
+        used.push(i);
+        let x = room.x1 + 1 + (room.x2 - room.x1 - 1 - w) / 2;
+        let y = room.y1 + 1 + (room.y2 - room.y1 - 1 - h) / 2;
+        return Some((x, y));
+    }
+
+    let (map_width, map_height) = (data.map.width, data.map.height);
+    if w + 2 >= map_width - 1 || h + 2 >= map_height - 1 {
+        return None;
+    }
+    for _ in 0..ROCK_ATTEMPTS {
+        let x = rng.gen_range(2, map_width - w - 1);
+        let y = rng.gen_range(2, map_height - h - 1);
+        // solid rock, with a wall all the way around it
+        let solid = (x - 1..x + w + 1).all(|tx| (y - 1..y + h + 1).all(|ty| data.map[(tx, ty)].blocked));
+        if solid {
+            return Some((x, y));
+        }
+    }
+    None
+}
+
+
+// Roll for each prefab, and stamp the ones that come up somewhere they fit.
+pub struct PrefabRooms;
+
+impl MapBuilder for PrefabRooms {
+    fn build(&mut self, data: &mut BuildData, rng: &mut GameRng) {
+        let mut used: Vec<usize> = vec![];
+        for prefab in all_prefabs() {
+            let chance = from_map_level(&prefab.frequency, data.level as u32);
+            if rng.gen_range(0, 100) >= chance {
+                continue;
+            }
+
+            let turned = prefab.transformed(rng.gen_range(0, 4), rng.gen());
+            if let Some((x, y)) = find_spot(&turned, data, &mut used, rng) {
+                stamp(&turned, x, y, data, rng);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::MAP_HEIGHT;
+    use crate::constants::MAP_WIDTH;
+    use crate::map_algos::common::carve_room;
+    use crate::map_algos::common::Rect;
+    use tempfile::TempDir;
+
+    const L_SHAPE: &str = r##"
+name = "l shape"
+frequency = [{ level = 0, value = 100 }]
+layout = """
+#.
+#!
+##
+"""
+
+[legend]
+"#" = "wall"
+"." = "floor"
+"!" = "item:healing potion"
+"##;
+
+
+    #[test]
+    fn the_built_in_prefabs_parse() {
+        assert_eq!(built_in_prefabs().len(), BUILT_IN_PREFABS.len());
+        let prefab = parse_prefab(L_SHAPE).unwrap();
+        assert_eq!((prefab.width(), prefab.height()), (2, 3));
+        assert_eq!(prefab.rows[1][1], Glyph::Item(Some("healing potion".into())));
+    }
+
+
+    #[test]
+    fn turning_and_flipping_keeps_every_glyph() {
+        let prefab = parse_prefab(L_SHAPE).unwrap();
+        let turned = prefab.transformed(1, false);
+        assert_eq!((turned.width(), turned.height()), (3, 2));
+        // a quarter clockwise puts the bottom-left corner at the top-left
+        assert_eq!(turned.rows[0], vec![Glyph::Wall, Glyph::Wall, Glyph::Wall]);
+        assert_eq!(turned.rows[1], vec![Glyph::Wall, Glyph::Item(Some("healing potion".into())), Glyph::Floor]);
+        assert_eq!(prefab.transformed(4, false).rows, prefab.rows);
+        assert_eq!(prefab.transformed(0, true).rows[0], vec![Glyph::Floor, Glyph::Wall]);
+    }
+
+
+    #[test]
+    fn bad_prefabs_are_refused() {
+        assert!(parse_prefab(&L_SHAPE.replace("\"!\" = \"item:healing potion\"", "")).is_err());
+        assert!(parse_prefab(&L_SHAPE.replace("item:healing potion", "item:cake")).is_err());
+        assert!(parse_prefab(&L_SHAPE.replace("\"#\" = \"wall\"", "\"##\" = \"wall\"")).is_err());
+        assert!(parse_prefab(&L_SHAPE.replace("#.\n#!\n##", "")).is_err());
+
+        // and a broken file is an error, naming the file
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("good.toml"), L_SHAPE).unwrap();
+        assert_eq!(load_prefabs(dir.path()).unwrap().len(), 1);
+        fs::write(dir.path().join("bad.toml"), "name = ").unwrap();
+        assert!(load_prefabs(dir.path()).unwrap_err().to_string().contains("bad.toml"));
+    }
+
+
+    #[test]
+    fn a_prefab_goes_in_a_middle_room() {
+        let mut rng = GameRng::new(4);
+        let mut data = BuildData::new(3, MAP_WIDTH, MAP_HEIGHT);
+        data.rooms = vec![Rect::new(1, 1, 6, 6), Rect::new(20, 10, 8, 8), Rect::new(40, 20, 6, 6)];
+        for &room in data.rooms.iter() {
+            carve_room(room, &mut data.map);
+        }
+        let prefab = parse_prefab(L_SHAPE).unwrap();
+        let mut used: Vec<usize> = vec![];
+
+        let (x, y) = find_spot(&prefab, &data, &mut used, &mut rng).unwrap();
+        assert_eq!(used, vec![1]);
+        stamp(&prefab, x, y, &mut data, &mut rng);
+        assert!(data.map[(x, y)].blocked);
+        assert!(!data.map[(x + 1, y)].blocked);
+        assert_eq!(data.spawns.len(), 1);
+        assert_eq!(data.spawns[0].pos(), (x + 1, y + 1));
+
+        // the middle room is taken now, and the others are where the stairs go
+        assert_eq!(find_spot(&prefab, &data, &mut used, &mut rng), None);
+    }
+
+
+    #[test]
+    fn without_rooms_a_prefab_goes_in_solid_rock() {
+        let mut rng = GameRng::new(5);
+        let data = BuildData::new(3, MAP_WIDTH, MAP_HEIGHT);
+        let prefab = parse_prefab(L_SHAPE).unwrap();
+        let (x, y) = find_spot(&prefab, &data, &mut vec![], &mut rng).unwrap();
+        assert!(x >= 2 && y >= 2 && x + prefab.width() < data.map.width && y + prefab.height() < data.map.height);
+
+        // one too big for the map doesn't go anywhere
+        let mut huge = prefab.clone();
+        huge.rows = vec![vec![Glyph::Floor; data.map.width as usize]; 3];
+        assert_eq!(find_spot(&huge, &data, &mut vec![], &mut rng), None);
+    }
+}
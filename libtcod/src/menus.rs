@@ -1,64 +1,91 @@
 /*
   General Menu Tools
  */
+// Import Std Libs
+use std::path::Path;
+
 // Import Third-Party
-use serde::{Deserialize, Serialize};
 use tcod::colors::*;
 use tcod::console::*;
-use tcod::input::{Key, Mouse};
-use tcod::map::{Map as FovMap};
+use tcod::input::{self, Event, Key, Mouse};
 
 // Import Locally
-use crate::constants::HELP_SCREEN_WIDTH;
-use crate::constants::INVENTORY_KEYS;
-use crate::constants::INVENTORY_MAX;
-use crate::constants::INVENTORY_WIDTH;
-use crate::constants::SCREEN_HEIGHT;
-use crate::constants::SCREEN_WIDTH;
-use crate::objects::Object;
+use feb2::constants::CHARACTER_SCREEN_WIDTH;
+use feb2::constants::HELP_SCREEN_WIDTH;
+use feb2::constants::INVENTORY_KEYS;
+use feb2::constants::INVENTORY_MAX;
+use feb2::constants::LVL_NAMES;
+use feb2::constants::PLAYER;
+use feb2::constants::SAVE_DIR;
+use feb2::constants::SCREEN_HEIGHT;
+use feb2::constants::SCREEN_WIDTH;
+use feb2::constants::SLOT_NAME_MAX;
+use feb2::constants::SLOTS_SCREEN_WIDTH;
+use feb2::engine::Ui;
+use feb2::objects::Game;
+use feb2::objects::Object;
+use feb2::player::character_sheet;
+use feb2::savegame::convert_slot;
+use feb2::savegame::delete_slot;
+use feb2::savegame::list_slots;
+use feb2::savegame::rename_slot;
+use feb2::savegame::SaveFormat;
+use crate::replay::choose;
+use crate::replay::list_replays;
+use crate::replay::target;
+use crate::replay::InputLog;
+use crate::ui::render_all;
 
 
 pub struct Tcod {
     pub root: Root,
     pub con: Offscreen,
     pub panel: Offscreen,
-    pub fov: FovMap,
     pub key: Key,
     pub mouse: Mouse,
+    pub log: InputLog,
 }
 
 
-pub fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Option<usize> {
-    // show a menu with each item of the inventory as an option
-    let options = if inventory.len() == 0 {
-        vec!["Inventory is empty.".into()]
-    } else {
-        inventory
-            .iter()
-            .map(|thing| {
-                // show additional information, in case it's equipped
-                match thing.equipment {
-                    Some(equipment) if equipment.equipped => {
-                        format!("{} (on {})", thing.name, equipment.slot)
-                    }
-                    _ => match thing.item {
-                        Some(_item) if thing.charges > 1 => {
-                            format!("{} ({})", thing.name, thing.charges)
-                        }
-                        _ => thing.name.clone(),
-                    }
+// The tcod window answers the game's questions (and records the answers, or replays them).
+impl Ui for Tcod {
+    fn menu(&mut self, game: &mut Game, header: &str, options: &[String], width: i32) -> Option<usize> {
+        choose(self, game, |tcod, _game| menu(header, options, width, &mut tcod.root))
+    }
+
+    // Return the position of a tile left-clicked in player's FOV (optionally in a range),
+    // or None if right-clicked.
+    fn target_tile(&mut self, game: &mut Game, objects: &[Object], max_range: Option<f32>) -> Option<(i32, i32)> {
+        use tcod::input::KeyCode::Escape;
+        // a replay already knows where the player aimed
+        target(self, game, |tcod, game| {
+            loop {
+                // render the screen. this erases the inventory and shows the names of
+                // objects under the mouse.
+                tcod.root.flush();
+                let event = input::check_for_event(input::KEY_PRESS | input::MOUSE).map(|e| e.1);
+                match event {
+                    Some(Event::Mouse(m)) => tcod.mouse = m,
+                    Some(Event::Key(k)) => tcod.key = k,
+                    None => tcod.key = Default::default(),
                 }
-            })
-            .collect()
-    };
+                render_all(tcod, game, objects);
 
-    let inventory_index = menu(header, &options, INVENTORY_WIDTH, root);
+                let (x, y) = (tcod.mouse.cx as i32, tcod.mouse.cy as i32);
 
-    // if an item was chosen, return it
-    if inventory.len() > 0 {
-        return inventory_index;
-    } else {
-        return None;
+                // accept the target if the player clicked in FOV, and in case a range
+                // is specified, if it's in that range
+                let in_fov = game.maps[game.lvl].in_bounds(x, y) && game.fov.is_in_fov(x, y);
+                let in_range = max_range.is_none_or(|range| objects[PLAYER].distance(x, y) <= range);
+                if tcod.mouse.lbutton_pressed && in_fov && in_range {
+                    return Some((x, y));
+                }
+
+                if tcod.mouse.rbutton_pressed || tcod.key.code == Escape {
+                    return None; // cancel if the player right-clicked or pressed Escape
+                }
+            }
+        })
     }
 }
 
@@ -116,10 +143,10 @@ pub fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut R
 
     // convert the ASCII code to an index; if it corresponds to an option, return it
     let index = INVENTORY_KEYS.find(key.printable);
-    if index.is_some() && index.unwrap() < options.len() as usize {
-        return index;
+    if index.is_some() && index.unwrap() < options.len() {
+        index
     } else {
-        return None;
+        None
     }
 }
 
@@ -130,9 +157,145 @@ pub fn msgbox(text: &str, width: i32, root: &mut Root) {
 }
 
 
+// Ask the player to type a line of text. Enter accepts it, Escape cancels.
+pub fn text_input(header: &str, initial: &str, max_len: usize, width: i32, root: &mut Root) -> Option<String> {
+    use tcod::input::KeyCode::{Backspace, Enter, Escape, NumPadEnter};
+    let mut text: String = initial.to_string();
+
+    loop {
+        // the header (after auto-wrap), a blank line, then the text being typed
+        let header_height = root.get_height_rect(0, 0, width, SCREEN_HEIGHT, header);
+        let height = header_height + 2;
+        let mut window = Offscreen::new(width, height);
+        window.set_default_foreground(WHITE);
+        window.print_rect_ex(
+            0,
+            0,
+            width,
+            height,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            header,
+        );
+        window.print_ex(
+            0,
+            header_height + 1,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            format!("> {}_", text),
+        );
+
+        let x: i32 = SCREEN_WIDTH / 2 - width / 2;
+        let y: i32 = SCREEN_HEIGHT / 2 - height / 2;
+        blit(&window, (0, 0), (width, height), root, (x, y), 1.0, 0.9);
+        root.flush();
+
+        let key = root.wait_for_keypress(true);
+        match key.code {
+            Enter | NumPadEnter => return Some(text.trim().to_string()),
+            Escape => return None,
+            Backspace => {
+                text.pop();
+            }
+            _ => {
+                if !key.printable.is_control() && text.len() < max_len {
+                    text.push(key.printable);
+                }
+            }
+        }
+    }
+}
+
+
+// Browse the saved games. Returns the name of the slot to load, if one was picked.
+pub fn load_game_menu(root: &mut Root) -> Option<String> {
+    loop {
+        let slots = list_slots(Path::new(SAVE_DIR));
+        if slots.is_empty() {
+            msgbox("\nNo saved game to load.\n", 24, root);
+            return None;
+        }
+
+        let options: Vec<String> = slots
+            .iter()
+            .take(INVENTORY_MAX)
+            .map(|slot| {
+                if slot.loadable {
+                    // a save from a newer game may have more floors than this one knows
+                    let lvl_name: &str = LVL_NAMES.get(slot.lvl).copied().unwrap_or("?");
+                    let backup: &str = if slot.backup_only { " (backup)" } else { "" };
+                    format!(
+                        "{:<16} Day {:<3} Turn {:<6} {:<13} Karma {:<5} {}{}",
+                        slot.name, slot.day, slot.turn, lvl_name, slot.karma, slot.format, backup
+                    )
+                } else {
+                    format!("{:<16} (cannot be loaded)", slot.name)
+                }
+            })
+            .collect();
+        let choice = menu("Choose a saved game:\n", &options, SLOTS_SCREEN_WIDTH, root);
+        let (name, format) = match choice {
+            Some(index) => (slots[index].name.clone(), slots[index].format),
+            None => return None,
+        };
+        let other_format = match format {
+            SaveFormat::Json => SaveFormat::Binary,
+            SaveFormat::Binary => SaveFormat::Json,
+        };
+
+        // what to do with the chosen game
+        let header = format!("{}\n", name);
+        let convert = format!("Convert to {}", other_format);
+        match menu(&header, &["Load", "Rename", "Delete", &convert, "Back"], 24, root) {
+            Some(0) => return Some(name),
+            Some(1) => {
+                let prompt = "Rename this saved game to:";
+                if let Some(new_name) = text_input(prompt, &name, SLOT_NAME_MAX, SLOTS_SCREEN_WIDTH / 2, root) {
+                    if let Err(e) = rename_slot(Path::new(SAVE_DIR), &name, &new_name) {
+                        msgbox(&format!("\n{}\n", e), SLOTS_SCREEN_WIDTH / 2, root);
+                    }
+                }
+            }
+            Some(2) => {
+                let header = format!("Delete \"{}\" forever?\n", name);
+                if menu(&header, &["No", "Yes"], 24, root) == Some(1) {
+                    if let Err(e) = delete_slot(Path::new(SAVE_DIR), &name) {
+                        msgbox(&format!("\n{}\n", e), SLOTS_SCREEN_WIDTH / 2, root);
+                    }
+                }
+            }
+            Some(3) => {
+                if let Err(e) = convert_slot(Path::new(SAVE_DIR), &name, other_format) {
+                    msgbox(&format!("\n{}\n", e), SLOTS_SCREEN_WIDTH / 2, root);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+
+// Pick a recorded run to watch.
+pub fn replay_menu(root: &mut Root) -> Option<String> {
+    let names: Vec<String> = list_replays(Path::new(SAVE_DIR)).into_iter().take(INVENTORY_MAX).collect();
+    if names.is_empty() {
+        msgbox("\nNo replays to watch.\n", 24, root);
+        return None;
+    }
+
+    let header = "Choose a replay to watch:\n('+'/'-' change the speed, space pauses, Escape stops)\n";
+    menu(header, &names, SLOTS_SCREEN_WIDTH / 2, root).map(|index| names[index].clone())
+}
+
+
+pub fn character_screen(tcod: &mut Tcod, player: &Object, game: &Game) {
+    let msg = character_sheet(player, game);
+    msgbox(&msg, CHARACTER_SCREEN_WIDTH, &mut tcod.root);
+}
+
+
 pub fn help_menu(tcod: &mut Tcod) {
-    let msg = format!(
-"Help Menu
+    let msg = "Help Menu
 
 Commands:
 
@@ -144,12 +307,13 @@ Commands:
 * 'd' drop item (from your inventory)
 * 'g' grab item from floor
 * 'i' view your inventory
-",
-            );
-            msgbox(&msg, HELP_SCREEN_WIDTH, &mut tcod.root);
+* 's' start/stop sneaking (slower, but quieter)
+";
+    msgbox(msg, HELP_SCREEN_WIDTH, &mut tcod.root);
 }
 
 
+#[allow(clippy::too_many_arguments)]
 pub fn render_bar(
     panel: &mut Offscreen,
     x: i32,
@@ -181,29 +345,6 @@ pub fn render_bar(
         y,
         BackgroundFlag::None,
         TextAlignment::Center,
-        &format!("{}: {}/{}", name, value, maximum),
+        format!("{}: {}/{}", name, value, maximum),
     );
 }
-
-
-#[derive(Serialize, Deserialize)]
-pub struct Messages {
-    messages: Vec<(String, Color)>,
-}
-
-
-impl Messages {
-    pub fn new() -> Self {
-        Self { messages: vec![] }
-    }
-
-    // add the new message as a tuple, with the text and the color
-    pub fn add<T: Into<String>>(&mut self, message: T, color: Color) {
-        self.messages.push((message.into(), color));
-    }
-
-    // Create a `DoubleEndedIterator` over the messages
-    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &(String, Color)> {
-        self.messages.iter()
-    }
-}
@@ -2,10 +2,21 @@
   Tools used to move the Player and NPCs
  */
 // Import Third-Party
+use serde::{Deserialize, Serialize};
+use tcod::colors::*;
+
+// Import Locally
+use crate::constants::ACTION_COST;
+use crate::constants::NOISE_WALK;
 use crate::constants::PLAYER;
+use crate::constants::SNEAK_EXTRA_COST;
+use crate::constants::WATER_MOVE_COST;
 use crate::map::Map;
+use crate::map::TileKind;
+use crate::objects::Awareness;
 use crate::objects::Game;
 use crate::objects::Object;
+use crate::spatial_index::SpatialIndex;
 use crate::utils::mut_two;
 
 
@@ -18,31 +29,84 @@ pub enum PlayerAction {
 }
 
 
-pub fn is_blocked(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
-    // first test the map tile
-    if map[x as usize][y as usize].blocked {
+// Everything the player can ask for, independent of which key asked for it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Command {
+    Move(i32, i32),
+    Wait,
+    Sneak,
+    GoUp,
+    GoDown,
+    CharacterScreen,
+    Inventory,
+    PickUp,
+    Drop,
+    Help,
+    Exit,
+}
+
+
+impl Command {
+    // screens that only show information can't change the course of the game
+    pub fn changes_game(self) -> bool {
+        !matches!(self, Command::CharacterScreen | Command::Help)
+    }
+}
+
+
+pub fn is_blocked(x: i32, y: i32, map: &Map, index: &SpatialIndex) -> bool {
+    // first test the map tile (closed doors block, until they're opened, and off the map is wall)
+    if map.is_wall(x, y) {
         return true;
     }
 
     // now check for any blocking objects
-    objects
-        .iter()
-        .any(|object| object.blocks && object.pos() == (x, y))
+    index.is_blocked(x, y)
 }
 
 
-// move by the given amount, if the destination is not blocked
-pub fn move_by(id: usize, dx: i32, dy: i32, map: &Map, objects: &mut [Object]) -> bool{
+// move by the given amount, if the destination is not blocked. Bumping into a
+// closed door opens it instead (which takes the move), and NPCs won't step
+// into lava or chasms. Stepping into water takes twice as long.
+pub fn move_by(id: usize, dx: i32, dy: i32, game: &mut Game, objects: &mut [Object]) -> bool{
     let (x, y) = objects[id].pos();
-    if !is_blocked(x + dx, y + dy, map, objects) {
-        objects[id].set_pos(x + dx, y + dy);
+    let (nx, ny) = (x + dx, y + dy);
+    let tile = match game.map().get(nx, ny) {
+        Some(&tile) => tile,
+        None => return false,
+    };
+    if tile.is_closed_door() {
+        objects[id].spend_energy(ACTION_COST);
+        game.map()[(nx, ny)].open_door();
+        game.fov.set(nx, ny, true, true);
+        if id == PLAYER {
+            game.messages.add("You open the door.", LIGHT_GREY);
+        }
+        return true;
+    }
+    if id != PLAYER && tile.is_hazard() {
+        return false;
+    }
+    if !is_blocked(nx, ny, &game.maps[game.lvl], &game.index) {
+        objects[id].set_pos(nx, ny);
+        game.index.moved(id, (x, y), (nx, ny), objects[id].blocks);
+        objects[id].spend_energy(if tile.kind == TileKind::Water { WATER_MOVE_COST } else { ACTION_COST });
         return true;
     }
-    return false;
+    false
 }
 
 
-pub fn move_towards(id: usize, target_x: i32, target_y: i32, map: &Map, objects: &mut [Object]) {
+// Take the next step on the shortest path to the target: around walls, pillars
+// and anyone in the way. With no path at all, just head straight for it.
+// Returns whether the step was taken.
+pub fn move_towards(id: usize, target_x: i32, target_y: i32, game: &mut Game, objects: &mut [Object]) -> bool {
+    let (x, y) = objects[id].pos();
+    let next = game.paths.next_step(objects[id].handle, (x, y), (target_x, target_y), &game.maps[game.lvl], &game.index);
+    if let Some((nx, ny)) = next {
+        return move_by(id, nx - x, ny - y, game, objects);
+    }
+
     // vector from this object to the target, and distance
     let dx = target_x - objects[id].x;
     let dy = target_y - objects[id].y;
@@ -52,7 +116,7 @@ pub fn move_towards(id: usize, target_x: i32, target_y: i32, map: &Map, objects:
     // convert to integer so the movement is restricted to the map grid
     let dx = (dx as f32 / distance).round() as i32;
     let dy = (dy as f32 / distance).round() as i32;
-    move_by(id, dx, dy, map, objects);
+    move_by(id, dx, dy, game, objects)
 }
 
 
@@ -64,9 +128,19 @@ pub fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut [O
     let y = objects[PLAYER].y + dy;
 
     // try to find an attackable object there
-    let target_id = objects
+    let target_id = game.index
+        .at(x, y)
         .iter()
-        .position(|object| object.fighter.is_some() && object.pos() == (x, y));
+        .copied()
+        .find(|&id| objects[id].fighter.is_some());
+
+    // sneaking, the player trades places with anyone who hasn't noticed them, rather than attacking
+    if let Some(target_id) = target_id {
+        let unaware = objects[target_id].ai.as_ref().is_some_and(|ai| ai.awareness() == Awareness::Unaware);
+        if game.sneaking && unaware {
+            return slip_past(target_id, game, objects);
+        }
+    }
 
     // attack if target found, move otherwise
     match target_id {
@@ -75,14 +149,42 @@ pub fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut [O
             player.melee_attack(target, game);
             // TRYING to attack reduces karma
             player.fighter.as_mut().unwrap().karma -= 1;
-            return TookTurn;
+            TookTurn
         }
         None => {
-            if move_by(PLAYER, dx, dy, &game.map(), objects) {
-                return TookTurn;
+            if move_by(PLAYER, dx, dy, game, objects) {
+                // sneaking is slow, but silent
+                if game.sneaking {
+                    objects[PLAYER].spend_energy(SNEAK_EXTRA_COST);
+                } else {
+                    game.make_noise(objects[PLAYER].pos(), NOISE_WALK);
+                }
+                TookTurn
             } else {
-                return DidntTakeTurn;
+                DidntTakeTurn
             }
         }
-    };
+    }
+}
+
+
+// Trade places with an NPC that hasn't noticed the player: a sneaking step, so
+// slow and silent. The NPC won't be put somewhere it wouldn't step itself.
+fn slip_past(npc_id: usize, game: &mut Game, objects: &mut [Object]) -> PlayerAction {
+    let from = objects[PLAYER].pos();
+    let to = objects[npc_id].pos();
+    if game.maps[game.lvl][from].is_hazard() {
+        game.messages.add(format!("There is no room to slip past the {}.", objects[npc_id].name), LIGHT_GREY);
+        return PlayerAction::DidntTakeTurn;
+    }
+
+    objects[PLAYER].set_pos(to.0, to.1);
+    objects[npc_id].set_pos(from.0, from.1);
+    game.index.moved(PLAYER, from, to, objects[PLAYER].blocks);
+    game.index.moved(npc_id, to, from, objects[npc_id].blocks);
+
+    let cost = if game.maps[game.lvl][to].kind == TileKind::Water { WATER_MOVE_COST } else { ACTION_COST };
+    objects[PLAYER].spend_energy(cost + SNEAK_EXTRA_COST);
+    game.messages.add(format!("You slip past the {}, unnoticed.", objects[npc_id].name), LIGHT_GREY);
+    PlayerAction::TookTurn
 }
@@ -0,0 +1,134 @@
+/*
+  Noise
+
+  Walking, fighting and spells all make noise. A noise spreads out from where
+  it was made through the open tiles of the floor (walls stop it, and closed
+  doors muffle it) for as many steps as it is loud. Any NPC it reaches goes to
+  see what it was.
+
+  Noises are heard at the start of the next tick, before the NPCs act (any
+  not heard yet are saved with the game).
+ */
+// Import Std Libs
+use std::collections::BinaryHeap;
+
+// Import Third-Party
+use serde::{Deserialize, Serialize};
+
+// Import Locally
+use crate::ai_algos::hear;
+use crate::constants::NOISE_DOOR_COST;
+use crate::constants::PLAYER;
+use crate::map::Map;
+use crate::map::NEIGHBORS;
+use crate::objects::Game;
+use crate::objects::Object;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Noise {
+    pub at: (i32, i32),
+    // how many steps it carries
+    pub loudness: i32,
+}
+
+
+// every tile this noise reaches
+pub fn spread(map: &Map, noise: Noise) -> Vec<(i32, i32)> {
+    let cell = |(x, y): (i32, i32)| (x * map.height + y) as usize;
+    if !map.in_bounds(noise.at.0, noise.at.1) {
+        return vec![];
+    }
+    // how much of the noise is left on each tile
+    let mut left: Vec<i32> = vec![-1; (map.width * map.height) as usize];
+    let mut open: BinaryHeap<(i32, (i32, i32))> = BinaryHeap::new();
+    let mut heard: Vec<(i32, i32)> = vec![];
+    left[cell(noise.at)] = noise.loudness;
+    open.push((noise.loudness, noise.at));
+
+    while let Some((loudness, pos)) = open.pop() {
+        if loudness < left[cell(pos)] {
+            // already got here louder
+            continue;
+        }
+        heard.push(pos);
+        for &(dx, dy) in NEIGHBORS.iter() {
+            let next = (pos.0 + dx, pos.1 + dy);
+            let tile = match map.get(next.0, next.1) {
+                Some(tile) if !tile.blocked || tile.is_closed_door() => tile,
+                _ => continue,
+            };
+            let muffled = if tile.is_closed_door() { NOISE_DOOR_COST } else { 0 };
+            let next_loudness = loudness - 1 - muffled;
+            if next_loudness >= 0 && next_loudness > left[cell(next)] {
+                left[cell(next)] = next_loudness;
+                open.push((next_loudness, next));
+            }
+        }
+    }
+    heard
+}
+
+
+// the NPCs on the player's floor hear everything that was made since the last tick
+pub fn hear_noises(game: &mut Game, objects: &mut [Object]) {
+    let noises: Vec<Noise> = game.noises.drain(..).collect();
+    for noise in noises {
+        for (x, y) in spread(&game.maps[game.lvl], noise) {
+            for &id in game.index.at(x, y) {
+                if id == PLAYER {
+                    continue;
+                }
+                if let Some(ai) = objects[id].ai.take() {
+                    objects[id].ai = Some(hear(ai, noise.at));
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Tile;
+
+    // a corridor 20 long, with a door across it at x = 10
+    fn corridor_with_a_door() -> Map {
+        let mut map = Map::new(22, 3);
+        for x in 1..21 {
+            map[(x, 1)] = Tile::empty();
+        }
+        map[(10, 1)] = Tile::door();
+        map
+    }
+
+
+    #[test]
+    fn a_noise_carries_as_many_steps_as_it_is_loud() {
+        let map = corridor_with_a_door();
+        let heard = spread(&map, Noise { at: (2, 1), loudness: 3 });
+        assert!(heard.contains(&(2, 1)));
+        assert!(heard.contains(&(5, 1)));
+        assert!(!heard.contains(&(6, 1)));
+        // walls stop it
+        assert!(heard.iter().all(|&(_, y)| y == 1));
+    }
+
+
+    #[test]
+    fn a_closed_door_muffles_a_noise_and_an_open_one_does_not() {
+        let mut map = corridor_with_a_door();
+        let noise = Noise { at: (8, 1), loudness: 6 };
+        let heard = spread(&map, noise);
+        // through the door costs its step, and NOISE_DOOR_COST more
+        let past = 8 + 6 - NOISE_DOOR_COST;
+        assert!(heard.contains(&(past, 1)));
+        assert!(!heard.contains(&(past + 1, 1)));
+
+        map[(10, 1)].open_door();
+        let heard = spread(&map, noise);
+        assert!(heard.contains(&(14, 1)));
+        assert!(!heard.contains(&(15, 1)));
+    }
+}
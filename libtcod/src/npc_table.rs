@@ -7,10 +7,11 @@ use tcod::colors::WHITE;
 use crate::objects::Ai;
 use crate::objects::Fighter;
 use crate::objects::Object;
+use crate::rng::GameRng;
 
 
 #[derive(Clone)]
-pub struct NPC {
+pub struct Npc {
     pub symbol: char,
     pub name: String,
     pub ai: Ai,
@@ -22,9 +23,14 @@ pub struct NPC {
     pub min_level: i32,
     pub max_level: i32,
     pub weight: u32,
+    pub speed: i32,
+    pub attack_cost: i32,
+    pub vision: i32,
 }
 
-impl NPC {
+impl Npc {
+    // one argument per column of the table
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         symbol: char,
         name: &str,
@@ -37,21 +43,29 @@ impl NPC {
         min_level: i32,
         max_level: i32,
         weight: u32,
+        speed: i32,
+        attack_cost: i32,
+        vision: i32,
     ) -> Self {
             assert!(min_level <= max_level);
+            // with no speed, it would never get a turn
+            assert!(speed > 0);
 
-            NPC {
-            symbol: symbol,
+            Npc {
+            symbol,
             name: name.to_string(),
-            ai: ai,
-            max_hp: max_hp,
-            defense: defense,
-            attack: attack,
-            xp: xp,
-            start_hp: start_hp,
-            min_level: min_level,
-            max_level: max_level,
-            weight: weight,
+            ai,
+            max_hp,
+            defense,
+            attack,
+            xp,
+            start_hp,
+            min_level,
+            max_level,
+            weight,
+            speed,
+            attack_cost,
+            vision,
         }
     }
 
@@ -61,39 +75,44 @@ impl NPC {
         npc.ai = Some(self.ai.clone());
         let mut fighter = Fighter::new(self.max_hp, self.defense, self.attack, self.xp, true);
         fighter.hp = self.start_hp;
+        fighter.speed = self.speed;
+        fighter.attack_cost = self.attack_cost;
+        fighter.vision = self.vision;
         npc.fighter = Some(fighter);
         npc.alive = true;
-        return npc;
+        npc
     }
 }
 
 
-fn npc_table() -> Vec<NPC> {
-    return vec![
-        NPC::new('I', "imp", Ai::Basic, 10, 0, 8, 120, 1, -99, 99, 1),
-        NPC::new('O', "orc", Ai::Basic, 20, 0, 4, 35, 20, -99, 19, 100),
-        NPC::new('T', "troll", Ai::Basic, 60, 2, 8, 100, 30, 0, 19, 25),
-        NPC::new('M', "minotaur", Ai::Basic, 60, 3, 9, 100, 60, 10, 10, 100),
-        NPC::new('W', "wraith", Ai::Basic, 100, 3, 9, 100, 100, 17, 19, 100),
-        NPC::new('D', "demon", Ai::Basic, 200, 12, 12, 100, 200, 20, 99, 250),
-    ];
+fn npc_table() -> Vec<Npc> {
+    // imps are quick, wraiths slow, and the big ones take their time to swing
+    // trolls are short-sighted, and wraiths are blind (they only know you when they touch you)
+    vec![
+        Npc::new('I', "imp", Ai::Cowardly, 10, 0, 8, 120, 1, -99, 99, 1, 150, 100, 10),
+        Npc::new('O', "orc", Ai::Basic, 20, 0, 4, 35, 20, -99, 19, 100, 100, 100, 8),
+        Npc::new('T', "troll", Ai::Basic, 60, 2, 8, 100, 30, 0, 19, 25, 100, 150, 5),
+        Npc::new('M', "minotaur", Ai::Basic, 60, 3, 9, 100, 60, 10, 10, 100, 100, 150, 8),
+        Npc::new('W', "wraith", Ai::Basic, 100, 3, 9, 100, 100, 17, 19, 100, 50, 100, 0),
+        Npc::new('D', "demon", Ai::Basic, 200, 12, 12, 100, 200, 20, 99, 250, 100, 150, 12),
+    ]
 }
 
 
-fn trim_npcs_by_level(level: i32) -> Vec<NPC> {
-    return npc_table()
+fn trim_npcs_by_level(level: i32) -> Vec<Npc> {
+    npc_table()
            .iter()
            .filter(|&row| level >= row.min_level && level <= row.max_level)
            .cloned()
-           .collect::<Vec<NPC>>();
+           .collect::<Vec<Npc>>()
 }
 
 
 // TODO: Convert to generate multiple NPCs
-pub fn generate_npc(level: i32) -> Object {
+pub fn generate_npc(level: i32, rng: &mut GameRng) -> Object {
     // find all NPCs possible on a given floor, and their weights
-    let table: Vec<NPC> = trim_npcs_by_level(level);
-    assert!(table.len() > 0);
+    let table: Vec<Npc> = trim_npcs_by_level(level);
+    assert!(!table.is_empty());
 
     // do the weighted random chance thing
     let mut chances = vec![];
@@ -103,6 +122,15 @@ pub fn generate_npc(level: i32) -> Object {
 
     let choices = WeightedChoice::new(&mut chances);
 
-    return table[choices.ind_sample(&mut rand::thread_rng())].generate();
+    table[choices.ind_sample(rng)].generate()
 }
 
+
+
+// A particular NPC, by name (say, one a prefab room insists on).
+pub fn npc_by_name(name: &str) -> Option<Object> {
+    npc_table()
+           .iter()
+           .find(|row| row.name == name)
+           .map(|row| row.generate())
+}
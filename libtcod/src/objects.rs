@@ -3,16 +3,35 @@
 
   * Object
   * Game
+  * Messages
   * Item, Slot, Equipment, Fighter
  */
 use serde::{Deserialize, Serialize};
 use tcod::colors::*;
 use tcod::console::*;
-
-use crate::constants::NUM_LVLS;
+use tcod::map::{FovAlgorithm, Map as FovMap};
+
+use crate::constants::ACTION_COST;
+use crate::constants::MAP_HEIGHT;
+use crate::constants::MAP_WIDTH;
+use crate::constants::NOISE_FIGHT;
+use crate::constants::NORMAL_SPEED;
+use crate::constants::PLAYER;
+use crate::constants::TORCH_RADIUS;
+use crate::dijkstra::AiMaps;
+use crate::handles::Handle;
+use crate::handles::Handles;
 use crate::map::Map;
 use crate::map::make_map;
-use crate::menus::Messages;
+use crate::noise::Noise;
+use crate::pathfinding::PathCache;
+use crate::rng::level_seed;
+use crate::rng::GameRng;
+use crate::savegame::SaveFormat;
+use crate::savegame::SAVE_VERSION;
+use crate::spatial_index::SpatialIndex;
+
+const FOV_ALGO: FovAlgorithm = FovAlgorithm::Basic;
 
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
@@ -78,6 +97,34 @@ pub struct Equipment {
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Ai {
     Basic,
+    // keeps its distance, and only fights when cornered
+    Cowardly,
+    // makes the rounds of a few spots on the floor, in order
+    Patrol {
+        route: Vec<(i32, i32)>,
+        next: usize,
+    },
+    // strolls from one nearby spot to another
+    Wander {
+        goal: (i32, i32),
+    },
+    // stays put (and goes back there after a chase)
+    Guard {
+        post: (i32, i32),
+    },
+    // caught a glimpse of the player: goes to have a look where they were, and
+    // searches there for a while before going back to what it was doing
+    Suspicious {
+        last_seen: (i32, i32),
+        turns: i32,
+        previous_ai: Box<Ai>,
+    },
+    // after the player: once they're out of sight, it heads for where they
+    // were last seen, and is only suspicious from then on
+    Hunting {
+        last_seen: (i32, i32),
+        previous_ai: Box<Ai>,
+    },
     Confused {
         previous_ai: Box<Ai>,
         num_turns: i32,
@@ -85,6 +132,27 @@ pub enum Ai {
 }
 
 
+// How much an NPC knows about the player
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Awareness {
+    Unaware,
+    Suspicious,
+    Hunting,
+}
+
+
+impl Ai {
+    pub fn awareness(&self) -> Awareness {
+        match self {
+            Ai::Suspicious { .. } => Awareness::Suspicious,
+            Ai::Hunting { .. } => Awareness::Hunting,
+            Ai::Confused { previous_ai, .. } => previous_ai.awareness(),
+            _ => Awareness::Unaware,
+        }
+    }
+}
+
+
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum DeathCallback {
     Player,
@@ -130,6 +198,9 @@ fn npc_death(npc: &mut Object, game: &mut Game) {
     npc.alive = false;
     npc.chr = '%';
     npc.color = DARK_RED;
+    if npc.blocks {
+        game.index.unblock(npc.pos());
+    }
     npc.blocks = false;
     npc.fighter = None;
     npc.ai = None;
@@ -148,6 +219,14 @@ pub struct Fighter {
     pub karma: i32,
     pub on_death: DeathCallback,
     pub inventory: Vec<Object>,
+    // energy gained each tick (NORMAL_SPEED is one action a turn)
+    pub speed: i32,
+    // acting once this reaches ENERGY_TO_ACT, and spent on every action
+    pub energy: i32,
+    // what a melee attack costs (heavy hitters take longer)
+    pub attack_cost: i32,
+    // how far it can see (0 is blind)
+    pub vision: i32,
 }
 
 
@@ -161,13 +240,17 @@ impl Fighter {
 
         Fighter {
             base_max_hp: hp as u32,
-            hp: hp,
-            base_defense: base_defense,
-            base_power: base_power,
-            xp: xp,
+            hp,
+            base_defense,
+            base_power,
+            xp,
             karma: -1000,
-            on_death: on_death,
+            on_death,
             inventory: vec![],
+            speed: NORMAL_SPEED,
+            energy: 0,
+            attack_cost: ACTION_COST,
+            vision: TORCH_RADIUS,
         }
     }
 
@@ -212,7 +295,7 @@ impl Fighter {
     pub fn get_all_equipped(&self) -> Vec<Equipment> {
         self.inventory
             .iter()
-            .filter(|item| item.equipment.map_or(false, |e| e.equipped))
+            .filter(|item| item.equipment.is_some_and(|e| e.equipped))
             .map(|item| item.equipment.unwrap())
             .collect()
     }
@@ -223,7 +306,7 @@ impl Fighter {
     }
 
     pub fn kill_karma(xp: i32, game_level: i32) -> i32 {
-        return -xp * (game_level + 1);
+        -xp * (game_level + 1)
     }
 }
 
@@ -232,6 +315,8 @@ impl Fighter {
 // It's represented by a character on screen (unless it's in an inventory).
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Object {
+    // how to find this object again, wherever it ends up in a list
+    pub handle: Handle,
     pub x: i32,
     pub y: i32,
     pub chr: char,
@@ -251,12 +336,13 @@ pub struct Object {
 impl Object {
     pub fn new(x: i32, y: i32, chr: char, name: &str, color: Color, blocks: bool) -> Self {
         Object {
-            x: x,
-            y: y,
-            chr: chr,
-            color: color,
+            handle: Handle::NONE,
+            x,
+            y,
+            chr,
+            color,
             name: name.into(),
-            blocks: blocks,
+            blocks,
             alive: false,
             fighter: None,
             ai: None,
@@ -271,12 +357,12 @@ impl Object {
     pub fn get_color(&self) -> Color {
         if let Some(fighter) = self.fighter.as_ref() {
             let frac: f32 = fighter.hp as f32 / fighter.base_max_hp as f32;
-            if frac >= 0.95 { return WHITE; }
+            if frac >= 0.95 { WHITE }
             else {
                 // player / NPC health displayed by color
-                return Color{r: 255 - (255.0 * frac) as u8, g: (255.0 * frac) as u8, b: 0};
+                Color{r: 255 - (255.0 * frac) as u8, g: (255.0 * frac) as u8, b: 0}
             }
-        } else { return self.color; }
+        } else { self.color }
     }
 
     // set the color and then draw the character that represents this object at its position
@@ -295,14 +381,14 @@ impl Object {
     }
 
     pub fn is_stackable(&self) -> bool {
-        return self.item.is_some() && self.equipment.is_none();
+        self.item.is_some() && self.equipment.is_none()
     }
 
     // return the distance to another object
     pub fn distance_to(&self, other: &Object) -> f32 {
         let dx = other.x - self.x;
         let dy = other.y - self.y;
-        return ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
+        ((dx.pow(2) + dy.pow(2)) as f32).sqrt()
     }
 
     pub fn take_damage(&mut self, damage: i32, game: &mut Game) -> i32 {
@@ -322,10 +408,21 @@ impl Object {
             }
         }
 
-        return xp;
+        xp
+    }
+
+    // acting uses up energy (things that can't act have none to spend)
+    pub fn spend_energy(&mut self, cost: i32) {
+        if let Some(fighter) = self.fighter.as_mut() {
+            fighter.energy -= cost;
+        }
     }
 
     pub fn melee_attack(&mut self, target: &mut Object, game: &mut Game) {
+        let cost: i32 = self.fighter.as_ref().unwrap().attack_cost;
+        self.spend_energy(cost);
+        game.make_noise(self.pos(), NOISE_FIGHT);
+
         // a simple formula for attack damage
         let damage = self.fighter.as_ref().unwrap().power() - target.fighter.as_ref().unwrap().defense();
         if damage > 0 {
@@ -416,45 +513,203 @@ impl Object {
 
 #[derive(Serialize, Deserialize)]
 pub struct Game {
+    // the floors built so far, from the bottom up (with their stairs)
     pub maps: Vec<Map>,
     pub up_stairs: Vec<(i32, i32)>,
     pub down_stairs: Vec<(i32, i32)>,
     pub lvl: usize,
     pub messages: Messages,
     pub version: String,
+    pub save_version: u32,
+    pub save_format: SaveFormat,
     pub day: u32,
     pub turn: u32,
+    pub rng: GameRng,
+    pub handles: Handles,
+    pub inputs_recorded: u64,
+    // the player is sneaking: slower, but quieter
+    pub sneaking: bool,
+    // what the player can see right now, rebuilt after every load
+    #[serde(skip, default = "blank_fov")]
+    pub fov: FovMap,
+    // what is on each tile of this floor, also rebuilt after every load
+    #[serde(skip)]
+    pub index: SpatialIndex,
+    // where the NPCs on this floor are headed (saved, so a loaded game plays on the same)
+    pub paths: PathCache,
+    // the Dijkstra maps the NPCs steer by, worked out again every turn (when needed)
+    #[serde(skip)]
+    pub ai_maps: AiMaps,
+    // the noises made since the NPCs last listened
+    pub noises: Vec<Noise>,
+}
+
+
+fn blank_fov() -> FovMap {
+    FovMap::new(MAP_WIDTH, MAP_HEIGHT)
 }
 
 impl Game {
-    pub fn new(objects: &mut Vec<Vec<Object>>) -> Self {
-        let (m, up, down) = Game::make_maps(objects);
-        Game {
-            maps: m,
-            up_stairs: up,
-            down_stairs: down,
+    pub fn new(objects: &mut [Vec<Object>], seed: u64) -> Self {
+        // everything random in the run flows from this one seed
+        let mut game = Game {
+            maps: vec![],
+            up_stairs: vec![],
+            down_stairs: vec![],
             lvl: 0,
             messages: Messages::new(),
             version: env!("CARGO_PKG_VERSION").to_string(),
+            save_version: SAVE_VERSION,
+            save_format: SaveFormat::Binary,
             day: 1,
             turn: 1,
-        }
+            rng: GameRng::new(seed),
+            handles: Handles::default(),
+            inputs_recorded: 0,
+            sneaking: false,
+            fov: blank_fov(),
+            index: SpatialIndex::default(),
+            paths: PathCache::default(),
+            ai_maps: AiMaps::default(),
+            noises: vec![],
+        };
+        // only the first floor is built now, the rest as the player reaches them
+        game.make_level(objects);
+        game
     }
 
     pub fn map(&mut self) -> &mut Map {
-        return &mut self.maps[self.lvl];
-    }
-
-    fn make_maps(objects: &mut Vec<Vec<Object>>) -> (Vec<Map>, Vec<(i32, i32)>, Vec<(i32, i32)>) {
-        let mut maps: Vec<Map> = vec![];
-        let mut up_stairs: Vec<(i32, i32)> = vec![];
-        let mut down_stairs: Vec<(i32, i32)> = vec![];
-        for i in 0..NUM_LVLS {
-            let (m, up, down) = make_map(objects, i as usize);
-            maps.push(m);
-            up_stairs.push(up);
-            down_stairs.push(down);
+        &mut self.maps[self.lvl]
+    }
+
+    // the player has arrived on this floor: forget the paths planned (and noises made) on the last one
+    pub fn enter_floor(&mut self, objects: &[Object]) {
+        self.paths.clear();
+        self.noises.clear();
+        self.restore(objects);
+    }
+
+    // rebuild what isn't saved (the FOV map and the spatial index), after a load or on a new floor
+    pub fn restore(&mut self, objects: &[Object]) {
+        self.index.rebuild(&self.maps[self.lvl], objects);
+        self.initialise_fov(&objects[PLAYER]);
+    }
+
+    // something made a noise here, for the NPCs on this floor to hear
+    pub fn make_noise(&mut self, at: (i32, i32), loudness: i32) {
+        self.noises.push(Noise { at, loudness });
+    }
+
+    // rebuild the FOV map for the current floor
+    pub fn initialise_fov(&mut self, player: &Object) {
+        let map: &Map = &self.maps[self.lvl];
+        // floors can differ in size
+        self.fov = FovMap::new(map.width, map.height);
+        for ((x, y), tile) in map.iter() {
+            self.fov.set(x, y, !tile.block_sight, !tile.blocked);
         }
-        return (maps, up_stairs, down_stairs);
+        self.compute_fov(player);
+    }
+
+    // see what the player sees from where they stand; anything seen is explored
+    pub fn compute_fov(&mut self, player: &Object) {
+        self.fov.compute_fov(player.x, player.y, TORCH_RADIUS, true, FOV_ALGO);
+        let map: &mut Map = &mut self.maps[self.lvl];
+        for ((x, y), tile) in map.iter_mut() {
+            if self.fov.is_in_fov(x, y) {
+                tile.explored = true;
+            }
+        }
+    }
+
+    // Build the next floor up. Each floor has its own seed, worked out from the
+    // run's, so it comes out the same whenever the player first gets there.
+    pub fn make_level(&mut self, objects: &mut [Vec<Object>]) {
+        let level: usize = self.maps.len();
+        let mut rng = GameRng::new(level_seed(self.rng.seed(), level));
+        let (m, up, down) = make_map(objects, level, &mut rng);
+        self.handles.allocate_all(&mut objects[level]);
+        self.maps.push(m);
+        self.up_stairs.push(up);
+        self.down_stairs.push(down);
+    }
+}
+
+
+#[derive(Serialize, Deserialize)]
+pub struct Messages {
+    messages: Vec<(String, Color)>,
+}
+
+
+impl Default for Messages {
+    fn default() -> Self {
+        Self::new()
     }
 }
+
+impl Messages {
+    pub fn new() -> Self {
+        Self { messages: vec![] }
+    }
+
+    // add the new message as a tuple, with the text and the color
+    pub fn add<T: Into<String>>(&mut self, message: T, color: Color) {
+        self.messages.push((message.into(), color));
+    }
+
+    // Create a `DoubleEndedIterator` over the messages
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &(String, Color)> {
+        self.messages.iter()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::NUM_LVLS;
+    use rand::Rng;
+
+    fn new_floors(seed: u64) -> (Game, Vec<Vec<Object>>) {
+        let mut objects: Vec<Vec<Object>> = vec![vec![]; NUM_LVLS as usize];
+        objects[0].push(Object::new(0, 0, '@', "you", WHITE, true));
+        let game = Game::new(&mut objects, seed);
+        (game, objects)
+    }
+
+
+    #[test]
+    fn a_seed_always_builds_the_same_run() {
+        let (a, a_objects) = new_floors(7);
+        let (b, b_objects) = new_floors(7);
+        assert_eq!(serde_json::to_string(&a.maps).unwrap(), serde_json::to_string(&b.maps).unwrap());
+        assert_eq!(serde_json::to_string(&a_objects).unwrap(), serde_json::to_string(&b_objects).unwrap());
+        assert_eq!(a.rng, b.rng);
+
+        let (c, _) = new_floors(8);
+        assert_ne!(serde_json::to_string(&a.maps).unwrap(), serde_json::to_string(&c.maps).unwrap());
+    }
+
+    #[test]
+    fn floors_are_built_as_they_are_reached() {
+        let (mut a, mut a_objects) = new_floors(7);
+        assert_eq!(a.maps.len(), 1);
+        assert!(a_objects[1].is_empty());
+
+        // how much the run has rolled by then makes no difference to the floor
+        let (mut b, mut b_objects) = new_floors(7);
+        for _ in 0..50 {
+            b.rng.next_u64();
+        }
+        a.make_level(&mut a_objects);
+        a.make_level(&mut a_objects);
+        b.make_level(&mut b_objects);
+        b.make_level(&mut b_objects);
+        assert_eq!(a.maps.len(), 3);
+        assert_eq!(serde_json::to_string(&a.maps[2]).unwrap(), serde_json::to_string(&b.maps[2]).unwrap());
+        assert_eq!(a.up_stairs, b.up_stairs);
+        assert_eq!(serde_json::to_string(&a_objects[2]).unwrap(), serde_json::to_string(&b_objects[2]).unwrap());
+    }
+}
+
@@ -0,0 +1,293 @@
+/*
+  Pathfinding
+
+  A* over the tiles of a floor, in eight directions (a diagonal step costs a
+  bit more than a straight one). Walls, lava and chasms can't be crossed;
+  closed doors can, since walking into one opens it. Anyone standing in the
+  way is only a soft obstacle: walking around them is preferred, but if
+  there is no other way the path goes through, and the NPC waits its turn.
+
+  Paths are cached for each NPC, so one chasing the player doesn't search the
+  whole floor again every turn. The cache is saved with the game: where an NPC
+  steps next depends on it, and a loaded game has to play out the same.
+ */
+// Import Std Libs
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+// Import Third-Party
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// Import Locally
+use crate::constants::PATH_DIAGONAL_COST;
+use crate::constants::PATH_MAX_AGE;
+use crate::constants::PATH_MAX_NODES;
+use crate::constants::PATH_OBSTACLE_COST;
+use crate::constants::PATH_STEP_COST;
+use crate::handles::Handle;
+use crate::map::Map;
+use crate::map::NEIGHBORS;
+use crate::spatial_index::SpatialIndex;
+
+
+// the cheapest possible cost between two tiles: diagonals as far as they go, then straight
+fn octile(from: (i32, i32), to: (i32, i32)) -> u32 {
+    let dx = (from.0 - to.0).unsigned_abs();
+    let dy = (from.1 - to.1).unsigned_abs();
+    let (long, short) = if dx > dy { (dx, dy) } else { (dy, dx) };
+    PATH_DIAGONAL_COST * short + PATH_STEP_COST * (long - short)
+}
+
+
+fn is_adjacent(a: (i32, i32), b: (i32, i32)) -> bool {
+    a != b && (a.0 - b.0).abs() <= 1 && (a.1 - b.1).abs() <= 1
+}
+
+
+/**
+ * A* from one tile to another
+ *
+ * Returns every step to take (not counting where we start, but ending on the
+ * goal), or None if there is no way there (or it is too far to bother).
+ */
+pub fn find_path(map: &Map, index: &SpatialIndex, from: (i32, i32), to: (i32, i32)) -> Option<Vec<(i32, i32)>> {
+    if !map.in_bounds(from.0, from.1) || !map.in_bounds(to.0, to.1) {
+        return None;
+    }
+    let cell = |(x, y): (i32, i32)| (x * map.height + y) as usize;
+    let size = (map.width * map.height) as usize;
+    let mut cost: Vec<u32> = vec![u32::MAX; size];
+    let mut came_from: Vec<Option<(i32, i32)>> = vec![None; size];
+    // lowest estimated total first, then lowest cost so far
+    let mut open = BinaryHeap::new();
+
+    cost[cell(from)] = 0;
+    open.push(Reverse((octile(from, to), 0, from)));
+    let mut searched: usize = 0;
+
+    while let Some(Reverse((_, so_far, pos))) = open.pop() {
+        if pos == to {
+            // walk back to the start
+            let mut steps: Vec<(i32, i32)> = vec![];
+            let mut at = to;
+            while at != from {
+                steps.push(at);
+                at = came_from[cell(at)].unwrap();
+            }
+            steps.reverse();
+            return Some(steps);
+        }
+        if so_far > cost[cell(pos)] {
+            // already got here more cheaply
+            continue;
+        }
+        searched += 1;
+        if searched > PATH_MAX_NODES {
+            return None;
+        }
+
+        for &(dx, dy) in NEIGHBORS.iter() {
+            let next = (pos.0 + dx, pos.1 + dy);
+            // the goal is often somebody's tile (or hazardous), but that's where we're going
+            if next != to && !map.get(next.0, next.1).is_some_and(|tile| tile.passable()) {
+                continue;
+            }
+            let mut step = if dx != 0 && dy != 0 { PATH_DIAGONAL_COST } else { PATH_STEP_COST };
+            if next != to && index.is_blocked(next.0, next.1) {
+                step += PATH_OBSTACLE_COST;
+            }
+            let next_cost = so_far + step;
+            if next_cost < cost[cell(next)] {
+                cost[cell(next)] = next_cost;
+                came_from[cell(next)] = Some(pos);
+                open.push(Reverse((next_cost + octile(next, to), next_cost, next)));
+            }
+        }
+    }
+    None
+}
+
+
+// where an NPC is headed, and how it planned to get there
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedPath {
+    goal: (i32, i32),
+    steps: VecDeque<(i32, i32)>,
+    // how many turns since this path was planned
+    age: u32,
+}
+
+
+// The paths the NPCs on this floor are following. It is cleared whenever the
+// player changes floors.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PathCache {
+    #[serde(serialize_with = "paths_to_list", deserialize_with = "paths_from_list")]
+    paths: HashMap<Handle, CachedPath>,
+}
+
+
+// a handle can't be a JSON key, so the paths are saved as a list (in handle order)
+fn paths_to_list<S: Serializer>(paths: &HashMap<Handle, CachedPath>, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut list: Vec<(&Handle, &CachedPath)> = paths.iter().collect();
+    list.sort_by_key(|&(handle, _)| *handle);
+    list.serialize(serializer)
+}
+
+
+fn paths_from_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<Handle, CachedPath>, D::Error> {
+    let list: Vec<(Handle, CachedPath)> = Vec::deserialize(deserializer)?;
+    Ok(list.into_iter().collect())
+}
+
+
+impl PathCache {
+    pub fn clear(&mut self) {
+        self.paths.clear();
+    }
+
+    // The next tile for this NPC to step to, on its way to the goal. A cached
+    // path is followed while it still leads there (the goal may have moved
+    // over a tile, as the player does), otherwise a new one is planned.
+    pub fn next_step(&mut self,
+                     npc: Handle,
+                     from: (i32, i32),
+                     goal: (i32, i32),
+                     map: &Map,
+                     index: &SpatialIndex) -> Option<(i32, i32)> {
+        if let Some(path) = self.paths.get_mut(&npc) {
+            if follow(path, from, goal, map, index) {
+                return path.steps.front().copied();
+            }
+        }
+
+        let steps: VecDeque<(i32, i32)> = match find_path(map, index, from, goal) {
+            Some(steps) => steps.into(),
+            None => {
+                self.paths.remove(&npc);
+                return None;
+            }
+        };
+        let next = steps.front().copied();
+        self.paths.insert(npc, CachedPath { goal, steps, age: 0 });
+        next
+    }
+}
+
+
+// bring a cached path up to date, or say it won't do any more
+fn follow(path: &mut CachedPath, from: (i32, i32), goal: (i32, i32), map: &Map, index: &SpatialIndex) -> bool {
+    path.age += 1;
+    if path.age > PATH_MAX_AGE {
+        return false;
+    }
+
+    // drop the steps already taken
+    while path.steps.front() == Some(&from) {
+        path.steps.pop_front();
+    }
+
+    // the goal moved over: go one step further (or stop one short, if it came closer)
+    if goal != path.goal {
+        if !is_adjacent(goal, path.goal) {
+            return false;
+        }
+        match path.steps.iter().position(|&step| step == goal) {
+            Some(at) => path.steps.truncate(at + 1),
+            None => path.steps.push_back(goal),
+        }
+        path.goal = goal;
+    }
+
+    // the next step has to be right here, and nobody (and nothing) may have got in the way
+    let next = match path.steps.front() {
+        Some(&next) => next,
+        None => return false,
+    };
+    let open = map.get(next.0, next.1).is_some_and(|tile| tile.passable());
+    is_adjacent(from, next) && (next == goal || (open && !index.is_blocked(next.0, next.1)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tcod::colors::WHITE;
+    use crate::map::Tile;
+    use crate::objects::Object;
+
+    // an open room, 10 by 7, with a wall down the middle (and a gap at the bottom)
+    fn walled_room() -> Map {
+        let mut map = Map::new(12, 9);
+        for x in 1..11 {
+            for y in 1..8 {
+                map[(x, y)] = Tile::empty();
+            }
+        }
+        for y in 1..7 {
+            map[(5, y)] = Tile::wall();
+        }
+        map
+    }
+
+
+    fn each_step_is_adjacent(from: (i32, i32), steps: &[(i32, i32)]) -> bool {
+        let mut at = from;
+        steps.iter().all(|&step| {
+            let next = is_adjacent(at, step);
+            at = step;
+            next
+        })
+    }
+
+
+    #[test]
+    fn the_path_goes_around_the_wall() {
+        let map = walled_room();
+        let index = SpatialIndex::default();
+        let steps = find_path(&map, &index, (2, 2), (8, 2)).unwrap();
+        assert_eq!(steps.last(), Some(&(8, 2)));
+        assert!(each_step_is_adjacent((2, 2), &steps));
+        assert!(steps.contains(&(5, 7)));
+        assert!(steps.iter().all(|&(x, y)| map[(x, y)].passable()));
+    }
+
+
+    #[test]
+    fn hazards_are_never_crossed_but_crowds_are() {
+        let mut map = walled_room();
+        map[(5, 7)] = Tile::lava();
+        let index = SpatialIndex::default();
+        assert!(find_path(&map, &index, (2, 2), (8, 2)).is_none());
+
+        // somebody standing in the only gap: the path goes through, and the NPC waits
+        map[(5, 7)] = Tile::empty();
+        let mut index = SpatialIndex::default();
+        index.rebuild(&map, &[Object::new(5, 7, 'o', "orc", WHITE, true)]);
+        let steps = find_path(&map, &index, (2, 2), (8, 2)).unwrap();
+        assert!(steps.contains(&(5, 7)));
+    }
+
+
+    #[test]
+    fn a_cached_path_follows_a_goal_that_moves_over_a_tile() {
+        let map = walled_room();
+        let index = SpatialIndex::default();
+        let npc = Handle { slot: 1, generation: 0 };
+        let mut paths = PathCache::default();
+
+        let first = paths.next_step(npc, (2, 2), (8, 2), &map, &index).unwrap();
+        let planned = paths.paths[&npc].steps.len();
+        let second = paths.next_step(npc, first, (8, 3), &map, &index).unwrap();
+        assert!(is_adjacent(first, second));
+        // the old plan was kept: one step taken, one added for the goal moving
+        assert_eq!(paths.paths[&npc].steps.len(), planned);
+        assert_eq!(paths.paths[&npc].goal, (8, 3));
+
+        // a goal that jumps is planned for again
+        paths.next_step(npc, second, (10, 7), &map, &index).unwrap();
+        assert_eq!(paths.paths[&npc].age, 0);
+    }
+}
@@ -4,55 +4,42 @@
 use tcod::colors::WHITE;
 use tcod::colors::YELLOW;
 
-use crate::constants::CHARACTER_SCREEN_WIDTH;
+use crate::constants::ENERGY_TO_ACT;
 use crate::constants::LEVEL_SCREEN_WIDTH;
 use crate::constants::LEVEL_UP_BASE;
 use crate::constants::LEVEL_UP_FACTOR;
+use crate::constants::NORMAL_SPEED;
 use crate::constants::PLAYER;
-use crate::menus::menu;
-use crate::menus::msgbox;
-use crate::menus::Tcod;
+use crate::engine::Ui;
 use crate::objects::Game;
 use crate::objects::Object;
 
 
 pub fn xp_to_level_up(lvl: i32) -> i32 {
-    return LEVEL_UP_BASE + (lvl as i32 + 1) * LEVEL_UP_FACTOR;
+    LEVEL_UP_BASE + (lvl + 1) * LEVEL_UP_FACTOR
 }
 
 
-pub fn level_up(tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) {
+pub fn level_up(ui: &mut dyn Ui, game: &mut Game, objects: &mut [Object]) {
     let player = &mut objects[PLAYER];
     let level_up_xp = xp_to_level_up(player.level);
     // see if the player's experience is enough to level-up
     if player.fighter.as_ref().map_or(0, |f| f.xp) >= level_up_xp {
-        player.level += 1;
-        game.messages.add(
-            format!(
-                "You grow stronger! You reached level {}!",
-                player.level
-            ),
-            YELLOW,
-        );
-
         // Let the player choose a stat to level up
         let fighter = player.fighter.as_mut().unwrap();
-        let mut choice = None;
-        while choice.is_none() {
-            // keep asking until a choice is made
-            choice = menu(
-                "Level up! Choose a stat to raise:\n",
-                &[
-                    format!("Constitution (+20 HP, from {})", fighter.base_max_hp),
-                    format!("Strength (+1 attack, from {})", fighter.base_power),
-                    format!("Agility (+1 defense, from {})", fighter.base_defense),
-                ],
-                LEVEL_SCREEN_WIDTH,
-                &mut tcod.root,
-            );
-        }
+        let stats = [
+            format!("Constitution (+20 HP, from {})", fighter.base_max_hp),
+            format!("Strength (+1 attack, from {})", fighter.base_power),
+            format!("Agility (+1 defense, from {})", fighter.base_defense),
+        ];
+        let choice = match ui.menu(game, "Level up! Choose a stat to raise:\n", &stats, LEVEL_SCREEN_WIDTH) {
+            Some(choice) => choice,
+            // no choice made, so ask again before the next turn
+            None => return,
+        };
+
         fighter.xp -= level_up_xp;
-        match choice.unwrap() {
+        match choice {
             0 => {
                 fighter.base_max_hp += 20;
                 fighter.hp += 20;
@@ -65,16 +52,25 @@ pub fn level_up(tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) {
             }
             _ => unreachable!(),
         }
+
+        player.level += 1;
+        game.messages.add(
+            format!(
+                "You grow stronger! You reached level {}!",
+                player.level
+            ),
+            YELLOW,
+        );
     }
 }
 
 
 // TODO: Add inventory and anything else players are proud of.
-pub fn character_screen(tcod: &mut Tcod, player: &Object, game: &Game) {
+pub fn character_sheet(player: &Object, game: &Game) -> String {
     let level = player.level;
     let level_up_xp = xp_to_level_up(player.level);
     if let Some(fighter) = player.fighter.as_ref() {
-        let msg = format!(
+        return format!(
 "Character information
 
 Karma: {}
@@ -84,9 +80,11 @@ Experience: {} of {}
 Maximum HP: {}
 Attack: {}
 Defense: {}
+Speed: {}
 
 Day: {}
 Turn: {}
+Seed: {}
 ",
         fighter.karma,
         level,
@@ -95,11 +93,13 @@ Turn: {}
         fighter.max_hp(),
         fighter.power(),
         fighter.defense(),
+        fighter.speed,
         game.day,
         game.turn,
-    );
-    msgbox(&msg, CHARACTER_SCREEN_WIDTH, &mut tcod.root);
+        game.rng.seed(),
+        );
     }
+    String::new()
 }
 
 
@@ -117,6 +117,9 @@ pub fn reincarnate_reset(player: &mut Object) {
         fighter.base_power = 3;
         fighter.xp = 0;
         fighter.inventory = vec![];
+        fighter.speed = NORMAL_SPEED;
+        // ready to go
+        fighter.energy = ENERGY_TO_ACT;
     }
 }
 
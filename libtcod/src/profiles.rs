@@ -0,0 +1,298 @@
+/*
+  Level Generation Profiles
+
+  Which map-gen algorithm builds each floor of Purgatory, with what
+  parameters, and how thickly it gets filled with NPCs and items. They are
+  read from LEVELS_FILE at startup, so floors can be tuned without a rebuild;
+  without that file, the copy built into the game is used.
+ */
+// Import Std Libs
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+// Import Third-Party
+use serde::Deserialize;
+
+// Import Locally
+use crate::constants::MAP_HEIGHT;
+use crate::constants::MAP_WIDTH;
+use crate::constants::MIN_MAP_HEIGHT;
+use crate::constants::MIN_MAP_WIDTH;
+use crate::constants::NUM_LVLS;
+use crate::map_algos::bsp::BspParams;
+use crate::map_algos::bsp_mod::BspModParams;
+use crate::map_algos::caves::CavesParams;
+use crate::map_algos::maze::MazeParams;
+use crate::map_algos::simple_fast::SimpleFastParams;
+use crate::transition::Transition;
+
+const BUILT_IN_PROFILES: &str = include_str!("../levels.toml");
+
+static PROFILES: OnceLock<Vec<LevelProfile>> = OnceLock::new();
+
+
+// The map-gen algorithm, and its parameters
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "snake_case")]
+pub enum Generator {
+    SimpleFast(SimpleFastParams),
+    Bsp(BspParams),
+    BspMod(BspModParams),
+    Caves(CavesParams),
+    Maze(MazeParams),
+}
+
+
+// How many NPCs and items to place, by level (what the values mean is up to the algorithm)
+#[derive(Clone, Debug, Deserialize)]
+pub struct Density {
+    pub npcs: Vec<Transition>,
+    pub items: Vec<Transition>,
+}
+
+
+// Doors and pools added once the floor is laid out (all optional)
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Terrain {
+    // chance-in-100 that a doorway into a room gets a door
+    pub door_percent: u32,
+    // how many pools of each kind, and about how many tiles in each
+    pub water_pools: u32,
+    pub lava_pools: u32,
+    pub chasms: u32,
+    pub pool_size: u32,
+}
+
+
+// What some of the NPCs get up to, before they see the player (all optional)
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Behaviour {
+    // chance-in-100 that an NPC patrols between rooms, wanders about, or guards where it stands
+    pub patrol_percent: u32,
+    pub wander_percent: u32,
+    pub guard_percent: u32,
+}
+
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct LevelProfile {
+    // the floors built this way (inclusive, counting from 0)
+    pub first: usize,
+    pub last: usize,
+    // how big the floors are (no bigger than the map window)
+    #[serde(default = "default_width")]
+    pub width: i32,
+    #[serde(default = "default_height")]
+    pub height: i32,
+    pub generator: Generator,
+    pub density: Density,
+    #[serde(default)]
+    pub terrain: Terrain,
+    #[serde(default)]
+    pub behaviour: Behaviour,
+}
+
+
+fn default_width() -> i32 {
+    MAP_WIDTH
+}
+
+
+fn default_height() -> i32 {
+    MAP_HEIGHT
+}
+
+
+#[derive(Deserialize)]
+struct ProfileFile {
+    profile: Vec<LevelProfile>,
+}
+
+
+pub fn parse_profiles(text: &str) -> Result<Vec<LevelProfile>, Box<dyn Error>> {
+    let file: ProfileFile = toml::from_str(text)?;
+
+    // every floor needs exactly one profile
+    for level in 0..NUM_LVLS as usize {
+        let count = file
+            .profile
+            .iter()
+            .filter(|profile| profile.first <= level && level <= profile.last)
+            .count();
+        if count != 1 {
+            return Err(format!("floor {} has {} profiles, instead of 1", level, count).into());
+        }
+    }
+    for profile in file.profile.iter() {
+        let (w, h) = (profile.width, profile.height);
+        if w < MIN_MAP_WIDTH || h < MIN_MAP_HEIGHT || w > MAP_WIDTH || h > MAP_HEIGHT {
+            return Err(format!("floors {} to {}: {}x{} is not between {}x{} and {}x{}",
+                               profile.first, profile.last, w, h,
+                               MIN_MAP_WIDTH, MIN_MAP_HEIGHT, MAP_WIDTH, MAP_HEIGHT).into());
+        }
+        check_generator(&profile.generator, w, h)
+            .map_err(|e| format!("floors {} to {}: {}", profile.first, profile.last, e))?;
+        if profile.terrain.door_percent > 100 {
+            return Err(format!("floors {} to {}: door_percent is over 100", profile.first, profile.last).into());
+        }
+        let behaviour = &profile.behaviour;
+        if behaviour.patrol_percent + behaviour.wander_percent + behaviour.guard_percent > 100 {
+            return Err(format!("floors {} to {}: the behaviour percents add up to over 100", profile.first, profile.last).into());
+        }
+    }
+    Ok(file.profile)
+}
+
+
+// catch the parameters that would crash (or hang) the generator
+fn check_generator(generator: &Generator, width: i32, height: i32) -> Result<(), String> {
+    let ok: bool = match generator {
+        Generator::SimpleFast(params) => params.room_min_size > 0 && params.room_min_size <= params.room_max_size,
+        Generator::Bsp(params) => params.room_min_size > 0 && params.iterations > 0,
+        Generator::BspMod(params) => params.room_min_size > 0 && params.iterations > 0,
+        Generator::Caves(params) => params.fill_percent < 100,
+        Generator::Maze(params) => params.newest_percent <= 100 && params.braid_percent <= 100,
+    };
+    if !ok {
+        return Err(format!("bad parameters: {:?}", generator));
+    }
+
+    // the rooms have to fit on the floor (inside its outer wall)
+    let (fits, limit): (bool, String) = match generator {
+        Generator::SimpleFast(params) => (
+            params.room_max_size < width - 1 && params.room_max_size < height - 1,
+            format!("room_max_size {} must be under {}", params.room_max_size, width.min(height) - 1),
+        ),
+        Generator::Bsp(params) => (
+            params.room_min_size * 2 < width - 2 && params.room_min_size * 2 < height - 2,
+            format!("twice room_min_size {} must be under {}", params.room_min_size, width.min(height) - 2),
+        ),
+        Generator::BspMod(params) => (
+            params.room_min_size * 2 < width - 2 && params.room_min_size * 2 < height - 2,
+            format!("twice room_min_size {} must be under {}", params.room_min_size, width.min(height) - 2),
+        ),
+        Generator::Caves(_) | Generator::Maze(_) => (true, String::new()),
+    };
+    if !fits {
+        return Err(format!("the rooms are too big for a {}x{} floor: {}", width, height, limit));
+    }
+    Ok(())
+}
+
+
+pub fn load_profiles(path: &Path) -> Result<Vec<LevelProfile>, Box<dyn Error>> {
+    parse_profiles(&fs::read_to_string(path)?)
+}
+
+
+fn built_in_profiles() -> Vec<LevelProfile> {
+    parse_profiles(BUILT_IN_PROFILES).expect("the built-in level profiles are broken")
+}
+
+
+// Read the profiles from LEVELS_FILE, if there is one, before any floor gets built.
+// A broken file is handed back to the frontend, and the built-in profiles are used instead.
+pub fn init_profiles(path: &Path) -> Result<(), Box<dyn Error>> {
+    let loaded = if path.exists() { load_profiles(path) } else { Ok(built_in_profiles()) };
+    match loaded {
+        Ok(profiles) => {
+            let _ = PROFILES.set(profiles);
+            Ok(())
+        }
+        Err(e) => {
+            let _ = PROFILES.set(built_in_profiles());
+            Err(format!("{}: {}", path.display(), e).into())
+        }
+    }
+}
+
+
+// All the profiles (the built-in ones, unless init_profiles found others)
+pub fn level_profiles() -> &'static [LevelProfile] {
+    PROFILES.get_or_init(built_in_profiles)
+}
+
+
+pub fn profile_for(level: usize) -> &'static LevelProfile {
+    level_profiles()
+        .iter()
+        .find(|profile| profile.first <= level && level <= profile.last)
+        .unwrap()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    // the built-in profiles, with one floor's lines swapped for others
+    fn edited(from: &str, to: &str) -> String {
+        assert!(BUILT_IN_PROFILES.contains(from));
+        BUILT_IN_PROFILES.replacen(from, to, 1)
+    }
+
+
+    #[test]
+    fn the_built_in_profiles_cover_every_floor() {
+        let profiles = parse_profiles(BUILT_IN_PROFILES).unwrap();
+        for level in 0..NUM_LVLS as usize {
+            assert!(profiles.iter().any(|p| p.first <= level && level <= p.last));
+        }
+        assert!(matches!(profile_for(10).generator, Generator::Maze(_)));
+    }
+
+
+    #[test]
+    fn every_floor_needs_exactly_one_profile() {
+        // the Pit and the Well of Souls now stop short, then overlap the Abyss
+        let gap = parse_profiles(&edited("first = 0\nlast = 3", "first = 0\nlast = 2"));
+        assert!(gap.unwrap_err().to_string().contains("floor 3 has 0 profiles"));
+        let overlap = parse_profiles(&edited("first = 0\nlast = 3", "first = 0\nlast = 4"));
+        assert!(overlap.unwrap_err().to_string().contains("floor 4 has 2 profiles"));
+    }
+
+
+    #[test]
+    fn parameters_that_would_break_the_generator_are_refused() {
+        let solid = parse_profiles(&edited("fill_percent = 45", "fill_percent = 100"));
+        assert!(solid.unwrap_err().to_string().contains("floors 4 to 6"));
+        let unknown = parse_profiles(&edited("algorithm = \"maze\"", "algorithm = \"wfc\""));
+        assert!(unknown.is_err());
+        let missing = parse_profiles(&edited("braid_percent = 40\n", ""));
+        assert!(missing.is_err());
+    }
+
+
+    #[test]
+    fn every_floor_has_to_fit_on_the_screen() {
+        let tiny = parse_profiles(&edited("first = 10\nlast = 10", "first = 10\nlast = 10\nwidth = 5"));
+        assert!(tiny.unwrap_err().to_string().contains("5x43 is not between"));
+        let huge = parse_profiles(&edited("first = 10\nlast = 10", "first = 10\nlast = 10\nheight = 500"));
+        assert!(huge.is_err());
+        let small = parse_profiles(&edited("first = 10\nlast = 10", "first = 10\nlast = 10\nwidth = 21\nheight = 15"));
+        assert!(small.is_ok());
+
+        // and the rooms have to fit on the floor
+        let cramped = edited("first = 0\nlast = 3", "first = 0\nlast = 3\nwidth = 20");
+        assert!(parse_profiles(&cramped).is_ok());
+        let cramped = cramped.replacen("room_min_size = 5", "room_min_size = 9", 1);
+        assert!(parse_profiles(&cramped).unwrap_err().to_string().contains("too big for a 20x43 floor"));
+    }
+
+
+    #[test]
+    fn a_broken_file_is_an_error() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("levels.toml");
+        assert!(load_profiles(&path).is_err());
+        fs::write(&path, "[[profile]]\nfirst = ").unwrap();
+        assert!(load_profiles(&path).is_err());
+        fs::write(&path, BUILT_IN_PROFILES).unwrap();
+        assert_eq!(load_profiles(&path).unwrap().len(), parse_profiles(BUILT_IN_PROFILES).unwrap().len());
+    }
+}
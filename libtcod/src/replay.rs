@@ -0,0 +1,365 @@
+/*
+  Recording and Replaying Runs
+
+  Every input that changes the game (commands, menu choices, targets) is
+  appended to the slot's replay file the moment it happens. Since the run is
+  seeded, feeding the same inputs back through `handle_keys` reproduces it,
+  turn for turn.
+
+  The replay file is JSON lines: a header with the seed, then one input per line.
+  A replay only plays back the same on the version of the game that recorded it,
+  so any other version refuses it.
+ */
+// Import Std Libs
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+// Import Third-Party
+use serde::{Deserialize, Serialize};
+
+// Import Locally
+use feb2::constants::REPLAY_EXT;
+use feb2::moves::Command;
+use feb2::objects::Game;
+use feb2::savegame::replay_path;
+use crate::menus::Tcod;
+
+
+#[derive(Serialize, Deserialize)]
+struct ReplayHeader {
+    version: String,
+    seed: u64,
+}
+
+
+impl ReplayHeader {
+    fn check_version(&self) -> Result<(), ReplayError> {
+        if self.version != env!("CARGO_PKG_VERSION") {
+            return Err(ReplayError::WrongVersion(self.version.clone()));
+        }
+        Ok(())
+    }
+}
+
+
+#[derive(Debug)]
+pub enum ReplayError {
+    WrongVersion(String),
+}
+
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::WrongVersion(version) => write!(
+                f,
+                "This replay was recorded by version {} of the game, but this is version {}.",
+                version,
+                env!("CARGO_PKG_VERSION")
+            ),
+        }
+    }
+}
+
+
+impl Error for ReplayError {}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Input {
+    Command(Command),
+    Choice(Option<usize>),
+    Target(Option<(i32, i32)>),
+}
+
+
+pub enum InputLog {
+    Off,
+    Recording(File),
+    Playing {
+        inputs: VecDeque<Input>,
+        speed: usize,
+        paused: bool,
+    },
+    // the replay ran out, or stopped making sense
+    Finished(String),
+}
+
+
+impl InputLog {
+    pub fn is_playing(&self) -> bool {
+        matches!(self, InputLog::Playing { .. })
+    }
+
+    fn record(&mut self, game: &mut Game, input: Input) {
+        if let InputLog::Recording(file) = self {
+            // a replay we can't write to is not worth stopping the game over
+            if let Ok(line) = serde_json::to_string(&input) {
+                if writeln!(file, "{}", line).is_ok() {
+                    game.inputs_recorded += 1;
+                }
+            }
+        }
+    }
+
+    fn next(&mut self) -> Option<Input> {
+        let next = match self {
+            InputLog::Playing { inputs, .. } => inputs.pop_front(),
+            _ => return None,
+        };
+        if next.is_none() {
+            *self = InputLog::Finished("The replay is over.".to_string());
+        }
+        next
+    }
+
+    fn out_of_sync(&mut self, expected: &str) {
+        *self = InputLog::Finished(format!("The replay is out of sync: expected {}.", expected));
+    }
+}
+
+
+// The next command for handle_keys: from the replay if one is playing,
+// otherwise whatever the player pressed (which gets recorded).
+pub fn next_command(tcod: &mut Tcod, game: &mut Game, pressed: Option<Command>) -> Option<Command> {
+    if let InputLog::Playing { paused: true, .. } = tcod.log {
+        return None;
+    }
+    if tcod.log.is_playing() {
+        return match tcod.log.next() {
+            Some(Input::Command(command)) => Some(command),
+            Some(_) => {
+                tcod.log.out_of_sync("a command");
+                None
+            }
+            None => None,
+        };
+    }
+
+    if let Some(command) = pressed {
+        if command.changes_game() {
+            tcod.log.record(game, Input::Command(command));
+        }
+    }
+    pressed
+}
+
+
+// A menu choice: replayed, or asked for and recorded.
+pub fn choose<F>(tcod: &mut Tcod, game: &mut Game, ask: F) -> Option<usize>
+where
+    F: FnOnce(&mut Tcod, &mut Game) -> Option<usize>,
+{
+    if tcod.log.is_playing() {
+        return match tcod.log.next() {
+            Some(Input::Choice(choice)) => choice,
+            Some(_) => {
+                tcod.log.out_of_sync("a menu choice");
+                None
+            }
+            None => None,
+        };
+    }
+
+    let choice = ask(tcod, game);
+    tcod.log.record(game, Input::Choice(choice));
+    choice
+}
+
+
+// A targeted tile: replayed, or asked for and recorded.
+pub fn target<F>(tcod: &mut Tcod, game: &mut Game, ask: F) -> Option<(i32, i32)>
+where
+    F: FnOnce(&mut Tcod, &mut Game) -> Option<(i32, i32)>,
+{
+    if tcod.log.is_playing() {
+        return match tcod.log.next() {
+            Some(Input::Target(target)) => target,
+            Some(_) => {
+                tcod.log.out_of_sync("a target");
+                None
+            }
+            None => None,
+        };
+    }
+
+    let target = ask(tcod, game);
+    tcod.log.record(game, Input::Target(target));
+    target
+}
+
+
+// Start a fresh recording for a brand new game.
+pub fn start_recording(dir: &Path, slot: &str, seed: u64) -> Result<InputLog, Box<dyn Error>> {
+    let header = ReplayHeader {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        seed,
+    };
+    fs::create_dir_all(dir)?;
+    let mut file = File::create(replay_path(dir, slot)?)?;
+    writeln!(file, "{}", serde_json::to_string(&header)?)?;
+    Ok(InputLog::Recording(file))
+}
+
+
+// Pick the recording back up for a loaded game. Anything recorded after that
+// save was made (say, before a crash) never happened, so it is dropped.
+pub fn resume_recording(dir: &Path, slot: &str, game: &Game) -> Result<InputLog, Box<dyn Error>> {
+    let path = replay_path(dir, slot)?;
+    if !path.exists() {
+        // this run was started before replays existed
+        return Ok(InputLog::Off);
+    }
+
+    let text: String = fs::read_to_string(&path)?;
+    let keep: usize = 1 + game.inputs_recorded as usize;
+    let lines: Vec<&str> = text.lines().take(keep).collect();
+    let header: ReplayHeader = serde_json::from_str(lines.first().unwrap_or(&""))?;
+    header.check_version()?;
+    if lines.len() < keep {
+        // the replay is missing inputs, so it could never reproduce this run
+        return Ok(InputLog::Off);
+    }
+
+    let mut file = File::create(&path)?;
+    for line in lines {
+        writeln!(file, "{}", line)?;
+    }
+    file.flush()?;
+    Ok(InputLog::Recording(OpenOptions::new().append(true).open(&path)?))
+}
+
+
+// Read a replay: the seed to start a new game from, and the inputs to feed it.
+pub fn load_replay(dir: &Path, slot: &str, speed: usize) -> Result<(u64, InputLog), Box<dyn Error>> {
+    let text: String = fs::read_to_string(replay_path(dir, slot)?)?;
+    let mut lines = text.lines();
+    let header: ReplayHeader = serde_json::from_str(lines.next().unwrap_or(""))?;
+    header.check_version()?;
+
+    let mut inputs: VecDeque<Input> = VecDeque::new();
+    for line in lines {
+        inputs.push_back(serde_json::from_str(line)?);
+    }
+
+    Ok((header.seed, InputLog::Playing { inputs, speed, paused: false }))
+}
+
+
+// The names of every slot with a recording to watch
+pub fn list_replays(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == REPLAY_EXT) {
+                if let Some(name) = path.file_stem().and_then(|n| n.to_str()) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+    }
+    names.sort();
+    names
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tcod::colors::WHITE;
+    use tempfile::TempDir;
+
+    use feb2::constants::NUM_LVLS;
+    use feb2::objects::Object;
+
+    const INPUTS: [Input; 5] = [
+        Input::Command(Command::Move(1, 0)),
+        Input::Command(Command::Inventory),
+        Input::Choice(Some(2)),
+        Input::Target(Some((4, 7))),
+        Input::Command(Command::Wait),
+    ];
+
+    fn new_game(seed: u64) -> Game {
+        let mut objects: Vec<Vec<Object>> = vec![vec![]; NUM_LVLS as usize];
+        objects[0].push(Object::new(0, 0, '@', "you", WHITE, true));
+        Game::new(&mut objects, seed)
+    }
+
+    fn played_inputs(log: InputLog) -> Vec<Input> {
+        match log {
+            InputLog::Playing { inputs, .. } => inputs.into_iter().collect(),
+            _ => panic!("expected a replay to play"),
+        }
+    }
+
+
+    #[test]
+    fn recorded_inputs_play_back() {
+        let tmp = TempDir::new().unwrap();
+        let mut game = new_game(99);
+        let mut log = start_recording(tmp.path(), "run", 99).unwrap();
+        for input in INPUTS {
+            log.record(&mut game, input);
+        }
+        assert_eq!(game.inputs_recorded, INPUTS.len() as u64);
+
+        let (seed, replay) = load_replay(tmp.path(), "run", 1).unwrap();
+        assert_eq!(seed, 99);
+        assert_eq!(played_inputs(replay), INPUTS.to_vec());
+        assert_eq!(list_replays(tmp.path()), vec!["run".to_string()]);
+    }
+
+
+    #[test]
+    fn resuming_drops_inputs_made_after_the_save() {
+        let tmp = TempDir::new().unwrap();
+        let mut game = new_game(5);
+        let mut log = start_recording(tmp.path(), "run", 5).unwrap();
+        for input in INPUTS {
+            log.record(&mut game, input);
+        }
+
+        // the game was saved after three inputs, then the last two were lost in a crash
+        game.inputs_recorded = 3;
+        let mut log = resume_recording(tmp.path(), "run", &game).unwrap();
+        assert!(matches!(log, InputLog::Recording(_)));
+        log.record(&mut game, Input::Command(Command::GoDown));
+
+        let (_, replay) = load_replay(tmp.path(), "run", 1).unwrap();
+        let mut expected: Vec<Input> = INPUTS[..3].to_vec();
+        expected.push(Input::Command(Command::GoDown));
+        assert_eq!(played_inputs(replay), expected);
+    }
+
+
+    #[test]
+    fn a_replay_missing_inputs_is_not_resumed() {
+        let tmp = TempDir::new().unwrap();
+        let mut game = new_game(5);
+        start_recording(tmp.path(), "run", 5).unwrap();
+        game.inputs_recorded = 2;
+        assert!(matches!(resume_recording(tmp.path(), "run", &game).unwrap(), InputLog::Off));
+        assert!(matches!(resume_recording(tmp.path(), "never", &game).unwrap(), InputLog::Off));
+    }
+
+
+    #[test]
+    fn replays_from_other_versions_are_refused() {
+        let tmp = TempDir::new().unwrap();
+        let header = ReplayHeader { version: "0.0.1".to_string(), seed: 3 };
+        let text = format!("{}\n{}\n", serde_json::to_string(&header).unwrap(), serde_json::to_string(&INPUTS[0]).unwrap());
+        fs::write(replay_path(tmp.path(), "old").unwrap(), text).unwrap();
+
+        let error = load_replay(tmp.path(), "old", 1).err().unwrap().to_string();
+        assert!(error.contains("version 0.0.1"), "{}", error);
+        let game = new_game(3);
+        assert!(resume_recording(tmp.path(), "old", &game).is_err());
+    }
+}
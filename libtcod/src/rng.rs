@@ -0,0 +1,106 @@
+/*
+  The one source of randomness in the game.
+
+  Everything random in play (combat, AI, items) draws from the GameRng owned by
+  Game, so a run is reproducible from its seed. Each floor is built from a
+  generator of its own, seeded from the run's seed and the floor number, so a
+  floor is the same whenever it gets built. It is a tiny SplitMix64 generator
+  because its whole state has to fit in a save game.
+ */
+// Import Std Libs
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Import Third-Party
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GameRng {
+    seed: u64,
+    state: u64,
+}
+
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng {
+            seed,
+            state: seed,
+        }
+    }
+
+    // the seed this generator started from (not where it is now)
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+
+impl Rng for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z: u64 = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+
+// The seed a floor is built from, in the run with this seed.
+pub fn level_seed(seed: u64, level: usize) -> u64 {
+    GameRng::new(seed ^ (level as u64 + 1).wrapping_mul(0xD6E8_FEB8_6659_FD93)).next_u64()
+}
+
+
+// A fresh seed, for when the player doesn't pick one.
+pub fn random_seed() -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    GameRng::new(now.as_nanos() as u64).next_u64()
+}
+
+
+// Turn whatever the player typed into a seed: numbers are used as-is,
+// anything else (like "groundhog") is hashed (FNV-1a).
+pub fn parse_seed(text: &str) -> u64 {
+    let text = text.trim();
+    if let Ok(seed) = text.parse::<u64>() {
+        return seed;
+    }
+
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+    }
+    hash
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_seed_always_gives_the_same_rolls() {
+        let mut a = GameRng::new(42);
+        let mut b = GameRng::new(42);
+        let rolls: Vec<i32> = (0..20).map(|_| a.gen_range(0, 100)).collect();
+        assert_eq!(rolls, (0..20).map(|_| b.gen_range(0, 100)).collect::<Vec<i32>>());
+        assert_ne!(GameRng::new(43).next_u64(), GameRng::new(42).next_u64());
+        assert_eq!(a.seed(), 42);
+    }
+
+
+    #[test]
+    fn seeds_can_be_numbers_or_words() {
+        assert_eq!(parse_seed(" 1234 "), 1234);
+        assert_eq!(parse_seed("groundhog"), parse_seed("groundhog"));
+        assert_ne!(parse_seed("groundhog"), parse_seed("punxsutawney"));
+    }
+}
@@ -0,0 +1,1056 @@
+/*
+  Saving and Loading Games
+
+  Each run lives in its own named slot: a file in the save directory the
+  frontend passes in (SAVE_DIR for the game).
+  Saves are written to a temp file and renamed into place, so a crash mid-write
+  can't eat the run, and the previous save is kept as a ".bak" next to it.
+
+  A save is either plain JSON (for hand-editing) or gzipped CBOR (small and
+  fast, via ciborium). Loading tells them apart by the gzip header, so either works anywhere.
+
+  Every save is stamped with a schema number (`Game.save_version`). When the
+  save layout changes, bump SAVE_VERSION by adding a step to MIGRATIONS that
+  upgrades the raw JSON from the previous schema. Loading runs each step in
+  order, so old runs survive version bumps.
+ */
+// Import Std Libs
+use std::cmp::Reverse;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+// Import Third-Party
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::de::DeserializeOwned;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Import Locally
+use crate::constants::ACTION_COST;
+use crate::constants::ENERGY_TO_ACT;
+use crate::constants::LEGACY_SAVE_FILE;
+use crate::constants::NORMAL_SPEED;
+use crate::constants::PLAYER;
+use crate::constants::REPLAY_EXT;
+use crate::constants::SAVE_EXT;
+use crate::constants::SLOT_NAME_MAX;
+use crate::constants::TORCH_RADIUS;
+use crate::handles::Handles;
+use crate::objects::Game;
+use crate::objects::Object;
+use crate::rng::random_seed;
+use crate::rng::GameRng;
+
+// Everything we write to disk: the game, the current objects, and the objects at the start of Purgatory
+pub type SaveState = (Game, Vec<Vec<Object>>, Vec<Vec<Object>>);
+
+// Migrating from schema N to N+1 is done by MIGRATIONS[N].
+type Migration = fn(&mut Value) -> Result<(), SaveError>;
+const MIGRATIONS: [Migration; 11] = [
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+    migrate_v4_to_v5,
+    migrate_v5_to_v6,
+    migrate_v6_to_v7,
+    migrate_v7_to_v8,
+    migrate_v8_to_v9,
+    migrate_v9_to_v10,
+    migrate_v10_to_v11,
+];
+
+// The schema number of the saves this version of the game writes
+pub const SAVE_VERSION: u32 = MIGRATIONS.len() as u32;
+
+// every gzip stream starts with these two bytes, JSON never does
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SaveFormat {
+    Json,
+    Binary,
+}
+
+
+impl fmt::Display for SaveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SaveFormat::Json => write!(f, "JSON"),
+            SaveFormat::Binary => write!(f, "binary"),
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub enum SaveError {
+    Corrupt(String),
+    NoMigrationPath { from: u32, to: u32 },
+    BadSlotName(String),
+    SlotExists(String),
+}
+
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Corrupt(reason) => write!(f, "The save game is corrupt: {}", reason),
+            SaveError::NoMigrationPath { from, to } => write!(
+                f,
+                "There is no way to upgrade a save from version {} to version {}.",
+                from, to
+            ),
+            SaveError::BadSlotName(name) => write!(
+                f,
+                "\"{}\" is not a valid name. Use up to {} letters, numbers, spaces, '-' or '_'.",
+                name, SLOT_NAME_MAX
+            ),
+            SaveError::SlotExists(name) => write!(f, "There is already a saved game named \"{}\".", name),
+        }
+    }
+}
+
+
+impl Error for SaveError {}
+
+
+// A quick look at a saved game, for the load-game screen
+pub struct SlotSummary {
+    pub name: String,
+    pub loadable: bool,
+    // the save itself is broken, and only its backup can be loaded
+    pub backup_only: bool,
+    pub day: u32,
+    pub turn: u32,
+    pub lvl: usize,
+    pub karma: i32,
+    pub format: SaveFormat,
+    pub modified: SystemTime,
+}
+
+
+// NOTE: JSON save games are human-readable, storable, and editable.
+pub fn save_game(dir: &Path,
+                 slot: &str,
+                 game: &Game,
+                 objects: &Vec<Vec<Object>>,
+                 start_objects: &Vec<Vec<Object>>
+                ) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+    write_save(&slot_path(dir, slot)?, game, objects, start_objects, true)
+}
+
+
+// Write a save to its path, first moving the save already there to the backup if asked.
+fn write_save(path: &Path,
+              game: &Game,
+              objects: &Vec<Vec<Object>>,
+              start_objects: &Vec<Vec<Object>>,
+              rotate_backup: bool
+             ) -> Result<(), Box<dyn Error>> {
+    let tmp_path = with_suffix(path, "tmp");
+    let save_state = (game, objects, start_objects);
+
+    // write the whole save out, and make sure it hit the disk, before touching the old one
+    let file = File::create(&tmp_path)?;
+    let file = match game.save_format {
+        SaveFormat::Json => {
+            let mut file = file;
+            serde_json::to_writer(&mut file, &save_state)?;
+            file
+        }
+        SaveFormat::Binary => {
+            let mut encoder = GzEncoder::new(file, Compression::default());
+            ciborium::into_writer(&save_state, &mut encoder)?;
+            encoder.finish()?
+        }
+    };
+    file.sync_all()?;
+
+    // the last good save becomes the backup
+    if rotate_backup && path.exists() {
+        fs::rename(path, with_suffix(path, "bak"))?;
+    }
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+
+// Load a slot, falling back to its backup if the save itself is missing or broken.
+pub fn load_game(dir: &Path, slot: &str) -> Result<SaveState, Box<dyn Error>> {
+    let path = slot_path(dir, slot)?;
+    match load_game_from(&path) {
+        Ok(result) => Ok(result),
+        Err(e) => load_game_from(&with_suffix(&path, "bak")).map_err(|_| e),
+    }
+}
+
+
+fn load_game_from(path: &Path) -> Result<SaveState, Box<dyn Error>> {
+    // both formats decode to the same raw value, so they share the migrations
+    let (mut raw, _): (Value, SaveFormat) = read_save(path)?;
+    migrate(&mut raw)?;
+
+    let (mut game, objects, start_objects) = serde_json::from_value::<SaveState>(raw)?;
+    check_speeds(&objects)?;
+    check_speeds(&start_objects)?;
+    game.version = env!("CARGO_PKG_VERSION").to_string();
+    // FOV (and the spatial index) isn't saved; work it out again from where everyone stands
+    match objects.get(game.lvl) {
+        Some(floor) if floor.len() > PLAYER => game.restore(floor),
+        _ => return Err(Box::new(SaveError::Corrupt("the player is missing".to_string()))),
+    }
+    Ok((game, objects, start_objects))
+}
+
+
+// A fighter with no speed would never get a turn, and time would stand still waiting for it.
+fn check_speeds(floors: &[Vec<Object>]) -> Result<(), SaveError> {
+    for object in floors.iter().flatten() {
+        if object.fighter.as_ref().is_some_and(|fighter| fighter.speed <= 0) {
+            return Err(SaveError::Corrupt(format!("the {} has no speed", object.name)));
+        }
+    }
+    Ok(())
+}
+
+
+// Slot names double as file names, so keep them short and boring.
+pub fn valid_slot_name(name: &str) -> bool {
+    !name.trim().is_empty()
+        && name.len() <= SLOT_NAME_MAX
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == ' ' || c == '-' || c == '_')
+}
+
+
+fn slot_path(dir: &Path, slot: &str) -> Result<PathBuf, SaveError> {
+    if !valid_slot_name(slot) {
+        return Err(SaveError::BadSlotName(slot.to_string()));
+    }
+    Ok(dir.join(format!("{}.{}", slot, SAVE_EXT)))
+}
+
+
+// "saves/run 1.savegame" -> "saves/run 1.savegame.bak"
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+
+// The recording of every input made in this slot's run
+pub fn replay_path(dir: &Path, slot: &str) -> Result<PathBuf, SaveError> {
+    if !valid_slot_name(slot) {
+        return Err(SaveError::BadSlotName(slot.to_string()));
+    }
+    Ok(dir.join(format!("{}.{}", slot, REPLAY_EXT)))
+}
+
+
+pub fn slot_exists(dir: &Path, slot: &str) -> bool {
+    slot_path(dir, slot).is_ok_and(|p| p.exists())
+}
+
+
+// Just enough of a save to list it, read without migrating (or building) the whole game.
+// These fields are in every save, back to the first.
+#[derive(Deserialize)]
+struct SaveHeader(GameHeader, Vec<Vec<ObjectHeader>>, IgnoredAny);
+
+
+#[derive(Deserialize)]
+struct GameHeader {
+    // the first saves weren't numbered
+    #[serde(default)]
+    save_version: u32,
+    day: u32,
+    turn: u32,
+    lvl: usize,
+}
+
+
+#[derive(Deserialize)]
+struct ObjectHeader {
+    fighter: Option<FighterHeader>,
+}
+
+
+#[derive(Deserialize)]
+struct FighterHeader {
+    karma: i32,
+}
+
+
+// Decode a save in whichever format it was written, telling them apart by the gzip header.
+fn read_save<T: DeserializeOwned>(path: &Path) -> Result<(T, SaveFormat), Box<dyn Error>> {
+    let save_data: Vec<u8> = fs::read(path)?;
+    if save_data.starts_with(&GZIP_MAGIC) {
+        Ok((ciborium::from_reader(GzDecoder::new(&save_data[..]))?, SaveFormat::Binary))
+    } else {
+        Ok((serde_json::from_slice(&save_data)?, SaveFormat::Json))
+    }
+}
+
+
+fn read_header(path: &Path) -> Result<(SaveHeader, SaveFormat), Box<dyn Error>> {
+    let (header, format): (SaveHeader, SaveFormat) = read_save(path)?;
+
+    let SaveHeader(game, objects, _) = &header;
+    if game.save_version > SAVE_VERSION {
+        return Err(Box::new(SaveError::NoMigrationPath { from: game.save_version, to: SAVE_VERSION }));
+    }
+    if objects.get(game.lvl).is_none_or(|floor| floor.get(PLAYER).is_none()) {
+        return Err(Box::new(SaveError::Corrupt("the player is missing".to_string())));
+    }
+    Ok((header, format))
+}
+
+
+// List every saved game, most recently played first.
+pub fn list_slots(dir: &Path) -> Vec<SlotSummary> {
+    import_legacy_save(dir);
+
+    let mut slots: Vec<SlotSummary> = vec![];
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return slots,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != SAVE_EXT) {
+            continue;
+        }
+        let name: String = match path.file_stem().and_then(|n| n.to_str()) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let modified = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let mut summary = SlotSummary {
+            name,
+            loadable: false,
+            backup_only: false,
+            day: 0,
+            turn: 0,
+            lvl: 0,
+            karma: 0,
+            format: SaveFormat::Json,
+            modified,
+        };
+        let header = match read_header(&path) {
+            Ok(header) => Ok(header),
+            Err(_) => read_header(&with_suffix(&path, "bak")).inspect(|_header| {
+                summary.backup_only = true;
+            }),
+        };
+        if let Ok((SaveHeader(game, objects, _), format)) = header {
+            summary.loadable = true;
+            summary.day = game.day;
+            summary.turn = game.turn;
+            summary.lvl = game.lvl;
+            summary.format = format;
+            summary.karma = objects[game.lvl][PLAYER].fighter.as_ref().map_or(0, |f| f.karma);
+        }
+        slots.push(summary);
+    }
+
+    slots.sort_by_key(|slot| Reverse(slot.modified));
+    slots
+}
+
+
+pub fn delete_slot(dir: &Path, slot: &str) -> Result<(), Box<dyn Error>> {
+    let path = slot_path(dir, slot)?;
+    fs::remove_file(&path)?;
+    let _ = fs::remove_file(with_suffix(&path, "bak"));
+    let _ = fs::remove_file(replay_path(dir, slot)?);
+    Ok(())
+}
+
+
+pub fn rename_slot(dir: &Path, from: &str, to: &str) -> Result<(), Box<dyn Error>> {
+    let from_path = slot_path(dir, from)?;
+    let to_path = slot_path(dir, to)?;
+    if from == to {
+        return Ok(());
+    }
+    if to_path.exists() {
+        return Err(Box::new(SaveError::SlotExists(to.to_string())));
+    }
+    fs::rename(&from_path, &to_path)?;
+    let _ = fs::rename(with_suffix(&from_path, "bak"), with_suffix(&to_path, "bak"));
+    let _ = fs::rename(replay_path(dir, from)?, replay_path(dir, to)?);
+    Ok(())
+}
+
+
+// Re-write a saved game in the other format.
+pub fn convert_slot(dir: &Path, slot: &str, format: SaveFormat) -> Result<(), Box<dyn Error>> {
+    let path = slot_path(dir, slot)?;
+    let ((mut game, objects, start_objects), rotate_backup) = match load_game_from(&path) {
+        Ok(save_state) => (save_state, true),
+        // a save that won't load is no backup: write over it, and keep the good one
+        Err(e) => (load_game_from(&with_suffix(&path, "bak")).map_err(|_| e)?, false),
+    };
+    game.save_format = format;
+    write_save(&path, &game, &objects, &start_objects, rotate_backup)
+}
+
+
+// Older versions kept a single save next to the save directory; move it into its own slot.
+fn import_legacy_save(dir: &Path) {
+    let legacy: PathBuf = dir.parent().unwrap_or(Path::new("")).join(LEGACY_SAVE_FILE);
+    if !legacy.exists() || slot_exists(dir, "imported") {
+        return;
+    }
+    if let Ok(path) = slot_path(dir, "imported") {
+        if fs::create_dir_all(dir).is_ok() {
+            let _ = fs::rename(legacy, path);
+        }
+    }
+}
+
+
+// Upgrade a raw save, one schema at a time, until it matches SAVE_VERSION.
+fn migrate(raw: &mut Value) -> Result<(), SaveError> {
+    let from: u32 = save_version(raw)?;
+    if from > SAVE_VERSION {
+        // a save from the future: we can't know how to read it
+        return Err(SaveError::NoMigrationPath { from, to: SAVE_VERSION });
+    }
+
+    for step in from..SAVE_VERSION {
+        MIGRATIONS[step as usize](raw)?;
+        game_mut(raw)?.insert("save_version".to_string(), Value::from(step + 1));
+    }
+    Ok(())
+}
+
+
+// Saves made before schema numbers existed count as schema zero.
+fn save_version(raw: &Value) -> Result<u32, SaveError> {
+    let game = raw
+        .get(0)
+        .and_then(|g| g.as_object())
+        .ok_or(SaveError::Corrupt("missing the game data".to_string()))?;
+    match game.get("save_version") {
+        None => Ok(0),
+        Some(v) => v
+            .as_u64()
+            .map(|v| v as u32)
+            .ok_or(SaveError::Corrupt("save_version is not a number".to_string())),
+    }
+}
+
+
+fn game_mut(raw: &mut Value) -> Result<&mut serde_json::Map<String, Value>, SaveError> {
+    raw.get_mut(0)
+       .and_then(|g| g.as_object_mut())
+       .ok_or(SaveError::Corrupt("missing the game data".to_string()))
+}
+
+
+/*
+  Migration Steps
+ */
+
+// v0 -> v1: The first numbered schema. The layout is unchanged, but the save must be
+//           the (Game, objects, start_objects) triple the game has always written.
+fn migrate_v0_to_v1(raw: &mut Value) -> Result<(), SaveError> {
+    match raw.as_array() {
+        Some(parts) if parts.len() == 3 => Ok(()),
+        _ => Err(SaveError::Corrupt("expected a (game, objects, start objects) triple".to_string())),
+    }
+}
+
+
+// v1 -> v2: Saves remember their format. Everything before this was JSON.
+fn migrate_v1_to_v2(raw: &mut Value) -> Result<(), SaveError> {
+    game_mut(raw)?.insert("save_format".to_string(), Value::from("Json"));
+    Ok(())
+}
+
+
+// v2 -> v3: The game owns a seeded RNG. Older runs never had a seed, so they get a fresh one.
+fn migrate_v2_to_v3(raw: &mut Value) -> Result<(), SaveError> {
+    let rng = serde_json::to_value(GameRng::new(random_seed()))
+        .map_err(|e| SaveError::Corrupt(e.to_string()))?;
+    game_mut(raw)?.insert("rng".to_string(), rng);
+    Ok(())
+}
+
+
+// v3 -> v4: Runs are recorded for replays. Older runs have no recording to line up with.
+fn migrate_v3_to_v4(raw: &mut Value) -> Result<(), SaveError> {
+    game_mut(raw)?.insert("inputs_recorded".to_string(), Value::from(0));
+    Ok(())
+}
+
+
+// v4 -> v5: Tiles have a kind. Everything before this was wall or floor.
+fn migrate_v4_to_v5(raw: &mut Value) -> Result<(), SaveError> {
+    let maps = game_mut(raw)?
+        .get_mut("maps")
+        .and_then(|maps| maps.as_array_mut())
+        .ok_or(SaveError::Corrupt("missing the maps".to_string()))?;
+    for column in maps.iter_mut().flat_map(|map| map.as_array_mut()).flatten() {
+        for tile in column.as_array_mut().into_iter().flatten() {
+            let tile = tile
+                .as_object_mut()
+                .ok_or(SaveError::Corrupt("a map tile is not an object".to_string()))?;
+            let blocked = tile.get("blocked").and_then(|b| b.as_bool()).unwrap_or(false);
+            let kind = if blocked { "Wall" } else { "Floor" };
+            tile.insert("kind".to_string(), Value::from(kind));
+        }
+    }
+    Ok(())
+}
+
+
+// v5 -> v6: Each map is one flat list of tiles (column by column) with its size,
+//           instead of a list of columns.
+fn migrate_v5_to_v6(raw: &mut Value) -> Result<(), SaveError> {
+    let maps = game_mut(raw)?
+        .get_mut("maps")
+        .and_then(|maps| maps.as_array_mut())
+        .ok_or(SaveError::Corrupt("missing the maps".to_string()))?;
+    for map in maps.iter_mut() {
+        let columns: Vec<Value> = match map.take() {
+            Value::Array(columns) => columns,
+            _ => return Err(SaveError::Corrupt("a map is not a list of columns".to_string())),
+        };
+        let width = columns.len();
+        let height = columns.first().and_then(|column| column.as_array()).map_or(0, |column| column.len());
+        let mut tiles: Vec<Value> = Vec::with_capacity(width * height);
+        for column in columns {
+            match column {
+                Value::Array(column) if column.len() == height => tiles.extend(column),
+                _ => return Err(SaveError::Corrupt("the map columns are ragged".to_string())),
+            }
+        }
+        *map = serde_json::json!({ "width": width, "height": height, "tiles": tiles });
+    }
+    Ok(())
+}
+
+
+// v6 -> v7: Objects have handles, handed out by the game. Every object (on a floor,
+//           or carried) gets a new one, in order.
+fn migrate_v6_to_v7(raw: &mut Value) -> Result<(), SaveError> {
+    let mut handles = Handles::default();
+    for part in 1..3 {
+        let floors = raw
+            .get_mut(part)
+            .and_then(|floors| floors.as_array_mut())
+            .ok_or(SaveError::Corrupt("missing the objects".to_string()))?;
+        for floor in floors.iter_mut() {
+            let objects = floor
+                .as_array_mut()
+                .ok_or(SaveError::Corrupt("a floor's objects are not a list".to_string()))?;
+            add_handles(objects, &mut handles)?;
+        }
+    }
+    let handles = serde_json::to_value(handles).map_err(|e| SaveError::Corrupt(e.to_string()))?;
+    game_mut(raw)?.insert("handles".to_string(), handles);
+    Ok(())
+}
+
+
+fn add_handles(objects: &mut [Value], handles: &mut Handles) -> Result<(), SaveError> {
+    for object in objects.iter_mut() {
+        let object = object
+            .as_object_mut()
+            .ok_or(SaveError::Corrupt("an object is not an object".to_string()))?;
+        let handle = serde_json::to_value(handles.allocate()).map_err(|e| SaveError::Corrupt(e.to_string()))?;
+        object.insert("handle".to_string(), handle);
+        let inventory = object
+            .get_mut("fighter")
+            .and_then(|fighter| fighter.get_mut("inventory"))
+            .and_then(|inventory| inventory.as_array_mut());
+        if let Some(inventory) = inventory {
+            add_handles(inventory, handles)?;
+        }
+    }
+    Ok(())
+}
+
+
+// v7 -> v8: The NPCs' planned paths are saved. An older run had none.
+fn migrate_v7_to_v8(raw: &mut Value) -> Result<(), SaveError> {
+    game_mut(raw)?.insert("paths".to_string(), serde_json::json!({ "paths": [] }));
+    Ok(())
+}
+
+
+// v8 -> v9: Fighters have a speed, energy, and the cost of an attack. Everyone
+//           in an older run moves at normal speed, and starts out with no energy,
+//           except the player: it's their move, as in a new game.
+fn migrate_v8_to_v9(raw: &mut Value) -> Result<(), SaveError> {
+    let lvl = game_mut(raw)?
+        .get("lvl")
+        .and_then(|lvl| lvl.as_u64())
+        .ok_or(SaveError::Corrupt("missing the floor number".to_string()))? as usize;
+    for part in 1..3 {
+        let floors = raw
+            .get_mut(part)
+            .and_then(|floors| floors.as_array_mut())
+            .ok_or(SaveError::Corrupt("missing the objects".to_string()))?;
+        for object in floors.iter_mut().flat_map(|floor| floor.as_array_mut()).flatten() {
+            if let Some(fighter) = object.get_mut("fighter").and_then(|fighter| fighter.as_object_mut()) {
+                fighter.insert("speed".to_string(), Value::from(NORMAL_SPEED));
+                fighter.insert("energy".to_string(), Value::from(0));
+                fighter.insert("attack_cost".to_string(), Value::from(ACTION_COST));
+            }
+        }
+    }
+    let player = raw
+        .get_mut(1)
+        .and_then(|floors| floors.get_mut(lvl))
+        .and_then(|floor| floor.get_mut(PLAYER))
+        .and_then(|player| player.get_mut("fighter"))
+        .and_then(|fighter| fighter.as_object_mut())
+        .ok_or(SaveError::Corrupt("the player is missing".to_string()))?;
+    player.insert("energy".to_string(), Value::from(ENERGY_TO_ACT));
+    Ok(())
+}
+
+
+// v9 -> v10: Fighters can see for themselves. In an older run, everyone sees as
+//            far as the player's torch.
+fn migrate_v9_to_v10(raw: &mut Value) -> Result<(), SaveError> {
+    for part in 1..3 {
+        let floors = raw
+            .get_mut(part)
+            .and_then(|floors| floors.as_array_mut())
+            .ok_or(SaveError::Corrupt("missing the objects".to_string()))?;
+        for object in floors.iter_mut().flat_map(|floor| floor.as_array_mut()).flatten() {
+            if let Some(fighter) = object.get_mut("fighter").and_then(|fighter| fighter.as_object_mut()) {
+                fighter.insert("vision".to_string(), Value::from(TORCH_RADIUS));
+            }
+        }
+    }
+    Ok(())
+}
+
+
+// v10 -> v11: The player can sneak, and the noises not heard yet are saved with
+//             the game. Nobody in an older run was sneaking, or kept its noises.
+fn migrate_v10_to_v11(raw: &mut Value) -> Result<(), SaveError> {
+    let game = game_mut(raw)?;
+    game.insert("sneaking".to_string(), Value::from(false));
+    game.insert("noises".to_string(), Value::Array(vec![]));
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use serde_json::json;
+    use tcod::colors::WHITE;
+    use tempfile::TempDir;
+
+    use crate::constants::NUM_LVLS;
+    use crate::objects::Fighter;
+    use crate::pathfinding::find_path;
+
+    // an old save, with just the player (on the first floor)
+    fn old_save(mut game: Value) -> Value {
+        game["lvl"] = json!(0);
+        json!([game, [[{"name": "you", "fighter": {"inventory": []}}]], []])
+    }
+
+
+    // a fresh run: just the player, and the floors built around them
+    fn new_run() -> SaveState {
+        let mut player = Object::new(0, 0, '@', "you", WHITE, true);
+        player.alive = true;
+        player.fighter = Some(Fighter::new(100, 2, 3, 0, false));
+        let mut objects: Vec<Vec<Object>> = vec![vec![]; NUM_LVLS as usize];
+        objects[0].push(player);
+        let game = Game::new(&mut objects, 1);
+        let starts = objects.clone();
+        (game, objects, starts)
+    }
+
+
+    #[test]
+    fn slot_names_are_short_and_boring() {
+        assert!(valid_slot_name("run 1"));
+        assert!(valid_slot_name("Purgatory_run-2"));
+        assert!(!valid_slot_name(""));
+        assert!(!valid_slot_name("   "));
+        assert!(!valid_slot_name("../escape"));
+        assert!(!valid_slot_name("a name far too long"));
+    }
+
+
+    #[test]
+    fn saved_slots_are_listed_renamed_and_deleted() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().join("saves");
+        let (mut game, objects, starts) = new_run();
+        game.day = 3;
+        game.turn = 42;
+        save_game(&dir, "first", &game, &objects, &starts).unwrap();
+        assert!(slot_exists(&dir, "first"));
+
+        let slots = list_slots(&dir);
+        assert_eq!(slots.len(), 1);
+        assert!(slots[0].loadable);
+        assert_eq!((slots[0].name.as_str(), slots[0].day, slots[0].turn, slots[0].lvl), ("first", 3, 42, 0));
+
+        save_game(&dir, "second", &game, &objects, &starts).unwrap();
+        assert!(rename_slot(&dir, "first", "first").is_ok());
+        assert!(rename_slot(&dir, "first", "second").is_err());
+        rename_slot(&dir, "first", "third").unwrap();
+        assert!(!slot_exists(&dir, "first"));
+        let (loaded, _, _) = load_game(&dir, "third").unwrap();
+        assert_eq!(loaded.turn, 42);
+
+        delete_slot(&dir, "third").unwrap();
+        delete_slot(&dir, "second").unwrap();
+        assert!(list_slots(&dir).is_empty());
+    }
+
+
+    #[test]
+    fn broken_slots_are_listed_but_not_loadable() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().join("saves");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(format!("junk.{}", SAVE_EXT)), "not a save").unwrap();
+
+        let slots = list_slots(&dir);
+        assert_eq!(slots.len(), 1);
+        assert_eq!(slots[0].name, "junk");
+        assert!(!slots[0].loadable);
+        assert!(load_game(&dir, "junk").is_err());
+    }
+
+
+    #[test]
+    fn the_last_good_save_is_kept_as_a_backup() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().join("saves");
+        let (mut game, objects, starts) = new_run();
+        game.turn = 10;
+        save_game(&dir, "run", &game, &objects, &starts).unwrap();
+        game.turn = 20;
+        save_game(&dir, "run", &game, &objects, &starts).unwrap();
+
+        let path = slot_path(&dir, "run").unwrap();
+        assert!(!with_suffix(&path, "tmp").exists());
+        let (backup, _, _) = load_game_from(&with_suffix(&path, "bak")).unwrap();
+        assert_eq!(backup.turn, 10);
+        assert_eq!(load_game(&dir, "run").unwrap().0.turn, 20);
+    }
+
+
+    #[test]
+    fn a_broken_save_falls_back_to_its_backup() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().join("saves");
+        let (mut game, objects, starts) = new_run();
+        game.turn = 10;
+        save_game(&dir, "run", &game, &objects, &starts).unwrap();
+        game.turn = 20;
+        save_game(&dir, "run", &game, &objects, &starts).unwrap();
+        fs::write(slot_path(&dir, "run").unwrap(), "{\"half a save").unwrap();
+
+        assert_eq!(load_game(&dir, "run").unwrap().0.turn, 10);
+        let slots = list_slots(&dir);
+        assert!(slots[0].loadable);
+        assert!(slots[0].backup_only);
+        assert_eq!(slots[0].turn, 10);
+    }
+
+
+    #[test]
+    fn backups_follow_their_slot() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().join("saves");
+        let (game, objects, starts) = new_run();
+        save_game(&dir, "run", &game, &objects, &starts).unwrap();
+        save_game(&dir, "run", &game, &objects, &starts).unwrap();
+
+        rename_slot(&dir, "run", "renamed").unwrap();
+        let path = slot_path(&dir, "renamed").unwrap();
+        assert!(with_suffix(&path, "bak").exists());
+        assert!(!with_suffix(&slot_path(&dir, "run").unwrap(), "bak").exists());
+
+        delete_slot(&dir, "renamed").unwrap();
+        assert!(!with_suffix(&path, "bak").exists());
+    }
+
+
+    #[test]
+    fn binary_saves_load_and_list_like_json_ones() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().join("saves");
+        let (mut game, objects, starts) = new_run();
+        game.turn = 33;
+        game.save_format = SaveFormat::Binary;
+        save_game(&dir, "small", &game, &objects, &starts).unwrap();
+        game.save_format = SaveFormat::Json;
+        save_game(&dir, "big", &game, &objects, &starts).unwrap();
+
+        assert!(fs::read(slot_path(&dir, "small").unwrap()).unwrap().starts_with(&GZIP_MAGIC));
+        let (loaded, loaded_objects, _) = load_game(&dir, "small").unwrap();
+        assert_eq!(loaded.turn, 33);
+        assert_eq!(loaded.save_format, SaveFormat::Binary);
+        assert_eq!(loaded_objects[0][PLAYER].pos(), objects[0][PLAYER].pos());
+
+        let mut slots = list_slots(&dir);
+        slots.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!((slots[0].format, slots[0].turn), (SaveFormat::Json, 33));
+        assert_eq!((slots[1].format, slots[1].turn), (SaveFormat::Binary, 33));
+    }
+
+
+    #[test]
+    fn converting_a_slot_keeps_the_game() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().join("saves");
+        let (mut game, objects, starts) = new_run();
+        game.turn = 12;
+        game.save_format = SaveFormat::Json;
+        save_game(&dir, "run", &game, &objects, &starts).unwrap();
+
+        convert_slot(&dir, "run", SaveFormat::Binary).unwrap();
+        let path = slot_path(&dir, "run").unwrap();
+        assert!(fs::read(&path).unwrap().starts_with(&GZIP_MAGIC));
+        assert_eq!(load_game(&dir, "run").unwrap().0.turn, 12);
+        // the JSON save is now the backup
+        assert_eq!(read_save::<Value>(&with_suffix(&path, "bak")).unwrap().1, SaveFormat::Json);
+    }
+
+
+    #[test]
+    fn converting_a_broken_slot_keeps_its_backup() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().join("saves");
+        let (mut game, objects, starts) = new_run();
+        game.turn = 12;
+        game.save_format = SaveFormat::Json;
+        save_game(&dir, "run", &game, &objects, &starts).unwrap();
+        save_game(&dir, "run", &game, &objects, &starts).unwrap();
+        let path = slot_path(&dir, "run").unwrap();
+        let backup: Vec<u8> = fs::read(with_suffix(&path, "bak")).unwrap();
+        fs::write(&path, "{\"half a save").unwrap();
+
+        convert_slot(&dir, "run", SaveFormat::Binary).unwrap();
+        assert_eq!(fs::read(with_suffix(&path, "bak")).unwrap(), backup);
+        let (loaded, _, _) = load_game_from(&path).unwrap();
+        assert_eq!((loaded.turn, loaded.save_format), (12, SaveFormat::Binary));
+    }
+
+
+    #[test]
+    fn the_rng_picks_up_where_it_was_saved() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().join("saves");
+        let (mut game, objects, starts) = new_run();
+        game.rng.next_u64();
+        save_game(&dir, "run", &game, &objects, &starts).unwrap();
+
+        let (mut loaded, _, _) = load_game(&dir, "run").unwrap();
+        assert_eq!(loaded.rng, game.rng);
+        assert_eq!(loaded.rng.next_u64(), game.rng.next_u64());
+    }
+
+
+    #[test]
+    fn v2_saves_get_a_seeded_rng() {
+        let mut raw = old_save(json!({"save_version": 2, "maps": []}));
+        migrate(&mut raw).unwrap();
+        assert!(serde_json::from_value::<GameRng>(raw[0]["rng"].clone()).is_ok());
+    }
+
+
+    #[test]
+    fn v1_saves_were_json() {
+        let mut raw = old_save(json!({"save_version": 1, "maps": []}));
+        migrate(&mut raw).unwrap();
+        assert_eq!(raw[0]["save_format"], "Json");
+    }
+
+
+    #[test]
+    fn v4_tiles_are_wall_or_floor() {
+        let wall = json!({"blocked": true, "block_sight": true, "explored": false});
+        let floor = json!({"blocked": false, "block_sight": false, "explored": true});
+        let mut raw = old_save(json!({"save_version": 4, "maps": [[[wall, floor]]]}));
+        migrate(&mut raw).unwrap();
+        assert_eq!(raw[0]["maps"][0]["tiles"][0]["kind"], "Wall");
+        assert_eq!(raw[0]["maps"][0]["tiles"][1]["kind"], "Floor");
+
+        let mut raw = old_save(json!({"save_version": 4, "maps": [[["not a tile"]]]}));
+        assert!(matches!(migrate(&mut raw), Err(SaveError::Corrupt(_))));
+    }
+
+
+    #[test]
+    fn v5_maps_are_flattened_column_by_column() {
+        let mut raw = old_save(json!({"save_version": 5, "maps": [[["a", "b", "c"], ["d", "e", "f"]]]}));
+        migrate(&mut raw).unwrap();
+        assert_eq!(raw[0]["maps"][0], json!({"width": 2, "height": 3, "tiles": ["a", "b", "c", "d", "e", "f"]}));
+
+        let mut raw = old_save(json!({"save_version": 5, "maps": [[["a", "b"], ["c"]]]}));
+        assert!(matches!(migrate(&mut raw), Err(SaveError::Corrupt(_))));
+    }
+
+
+    #[test]
+    fn v6_objects_and_what_they_carry_get_handles() {
+        let carrier = json!({"name": "you", "fighter": {"inventory": [{"name": "dagger"}]}});
+        let mut raw = json!([{"save_version": 6, "lvl": 0}, [[carrier.clone()], [{"name": "orc"}]], [[carrier]]]);
+        migrate(&mut raw).unwrap();
+        assert_eq!(raw[1][0][0]["handle"], json!({"slot": 0, "generation": 0}));
+        assert_eq!(raw[1][0][0]["fighter"]["inventory"][0]["handle"]["slot"], 1);
+        assert_eq!(raw[1][1][0]["handle"]["slot"], 2);
+        // the start-of-run copies are separate objects, so get handles of their own
+        assert_eq!(raw[2][0][0]["handle"]["slot"], 3);
+        assert_eq!(raw[0]["handles"]["generations"].as_array().unwrap().len(), 5);
+    }
+
+
+    #[test]
+    fn v7_saves_start_with_no_paths_planned() {
+        let mut raw = old_save(json!({"save_version": 7}));
+        migrate(&mut raw).unwrap();
+        assert_eq!(raw[0]["paths"], json!({"paths": []}));
+    }
+
+
+    #[test]
+    fn v8_fighters_move_at_normal_speed_and_the_player_goes_first() {
+        let mut raw = old_save(json!({"save_version": 8}));
+        raw[1][0].as_array_mut().unwrap().push(json!({"name": "orc", "fighter": {"inventory": []}}));
+        migrate(&mut raw).unwrap();
+        assert_eq!(raw[1][0][0]["fighter"]["energy"], ENERGY_TO_ACT);
+        assert_eq!(raw[1][0][1]["fighter"]["energy"], 0);
+        assert_eq!(raw[1][0][1]["fighter"]["speed"], NORMAL_SPEED);
+        assert_eq!(raw[1][0][1]["fighter"]["attack_cost"], ACTION_COST);
+    }
+
+
+    #[test]
+    fn v9_fighters_see_as_far_as_the_torch() {
+        let mut raw = old_save(json!({"save_version": 9}));
+        migrate(&mut raw).unwrap();
+        assert_eq!(raw[1][0][0]["fighter"]["vision"], TORCH_RADIUS);
+    }
+
+
+    #[test]
+    fn v10_player_is_not_sneaking_and_heard_everything() {
+        let mut raw = old_save(json!({"save_version": 10}));
+        migrate(&mut raw).unwrap();
+        assert_eq!(raw[0]["sneaking"], false);
+        assert_eq!(raw[0]["noises"], json!([]));
+    }
+
+
+    #[test]
+    fn a_fighter_with_no_speed_is_refused() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().join("saves");
+        let (game, mut objects, starts) = new_run();
+        objects[0][PLAYER].fighter.as_mut().unwrap().speed = 0;
+        save_game(&dir, "run", &game, &objects, &starts).unwrap();
+        assert!(load_game(&dir, "run").is_err());
+    }
+
+
+    #[test]
+    fn a_loaded_game_keeps_the_paths_the_npcs_planned() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().join("saves");
+        let (mut game, objects, starts) = new_run();
+        game.enter_floor(&objects[0]);
+        let (walker, from) = (objects[0][PLAYER].handle, objects[0][PLAYER].pos());
+        // somewhere a few steps off
+        let goal = game.maps[0]
+            .positions()
+            .find(|&to| find_path(&game.maps[0], &game.index, from, to).is_some_and(|steps| steps.len() > 3))
+            .unwrap();
+        game.paths.next_step(walker, from, goal, &game.maps[0], &game.index).unwrap();
+        save_game(&dir, "run", &game, &objects, &starts).unwrap();
+
+        let (mut loaded, _, _) = load_game(&dir, "run").unwrap();
+        assert_eq!(serde_json::to_value(&loaded.paths).unwrap(), serde_json::to_value(&game.paths).unwrap());
+        // so both carry on the same way
+        let next = game.paths.next_step(walker, from, goal, &game.maps[0], &game.index);
+        assert_eq!(loaded.paths.next_step(walker, from, goal, &loaded.maps[0], &loaded.index), next);
+    }
+
+
+    #[test]
+    fn the_legacy_save_is_imported_into_a_slot() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().join("saves");
+        let (game, objects, starts) = new_run();
+        let legacy = tmp.path().join(LEGACY_SAVE_FILE);
+        fs::write(&legacy, serde_json::to_string(&(&game, &objects, &starts)).unwrap()).unwrap();
+
+        let slots = list_slots(&dir);
+        assert_eq!(slots.len(), 1);
+        assert_eq!(slots[0].name, "imported");
+        assert!(slots[0].loadable);
+        assert!(!legacy.exists());
+    }
+
+
+    #[test]
+    fn unnumbered_saves_upgrade_to_the_current_schema() {
+        let mut raw = old_save(json!({"turn": 7, "maps": []}));
+        migrate(&mut raw).unwrap();
+        assert_eq!(save_version(&raw).unwrap(), SAVE_VERSION);
+        assert_eq!(raw[0]["turn"], 7);
+    }
+
+
+    #[test]
+    fn saves_from_the_future_are_refused() {
+        let mut raw = json!([{"save_version": SAVE_VERSION + 1}, [], []]);
+        match migrate(&mut raw) {
+            Err(SaveError::NoMigrationPath { from, to }) => {
+                assert_eq!(from, SAVE_VERSION + 1);
+                assert_eq!(to, SAVE_VERSION);
+            }
+            other => panic!("expected NoMigrationPath, got {:?}", other),
+        }
+    }
+
+
+    #[test]
+    fn a_save_that_is_not_a_triple_is_corrupt() {
+        let mut raw = json!([{}, []]);
+        assert!(matches!(migrate(&mut raw), Err(SaveError::Corrupt(_))));
+    }
+
+
+    #[test]
+    fn a_save_version_that_is_not_a_number_is_corrupt() {
+        let mut raw = json!([{"save_version": "one"}, [], []]);
+        assert!(matches!(migrate(&mut raw), Err(SaveError::Corrupt(_))));
+        let mut raw = json!("not a save");
+        assert!(matches!(migrate(&mut raw), Err(SaveError::Corrupt(_))));
+    }
+}
@@ -0,0 +1,168 @@
+/*
+  Spatial Index
+
+  Which objects are on each tile of the player's floor (by their id in the
+  floor's object list), and how many of them block. Asking "what is here?"
+  no longer means looking at every object on the floor.
+
+  It isn't saved: it is rebuilt whenever the player arrives on a floor (or a
+  game is loaded), and kept up to date as objects move, are picked up, are
+  dropped or die.
+ */
+// Import Locally
+use crate::map::Map;
+use crate::objects::Object;
+
+
+#[derive(Default)]
+pub struct SpatialIndex {
+    width: i32,
+    height: i32,
+    // the ids on each tile, lowest first (column-major, like the Map)
+    contents: Vec<Vec<usize>>,
+    // how many blocking objects are on each tile
+    blockers: Vec<u32>,
+}
+
+
+impl SpatialIndex {
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x >= 0 && x < self.width && y >= 0 && y < self.height {
+            return Some((x * self.height + y) as usize);
+        }
+        None
+    }
+
+    // start over, for this floor and everything on it
+    pub fn rebuild(&mut self, map: &Map, objects: &[Object]) {
+        let size = (map.width * map.height) as usize;
+        self.width = map.width;
+        self.height = map.height;
+        self.contents = vec![vec![]; size];
+        self.blockers = vec![0; size];
+        for (id, object) in objects.iter().enumerate() {
+            self.add(id, object.pos(), object.blocks);
+        }
+    }
+
+    // the ids of everything on this tile (nothing, off the map)
+    pub fn at(&self, x: i32, y: i32) -> &[usize] {
+        match self.index(x, y) {
+            Some(i) => &self.contents[i],
+            None => &[],
+        }
+    }
+
+    pub fn is_blocked(&self, x: i32, y: i32) -> bool {
+        match self.index(x, y) {
+            Some(i) => self.blockers[i] > 0,
+            None => false,
+        }
+    }
+
+    pub fn add(&mut self, id: usize, (x, y): (i32, i32), blocks: bool) {
+        if let Some(i) = self.index(x, y) {
+            let ids = &mut self.contents[i];
+            if let Err(at) = ids.binary_search(&id) {
+                ids.insert(at, id);
+            }
+            if blocks {
+                self.blockers[i] += 1;
+            }
+        }
+    }
+
+    pub fn remove(&mut self, id: usize, (x, y): (i32, i32), blocks: bool) {
+        if let Some(i) = self.index(x, y) {
+            self.contents[i].retain(|&other| other != id);
+            if blocks {
+                self.blockers[i] = self.blockers[i].saturating_sub(1);
+            }
+        }
+    }
+
+    pub fn moved(&mut self, id: usize, from: (i32, i32), to: (i32, i32), blocks: bool) {
+        self.remove(id, from, blocks);
+        self.add(id, to, blocks);
+    }
+
+    // something on this tile stopped blocking (an NPC died)
+    pub fn unblock(&mut self, (x, y): (i32, i32)) {
+        if let Some(i) = self.index(x, y) {
+            self.blockers[i] = self.blockers[i].saturating_sub(1);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tcod::colors::WHITE;
+    use crate::constants::NUM_LVLS;
+    use crate::constants::PLAYER;
+    use crate::equipment::pick_item_up;
+    use crate::loot_table::item_by_name;
+    use crate::objects::Fighter;
+    use crate::objects::Game;
+
+    // every object is indexed where it stands, and nothing else is
+    fn in_step(index: &SpatialIndex, objects: &[Object]) -> bool {
+        let indexed: usize = index.contents.iter().map(|ids| ids.len()).sum();
+        let found = objects
+            .iter()
+            .enumerate()
+            .all(|(id, object)| index.at(object.x, object.y).contains(&id));
+        indexed == objects.len() && found
+    }
+
+
+    #[test]
+    fn the_index_follows_objects_as_they_move() {
+        let map = Map::new(10, 10);
+        let objects = vec![
+            Object::new(2, 2, 'o', "orc", WHITE, true),
+            Object::new(2, 2, '!', "potion", WHITE, false),
+        ];
+        let mut index = SpatialIndex::default();
+        index.rebuild(&map, &objects);
+        assert_eq!(index.at(2, 2), &[0, 1]);
+        assert!(index.is_blocked(2, 2));
+
+        index.moved(0, (2, 2), (3, 2), true);
+        assert_eq!(index.at(2, 2), &[1]);
+        assert!(!index.is_blocked(2, 2) && index.is_blocked(3, 2));
+
+        index.unblock((3, 2));
+        assert!(!index.is_blocked(3, 2));
+        assert!(index.at(-1, 0).is_empty() && !index.is_blocked(10, 3));
+    }
+
+
+    #[test]
+    fn picking_things_up_keeps_the_index_in_step() {
+        let mut objects: Vec<Vec<Object>> = vec![vec![]; NUM_LVLS as usize];
+        let mut player = Object::new(0, 0, '@', "you", WHITE, true);
+        player.fighter = Some(Fighter::new(30, 2, 5, 0, false));
+        objects[0].push(player);
+        let mut game = Game::new(&mut objects, 3);
+        let floor = &mut objects[0];
+
+        // a dagger (kept on its own) and two potions (the second goes on the first's stack)
+        let (x, y) = floor[PLAYER].pos();
+        for name in ["dagger", "healing potion", "healing potion"].iter() {
+            let mut item = item_by_name(name).unwrap();
+            item.set_pos(x, y);
+            floor.push(item);
+        }
+        game.enter_floor(floor);
+        assert!(in_step(&game.index, floor));
+
+        for name in ["dagger", "healing potion", "healing potion"].iter() {
+            let id = floor.iter().position(|object| object.name == *name).unwrap();
+            pick_item_up(id, PLAYER, &mut game, floor);
+            assert!(in_step(&game.index, floor), "after picking up a {}", name);
+        }
+        assert_eq!(floor[PLAYER].fighter.as_ref().unwrap().inventory.len(), 2);
+    }
+}
@@ -1,7 +1,11 @@
 /*
   A Transition is used to generate NPCs and items by level.
  */
+// Import Third-Party
+use serde::Deserialize;
 
+
+#[derive(Clone, Debug, Deserialize)]
 pub struct Transition {
     pub level: u32,
     pub value: u32,
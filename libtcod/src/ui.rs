@@ -8,30 +8,30 @@ use std::collections::HashMap;
 use tcod::colors::*;
 use tcod::console::*;
 use tcod::input::{Mouse};
-use tcod::map::{FovAlgorithm, Map as FovMap};
+use tcod::map::{Map as FovMap};
 
 // Import Locally
-use crate::constants::*;
-use crate::map::Map;
+use feb2::constants::*;
+use feb2::map::Map;
+use feb2::objects::Fighter;
+use feb2::objects::Game;
+use feb2::objects::Object;
+use feb2::player::xp_to_level_up;
+use feb2::spatial_index::SpatialIndex;
 use crate::menus::render_bar;
 use crate::menus::Tcod;
-use crate::objects::Fighter;
-use crate::objects::Game;
-use crate::objects::Object;
-use crate::player::xp_to_level_up;
-
-const FOV_ALGO: FovAlgorithm = FovAlgorithm::Basic;
 
 
 // return a string with the names of all objects under the mouse
-fn get_names_under_mouse(mouse: Mouse, objects: &[Object], fov_map: &FovMap) -> String {
+fn get_names_under_mouse(mouse: Mouse, objects: &[Object], index: &SpatialIndex, fov_map: &FovMap) -> String {
     let (x, y) = (mouse.cx as i32, mouse.cy as i32);
 
     // create a list with the names of all objects at the mouse's coordinates and in FOV
-    let names = objects
+    let names = index
+        .at(x, y)
         .iter()
-        .filter(|obj| obj.pos() == (x, y) && fov_map.is_in_fov(obj.x, obj.y))
-        .map(|obj| obj.name.clone())
+        .filter(|_| fov_map.is_in_fov(x, y))
+        .map(|&id| objects[id].name.clone())
         .collect::<Vec<_>>();
 
     // find duplicate items, if any
@@ -64,36 +64,24 @@ fn get_names_under_mouse(mouse: Mouse, objects: &[Object], fov_map: &FovMap) ->
 }
 
 
-pub fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_recompute: bool) {
-    if fov_recompute {
-        // recompute FOV if needed (the player moved or something)
-        let player: &Object = &objects[PLAYER];
-        tcod.fov
-            .compute_fov(player.x, player.y, TORCH_RADIUS, true, FOV_ALGO);
-    }
-
+// NOTE: FOV (and what has been explored) is worked out by the Game, each turn.
+pub fn render_all(tcod: &mut Tcod, game: &Game, objects: &[Object]) {
     // go through all tiles, and set their background color
-    let map: &mut Map = game.map();
-    for y in 0..MAP_HEIGHT {
-        for x in 0..MAP_WIDTH {
-            let visible: bool = tcod.fov.is_in_fov(x, y);
-            let wall: bool = map[x as usize][y as usize].block_sight;
-            let color = match (visible, wall) {
-                // outside of field of view:
-                (false, true) => COLOR_DARK_WALL,
-                (false, false) => COLOR_DARK_GROUND,
-                // inside fov:
-                (true, true) => COLOR_LIGHT_WALL,
-                (true, false) => COLOR_LIGHT_GROUND,
-            };
-            let explored = &mut map[x as usize][y as usize].explored;
-            if visible {
-                // since it's visible, explore it
-                *explored = true;
-            }
-            if *explored {
+    let map: &Map = &game.maps[game.lvl];
+    for y in 0..map.height {
+        for x in 0..map.width {
+            let visible: bool = game.fov.is_in_fov(x, y);
+            let tile = &map[(x, y)];
+            if tile.explored {
                 // show explored tiles only (any visible tile is explored already)
-                tcod.con.set_char_background(x, y, color, BackgroundFlag::Set);
+                let color = tile.color(visible);
+                match tile.glyph() {
+                    Some(chr) => {
+                        let door_color = if visible { COLOR_LIGHT_DOOR } else { COLOR_DARK_DOOR };
+                        tcod.con.put_char_ex(x, y, chr, door_color, color);
+                    }
+                    None => tcod.con.set_char_background(x, y, color, BackgroundFlag::Set),
+                }
             }
         }
     }
@@ -102,12 +90,12 @@ pub fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_reco
     let mut to_draw: Vec<_> = objects
         .iter()
         .filter(|o| {
-            tcod.fov.is_in_fov(o.x, o.y)
-                || (o.always_visible && map[o.x as usize][o.y as usize].explored)
+            game.fov.is_in_fov(o.x, o.y)
+                || (o.always_visible && map[(o.x, o.y)].explored)
         })
         .collect();
     // sort so that non-blocking objects come first
-    to_draw.sort_by(|o1, o2| o1.blocks.cmp(&o2.blocks));
+    to_draw.sort_by_key(|o1| o1.blocks);
     // draw the objects in the list
     for object in &to_draw {
         object.draw(&mut tcod.con);
@@ -158,6 +146,16 @@ pub fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_reco
         format!("Karma: {}", karma),
     );
 
+    if game.sneaking {
+        tcod.panel.print_ex(
+            1,
+            4,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            "Sneaking",
+        );
+    }
+
     tcod.panel.print_ex(
         1,
         5,
@@ -174,7 +172,7 @@ pub fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_reco
         0,
         BackgroundFlag::None,
         TextAlignment::Left,
-        get_names_under_mouse(tcod.mouse, objects, &tcod.fov),
+        get_names_under_mouse(tcod.mouse, objects, &game.index, &game.fov),
     );
 
     // print the game messages, one line at a time
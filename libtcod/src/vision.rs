@@ -0,0 +1,100 @@
+/*
+  NPC Vision
+
+  What an NPC can see is its own business: each has a sight radius (a blind
+  one has none), and walls and closed doors get in the way. The player's FOV
+  is only what the player can see.
+ */
+// Import Locally
+use crate::map::Map;
+
+
+// Nothing blocks the view between these two tiles (the tiles themselves don't count).
+pub fn line_of_sight(map: &Map, from: (i32, i32), to: (i32, i32)) -> bool {
+    // Bresenham, from one end to the other
+    let (dx, dy) = ((to.0 - from.0).abs(), -(to.1 - from.1).abs());
+    let (step_x, step_y) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+    let (mut x, mut y) = from;
+    let mut error = dx + dy;
+    loop {
+        let twice = 2 * error;
+        if twice >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if twice <= dx {
+            error += dx;
+            y += step_y;
+        }
+        if (x, y) == to {
+            return true;
+        }
+        if map.get(x, y).is_none_or(|tile| tile.block_sight) {
+            return false;
+        }
+    }
+}
+
+
+// Within this sight radius, and in plain view.
+pub fn can_see(map: &Map, from: (i32, i32), to: (i32, i32), radius: i32) -> bool {
+    if from == to {
+        return true;
+    }
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    if dx * dx + dy * dy > radius * radius {
+        return false;
+    }
+    line_of_sight(map, from, to)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Tile;
+
+    // a room 15 by 9 inside, with a door in a wall across the middle
+    fn room_with_a_door() -> Map {
+        let mut map = Map::new(17, 11);
+        for x in 1..16 {
+            for y in 1..10 {
+                map[(x, y)] = Tile::empty();
+            }
+        }
+        for y in 1..10 {
+            map[(8, y)] = Tile::wall();
+        }
+        map[(8, 5)] = Tile::door();
+        map
+    }
+
+
+    #[test]
+    fn sight_reaches_as_far_as_the_radius() {
+        let map = room_with_a_door();
+        assert!(can_see(&map, (2, 2), (6, 2), 4));
+        assert!(!can_see(&map, (2, 2), (7, 2), 4));
+        assert!(can_see(&map, (2, 2), (5, 5), 5));
+        // blind: only what it's standing on
+        assert!(!can_see(&map, (2, 2), (3, 2), 0));
+        assert!(can_see(&map, (2, 2), (2, 2), 0));
+    }
+
+
+    #[test]
+    fn walls_and_closed_doors_block_the_view() {
+        let mut map = room_with_a_door();
+        assert!(!can_see(&map, (4, 2), (12, 2), 20));
+        assert!(!can_see(&map, (4, 5), (12, 5), 20));
+        // but a wall (or door) itself can be seen
+        assert!(can_see(&map, (4, 2), (8, 2), 20));
+        assert!(can_see(&map, (4, 5), (8, 5), 20));
+
+        map[(8, 5)].open_door();
+        assert!(can_see(&map, (4, 5), (12, 5), 20));
+        assert!(line_of_sight(&map, (12, 5), (4, 5)));
+        // off to one side, the wall is still in the way
+        assert!(!line_of_sight(&map, (4, 1), (12, 3)));
+    }
+}